@@ -1,61 +1,106 @@
-﻿use crate::core::{AdbError, Result};
-use std::path::PathBuf;
+﻿use super::discovery::AdbDiscovery;
+use super::policy::{CallOptions, RetryPolicy};
+use super::protocol::{self, AdbConnection, AdbServer};
+use super::sync::{self, ProgressFn, RemoteEntry, SyncClient, SyncOptions, TransferReport};
+use super::transport::{AdbTransport, ShellOutput};
+use crate::core::config::Config;
+use crate::core::{AdbError, Result};
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
 use std::process::Stdio;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use tokio::io::{AsyncBufReadExt, BufReader};
 use tokio::process::Command;
 use tokio::time::timeout;
+use tokio_util::sync::CancellationToken;
 #[derive(Debug, Clone)]
 pub struct AdbClient {
     adb_path: PathBuf,
+    server: Option<AdbServer>,
+    options: CallOptions,
+    shell_v2: Arc<Mutex<HashMap<String, bool>>>,
 }
 impl AdbClient {
     pub fn new() -> Result<Self> {
         let adb_path = Self::resolve_adb()?;
-        Ok(Self { adb_path })
+        Ok(Self {
+            adb_path,
+            server: None,
+            options: CallOptions::default(),
+            shell_v2: Arc::default(),
+        })
     }
-    fn resolve_adb() -> Result<PathBuf> {
-        if let Ok(current_exe) = std::env::current_exe() {
-            if let Some(parent) = current_exe.parent() {
-                let bundled = parent.join("platform-tools").join("adb.exe");
-                if bundled.exists() {
-                    return Ok(bundled);
-                }
-                let vendor_bundled = parent.join("vendor").join("platform-tools").join("adb.exe");
-                if vendor_bundled.exists() {
-                    return Ok(vendor_bundled);
-                }
-                if let Some(project_root) = parent.parent().and_then(|p| p.parent()) {
-                     let dev_bundled = project_root.join("vendor").join("platform-tools").join("adb.exe");
-                     if dev_bundled.exists() {
-                         return Ok(dev_bundled);
-                     }
-                }
-            }
-        }
-        let local_vendor = PathBuf::from("vendor/platform-tools/adb.exe");
-        if local_vendor.exists() {
-             if let Ok(abs) = std::fs::canonicalize(local_vendor) {
-                 return Ok(abs);
-             }
+    pub fn from_config(config: &Config) -> Result<Self> {
+        let adb_path = AdbDiscovery::run(config.adb_path.as_deref())
+            .selected
+            .ok_or(AdbError::AdbNotFound)?;
+        Ok(Self {
+            adb_path,
+            server: None,
+            options: CallOptions {
+                timeout: Duration::from_secs(config.command_timeout_secs),
+                retry: RetryPolicy {
+                    max_attempts: config.retry_attempts,
+                    initial_backoff: Duration::from_millis(config.retry_backoff_ms),
+                    ..RetryPolicy::default()
+                },
+                cancel: CancellationToken::new(),
+            },
+            shell_v2: Arc::default(),
+        })
+    }
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.options.timeout = timeout;
+        self
+    }
+    pub fn with_cancel(mut self, cancel: CancellationToken) -> Self {
+        self.options.cancel = cancel;
+        self
+    }
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.options.retry = retry;
+        self
+    }
+    pub fn options(&self) -> &CallOptions {
+        &self.options
+    }
+    pub fn with_server(mut self, addr: SocketAddr) -> Self {
+        self.server = Some(AdbServer::new(addr));
+        self
+    }
+    pub async fn connect_server(self) -> Self {
+        if std::env::var("ANDROIDCHECKER_NATIVE_ADB").is_ok_and(|v| v == "0") {
+            return self;
         }
-        if let Ok(output) = std::process::Command::new("where").arg("adb").output() {
-            if output.status.success() {
-                let path_str = String::from_utf8_lossy(&output.stdout);
-                if let Some(line) = path_str.lines().next() {
-                    return Ok(PathBuf::from(line.trim()));
-                }
-            }
+        let server = AdbServer::new(protocol::default_server_addr());
+        match server.version().await {
+            Ok(_) => self.with_server(server.addr()),
+            Err(_) => self,
         }
-        Err(AdbError::AdbNotFound)
+    }
+    pub fn is_native(&self) -> bool {
+        self.server.is_some()
+    }
+    // 连接 adb server 阶段的失败才回退到子进程；服务打开后的错误说明命令可能已执行，直接返回
+    fn is_connection_error(err: &AdbError) -> bool {
+        RetryPolicy::is_connect_error(err)
+    }
+    fn resolve_adb() -> Result<PathBuf> {
+        AdbDiscovery::run(None).selected.ok_or(AdbError::AdbNotFound)
+    }
+    pub fn adb_path(&self) -> &PathBuf {
+        &self.adb_path
     }
     pub async fn ensure_present(&self) -> Result<()> {
-        let output = timeout(
-            Duration::from_secs(10),
-            Command::new(&self.adb_path).arg("version").output(),
-        )
-        .await
-        .map_err(|_| AdbError::Timeout(10))??;
+        let output = self
+            .guarded("adb version", || async {
+                Ok(Command::new(&self.adb_path).arg("version").kill_on_drop(true).output().await?)
+            })
+            .await?;
         if output.status.success() {
             Ok(())
         } else {
@@ -63,43 +108,255 @@ impl AdbClient {
         }
     }
     pub async fn start_server(&self) -> Result<()> {
-        timeout(
-            Duration::from_secs(10),
-            Command::new(&self.adb_path).arg("start-server").output(),
-        )
-        .await
-        .map_err(|_| AdbError::Timeout(10))??;
+        self.guarded("adb start-server", || async {
+            Ok(Command::new(&self.adb_path).arg("start-server").kill_on_drop(true).output().await?)
+        })
+        .await?;
         Ok(())
     }
+    async fn guarded<T, F, Fut>(&self, command: &str, op: F) -> Result<T>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        // guarded 包住的都是服务打开之前就能失败的调用（host: 请求、整个 adb 子进程），transport 被关闭也可以重试
+        self.retrying(command, op, |r| {
+            matches!(r, Err(e) if RetryPolicy::is_transient(e) || RetryPolicy::is_transport_closed(e))
+        })
+            .await
+    }
+    async fn retrying<T, F, Fut>(&self, command: &str, op: F, retryable: impl Fn(&Result<T>) -> bool) -> Result<T>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let opts = &self.options;
+        let mut attempt = 1;
+        loop {
+            let result = self.bounded(command, op()).await;
+            if attempt >= opts.retry.max_attempts || !retryable(&result) {
+                return result;
+            }
+            tokio::select! {
+                _ = opts.cancel.cancelled() => return Err(AdbError::Cancelled(command.to_string())),
+                _ = tokio::time::sleep(opts.retry.backoff(attempt)) => {}
+            }
+            attempt += 1;
+        }
+    }
+    // 只施加超时与取消，不重试
+    async fn bounded<T>(&self, command: &str, op: impl Future<Output = Result<T>>) -> Result<T> {
+        let opts = &self.options;
+        tokio::select! {
+            _ = opts.cancel.cancelled() => Err(AdbError::Cancelled(command.to_string())),
+            r = timeout(opts.timeout, op) => r.unwrap_or_else(|_| Err(AdbError::Timeout {
+                command: command.to_string(),
+                secs: opts.timeout.as_secs(),
+            })),
+        }
+    }
+    // 建立 host:transport 的阶段可以安全重试；服务请求发出后命令可能已在设备上执行，不再重试。
+    // 返回 None 表示连不上 adb server，由调用方回退到子进程
+    async fn open_native(&self, server: &AdbServer, serial: &str, service: &str) -> Result<Option<AdbConnection>> {
+        match self.guarded(&format!("host:transport:{}", serial), || server.open_transport(serial)).await {
+            Ok(mut conn) => {
+                self.bounded(service, conn.request(service)).await?;
+                Ok(Some(conn))
+            }
+            Err(e) if Self::is_connection_error(&e) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+    fn describe(args: &[&str]) -> String {
+        format!("adb {}", args.join(" "))
+    }
     pub async fn execute(&self, args: &[&str]) -> Result<(String, String)> {
-        let output = Command::new(&self.adb_path).args(args).output().await?;
-        let stdout = String::from_utf8(output.stdout)?;
-        let stderr = String::from_utf8(output.stderr)?;
+        self.guarded(&Self::describe(args), || self.execute_once(args)).await
+    }
+    async fn execute_once(&self, args: &[&str]) -> Result<(String, String)> {
+        let output = Command::new(&self.adb_path).args(args).kill_on_drop(true).output().await?;
+        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+        let stderr = String::from_utf8_lossy(&output.stderr).to_string();
         if !output.status.success() {
-            return Err(AdbError::CommandFailed(stderr.clone()));
+            return Err(AdbError::classify(&stderr));
         }
         Ok((stdout, stderr))
     }
     pub async fn try_execute(&self, args: &[&str]) -> Result<(bool, String, String)> {
-        let output = Command::new(&self.adb_path).args(args).output().await?;
+        self.retrying(&Self::describe(args), || self.try_execute_once(args), Self::retryable_try)
+            .await
+    }
+    async fn try_execute_once(&self, args: &[&str]) -> Result<(bool, String, String)> {
+        let output = Command::new(&self.adb_path).args(args).kill_on_drop(true).output().await?;
         let stdout = String::from_utf8_lossy(&output.stdout).to_string();
         let stderr = String::from_utf8_lossy(&output.stderr).to_string();
         Ok((output.status.success(), stdout, stderr))
     }
-    pub async fn shell(&self, serial: &str, cmd: &[&str]) -> Result<String> {
-        let mut args = vec!["-s", serial, "shell"];
-        args.extend_from_slice(cmd);
-        let (stdout, stderr) = self.execute(&args).await?;
-        if !stderr.is_empty() && stderr.to_lowercase().contains("error") {
-            return Err(AdbError::CommandFailed(stderr));
+    fn retryable_try(result: &Result<(bool, String, String)>) -> bool {
+        match result {
+            Ok((false, _, stderr)) => RetryPolicy::is_transient_message(stderr),
+            Ok(_) => false,
+            Err(e) => RetryPolicy::is_transient(e),
         }
+    }
+    pub async fn devices_long(&self) -> Result<String> {
+        if let Some(server) = &self.server {
+            match self.guarded("host:devices-l", || server.devices_long()).await {
+                Ok(out) => return Ok(out),
+                Err(e) if !Self::is_connection_error(&e) => return Err(e),
+                Err(_) => {}
+            }
+        }
+        let (stdout, _) = self.execute(&["devices", "-l"]).await?;
         Ok(stdout)
     }
+    pub async fn shell(&self, serial: &str, cmd: &[&str]) -> Result<String> {
+        self.shell_output(serial, cmd).await?.into_result()
+    }
     pub async fn try_shell(&self, serial: &str, cmd: &[&str]) -> Result<(bool, String, String)> {
-        let mut args = vec!["-s", serial, "shell"];
+        let out = self.shell_output(serial, cmd).await?;
+        Ok((out.success(), out.stdout, out.stderr))
+    }
+    pub async fn shell_output(&self, serial: &str, cmd: &[&str]) -> Result<ShellOutput> {
+        if let Some(server) = &self.server {
+            let desc = format!("shell:{} ({})", cmd.join(" "), serial);
+            let v2 = self.supports_shell_v2(server, serial).await;
+            let service = if v2 { protocol::shell_v2_service(cmd) } else { protocol::sentinel_service(cmd) };
+            if let Some(mut conn) = self.open_native(server, serial, &service).await? {
+                return if v2 {
+                    self.bounded(&desc, conn.read_shell_v2()).await
+                } else {
+                    self.bounded(&desc, conn.read_sentinel_output()).await
+                };
+            }
+        }
+        let command = protocol::sentinel_command(cmd);
+        let (ok, stdout, stderr) = self.try_execute(&["-s", serial, "shell", &command]).await?;
+        match protocol::split_exit_sentinel(&stdout) {
+            (stdout, Some(exit_code)) => Ok(ShellOutput {
+                stdout,
+                stderr,
+                exit_code,
+            }),
+            (stdout, None) if ok => Ok(ShellOutput {
+                stdout,
+                stderr,
+                exit_code: 0,
+            }),
+            (_, None) => Err(AdbError::classify(&stderr)),
+        }
+    }
+    pub async fn exec_out(&self, serial: &str, cmd: &[&str]) -> Result<Vec<u8>> {
+        let desc = format!("exec:{} ({})", cmd.join(" "), serial);
+        if let Some(server) = &self.server {
+            if let Some(mut conn) = self.open_native(server, serial, &protocol::exec_service(cmd)).await? {
+                return self.bounded(&desc, conn.read_to_end()).await;
+            }
+        }
+        let mut args = vec!["-s", serial, "exec-out"];
         args.extend_from_slice(cmd);
-        self.try_execute(&args).await
+        let output = self
+            .guarded(&desc, || async {
+                Ok(Command::new(&self.adb_path).args(&args).kill_on_drop(true).output().await?)
+            })
+            .await?;
+        if !output.status.success() {
+            return Err(AdbError::classify(&String::from_utf8_lossy(&output.stderr)));
+        }
+        Ok(output.stdout)
+    }
+    pub async fn pull(
+        &self,
+        serial: &str,
+        remote: &str,
+        local: &Path,
+        options: &SyncOptions,
+        progress: ProgressFn<'_>,
+    ) -> Result<TransferReport> {
+        match &self.server {
+            Some(server) => {
+                let sync = SyncClient::new(*server, serial);
+                self.cancellable(&format!("sync pull {}", remote), sync.pull(remote, local, options, progress))
+                    .await
+            }
+            None => {
+                std::fs::create_dir_all(local)?;
+                let source = format!("{}/.", remote.trim_end_matches('/'));
+                let target = local.to_string_lossy();
+                self.clone()
+                    .with_timeout(options.timeout)
+                    .execute(&["-s", serial, "pull", &source, &target])
+                    .await?;
+                Ok(TransferReport::whole(remote))
+            }
+        }
     }
+    pub async fn push(
+        &self,
+        serial: &str,
+        local: &Path,
+        remote: &str,
+        options: &SyncOptions,
+        progress: ProgressFn<'_>,
+    ) -> Result<TransferReport> {
+        match &self.server {
+            Some(server) => {
+                let sync = SyncClient::new(*server, serial);
+                self.cancellable(&format!("sync push {}", remote), sync.push(local, remote, options, progress))
+                    .await
+            }
+            None => {
+                let source = format!("{}/.", local.to_string_lossy().trim_end_matches(['/', '\\']));
+                self.clone()
+                    .with_timeout(options.timeout)
+                    .execute(&["-s", serial, "push", &source, remote])
+                    .await?;
+                Ok(TransferReport::whole(&local.to_string_lossy()))
+            }
+        }
+    }
+    // 目标不是目录时 LIST 返回空列表，先 STAT 区分不存在和单个文件
+    pub async fn list_dir(&self, serial: &str, remote: &str) -> Result<Vec<RemoteEntry>> {
+        let Some(server) = &self.server else {
+            return Ok(sync::parse_ls(&self.exec_out(serial, &["ls", "-1a", remote]).await?));
+        };
+        let sync = SyncClient::new(*server, serial);
+        let stat = self.cancellable(&format!("sync stat {}", remote), sync.stat(remote)).await?;
+        if !stat.exists() {
+            return Err(AdbError::NotFoundOnDevice(remote.to_string()));
+        }
+        if !stat.is_dir() && !stat.is_symlink() {
+            let name = remote.trim_end_matches('/').rsplit('/').next().unwrap_or(remote);
+            return Ok(vec![RemoteEntry {
+                name: name.as_bytes().to_vec(),
+                stat,
+            }]);
+        }
+        self.cancellable(&format!("sync list {}", remote), sync.list(remote)).await
+    }
+    async fn cancellable<T>(&self, command: &str, op: impl Future<Output = Result<T>>) -> Result<T> {
+        tokio::select! {
+            _ = self.options.cancel.cancelled() => Err(AdbError::Cancelled(command.to_string())),
+            r = op => r,
+        }
+    }
+    async fn supports_shell_v2(&self, server: &AdbServer, serial: &str) -> bool {
+        if let Some(cached) = self.shell_v2.lock().ok().and_then(|c| c.get(serial).copied()) {
+            return cached;
+        }
+        match self.guarded("host-serial:features", || server.features(serial)).await {
+            Ok(features) => {
+                let supported = features.iter().any(|f| f == "shell_v2");
+                if let Ok(mut cache) = self.shell_v2.lock() {
+                    cache.insert(serial.to_string(), supported);
+                }
+                supported
+            }
+            Err(_) => false,
+        }
+    }
+    // 保留的公开接口，命令行暂未使用
+    #[allow(dead_code)]
     pub async fn logcat_stream(
         &self,
         serial: &str,
@@ -109,7 +366,7 @@ impl AdbClient {
         let serial = serial.to_string();
         let handle = tokio::spawn(async move {
             let mut child = match Command::new(&adb_path)
-                .args(&["-s", &serial, "logcat"])
+                .args(["-s", &serial, "logcat"])
                 .stdout(Stdio::piped())
                 .stderr(Stdio::null())
                 .spawn()
@@ -131,30 +388,207 @@ impl AdbClient {
         });
         Ok(handle)
     }
-    pub async fn parallel_shell(&self, serial: &str, commands: Vec<Vec<String>>) -> Vec<Result<String>> {
-        let tasks: Vec<_> = commands
-            .into_iter()
-            .map(|cmd| {
-                let client = self.clone();
-                let serial = serial.to_string();
-                tokio::spawn(async move {
-                    let cmd_refs: Vec<&str> = cmd.iter().map(|s| s.as_str()).collect();
-                    client.shell(&serial, &cmd_refs).await
-                })
-            })
-            .collect();
-        let mut results = Vec::new();
-        for task in tasks {
-            results.push(match task.await {
-                Ok(r) => r,
-                Err(e) => Err(AdbError::CommandFailed(e.to_string())),
-            });
+}
+#[async_trait]
+impl AdbTransport for AdbClient {
+    async fn try_execute(&self, args: &[&str]) -> Result<(bool, String, String)> {
+        AdbClient::try_execute(self, args).await
+    }
+    async fn try_shell(&self, serial: &str, cmd: &[&str]) -> Result<(bool, String, String)> {
+        AdbClient::try_shell(self, serial, cmd).await
+    }
+    async fn execute(&self, args: &[&str]) -> Result<(String, String)> {
+        AdbClient::execute(self, args).await
+    }
+    async fn shell(&self, serial: &str, cmd: &[&str]) -> Result<String> {
+        AdbClient::shell(self, serial, cmd).await
+    }
+    async fn shell_output(&self, serial: &str, cmd: &[&str]) -> Result<ShellOutput> {
+        AdbClient::shell_output(self, serial, cmd).await
+    }
+    async fn exec_out(&self, serial: &str, cmd: &[&str]) -> Result<Vec<u8>> {
+        AdbClient::exec_out(self, serial, cmd).await
+    }
+    async fn pull(
+        &self,
+        serial: &str,
+        remote: &str,
+        local: &Path,
+        options: &SyncOptions,
+        progress: ProgressFn<'_>,
+    ) -> Result<TransferReport> {
+        AdbClient::pull(self, serial, remote, local, options, progress).await
+    }
+    async fn push(
+        &self,
+        serial: &str,
+        local: &Path,
+        remote: &str,
+        options: &SyncOptions,
+        progress: ProgressFn<'_>,
+    ) -> Result<TransferReport> {
+        AdbClient::push(self, serial, local, remote, options, progress).await
+    }
+    async fn list_dir(&self, serial: &str, remote: &str) -> Result<Vec<RemoteEntry>> {
+        AdbClient::list_dir(self, serial, remote).await
+    }
+    async fn execute_with_timeout(&self, args: &[&str], timeout: Duration) -> Result<(String, String)> {
+        self.clone().with_timeout(timeout).execute(args).await
+    }
+    async fn try_execute_with_timeout(&self, args: &[&str], timeout: Duration) -> Result<(bool, String, String)> {
+        self.clone().with_timeout(timeout).try_execute(args).await
+    }
+    async fn devices_long(&self) -> Result<String> {
+        AdbClient::devices_long(self).await
+    }
+    async fn track_devices(&self) -> Result<Option<AdbConnection>> {
+        match &self.server {
+            Some(server) => Ok(Some(self.guarded("host:track-devices", || server.track_devices()).await?)),
+            None => Ok(None),
         }
-        results
     }
 }
 impl Default for AdbClient {
     fn default() -> Self {
         Self::new().expect("Failed to initialize ADB client")
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adb::protocol::test_server::{self, fail, length_prefixed, okay, read_request, shell_v2_packet};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::io::AsyncWriteExt;
+    fn client(addr: SocketAddr) -> AdbClient {
+        AdbClient {
+            adb_path: PathBuf::from("adb"),
+            server: None,
+            options: CallOptions {
+                timeout: Duration::from_secs(5),
+                retry: RetryPolicy {
+                    max_attempts: 3,
+                    initial_backoff: Duration::from_millis(1),
+                    max_backoff: Duration::from_millis(1),
+                },
+                cancel: CancellationToken::new(),
+            },
+            shell_v2: Arc::default(),
+        }
+        .with_server(addr)
+    }
+    // features 查询之外的连接：host:transport 之后执行 handler 给出的服务应答
+    async fn serve_features(stream: &mut tokio::net::TcpStream, features: &str) -> bool {
+        let request = read_request(stream).await;
+        if request.ends_with(":features") {
+            okay(stream).await;
+            length_prefixed(stream, features).await;
+            return true;
+        }
+        assert_eq!(request, "host:transport:AAA");
+        okay(stream).await;
+        false
+    }
+    #[tokio::test]
+    async fn shell_output_uses_shell_v2_when_advertised() {
+        let addr = test_server::spawn(|mut stream| async move {
+            if serve_features(&mut stream, "cmd,shell_v2,stat_v2").await {
+                return;
+            }
+            assert_eq!(read_request(&mut stream).await, "shell,v2,raw:getenforce");
+            okay(&mut stream).await;
+            shell_v2_packet(&mut stream, 1, b"Enforcing\n").await;
+            shell_v2_packet(&mut stream, 3, &[0]).await;
+        })
+        .await;
+        let out = client(addr).shell_output("AAA", &["getenforce"]).await.unwrap();
+        assert_eq!(out.stdout, "Enforcing\n");
+        assert!(out.success());
+    }
+    #[tokio::test]
+    async fn shell_output_falls_back_to_sentinel_without_shell_v2() {
+        let addr = test_server::spawn(|mut stream| async move {
+            if serve_features(&mut stream, "cmd").await {
+                return;
+            }
+            assert!(read_request(&mut stream).await.starts_with("shell:false ; echo "));
+            okay(&mut stream).await;
+            stream
+                .write_all(format!("{}1\n", protocol::EXIT_SENTINEL).as_bytes())
+                .await
+                .unwrap();
+        })
+        .await;
+        let out = client(addr).shell_output("AAA", &["false"]).await.unwrap();
+        assert_eq!(out.exit_code, 1);
+    }
+    async fn flaky_transport(first_failure: &'static str) -> usize {
+        let attempts = Arc::new(AtomicUsize::new(0));
+        let counter = attempts.clone();
+        let addr = test_server::spawn(move |mut stream| {
+            let counter = counter.clone();
+            async move {
+                let request = read_request(&mut stream).await;
+                if request.ends_with(":features") {
+                    okay(&mut stream).await;
+                    length_prefixed(&mut stream, "shell_v2").await;
+                    return;
+                }
+                if counter.fetch_add(1, Ordering::SeqCst) == 0 {
+                    fail(&mut stream, first_failure).await;
+                    return;
+                }
+                okay(&mut stream).await;
+                read_request(&mut stream).await;
+                okay(&mut stream).await;
+                shell_v2_packet(&mut stream, 3, &[0]).await;
+            }
+        })
+        .await;
+        assert!(client(addr).shell_output("AAA", &["true"]).await.unwrap().success());
+        attempts.load(Ordering::SeqCst)
+    }
+    #[tokio::test]
+    async fn transport_failures_before_the_service_are_retried() {
+        assert_eq!(flaky_transport("device offline").await, 2);
+        // 设备传输刚断开时 server 对 host:transport 回 closed
+        assert_eq!(flaky_transport("closed").await, 2);
+    }
+    #[tokio::test]
+    async fn commands_are_not_rerun_after_the_service_opened() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let counter = runs.clone();
+        let addr = test_server::spawn(move |mut stream| {
+            let counter = counter.clone();
+            async move {
+                if serve_features(&mut stream, "shell_v2").await {
+                    return;
+                }
+                read_request(&mut stream).await;
+                counter.fetch_add(1, Ordering::SeqCst);
+                okay(&mut stream).await;
+                // 命令已开始执行，连接在退出码之前断开
+            }
+        })
+        .await;
+        let err = client(addr).shell_output("AAA", &["reboot"]).await.err().unwrap();
+        assert!(matches!(err, AdbError::Protocol(_)), "{:?}", err);
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+    }
+    #[tokio::test]
+    async fn exec_out_returns_raw_bytes() {
+        let payload: Vec<u8> = (0..=255u8).collect();
+        let expected = payload.clone();
+        let addr = test_server::spawn(move |mut stream| {
+            let payload = payload.clone();
+            async move {
+                assert_eq!(read_request(&mut stream).await, "host:transport:AAA");
+                okay(&mut stream).await;
+                assert_eq!(read_request(&mut stream).await, "exec:screencap -p");
+                okay(&mut stream).await;
+                stream.write_all(&payload).await.unwrap();
+            }
+        })
+        .await;
+        assert_eq!(client(addr).exec_out("AAA", &["screencap", "-p"]).await.unwrap(), expected);
+    }
+}
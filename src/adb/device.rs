@@ -1,11 +1,23 @@
-﻿use crate::adb::client::AdbClient;
-use crate::core::Result;
+﻿use crate::adb::inventory::{Inventory, InventoryRecord};
+use crate::adb::props::Properties;
+use crate::adb::protocol::shell_quote;
+use crate::adb::transport::SharedTransport;
+use crate::adb::wireless::{self, MdnsService, NetworkDevice, RememberedDevices};
+use crate::core::{AdbError, Result};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+const SIDELOAD_TIMEOUT: Duration = Duration::from_secs(30 * 60);
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Device {
     pub serial: String,
-    pub state: String,
+    pub state: DeviceState,
+    pub usb: Option<String>,
+    pub product: Option<String>,
+    pub device: Option<String>,
+    pub transport_id: Option<String>,
     pub model: Option<String>,
     pub brand: Option<String>,
     pub marketing_name: Option<String>,
@@ -24,103 +36,541 @@ impl Device {
         }
         self.serial.clone()
     }
+    pub fn connection(&self) -> String {
+        match (&self.usb, wireless::is_network_serial(&self.serial)) {
+            (Some(usb), _) => format!("usb:{}", usb),
+            (None, true) => "tcp".to_string(),
+            (None, false) => "-".to_string(),
+        }
+    }
+}
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum DeviceState {
+    Device,
+    Unauthorized,
+    Offline,
+    Authorizing,
+    Connecting,
+    Recovery,
+    Sideload,
+    Bootloader,
+    Rescue,
+    NoPermissions,
+    Unknown(String),
+}
+impl DeviceState {
+    pub fn parse(state: &str) -> Self {
+        match state {
+            "device" => Self::Device,
+            "unauthorized" => Self::Unauthorized,
+            "offline" => Self::Offline,
+            "authorizing" => Self::Authorizing,
+            "connecting" => Self::Connecting,
+            "recovery" => Self::Recovery,
+            "sideload" => Self::Sideload,
+            "bootloader" => Self::Bootloader,
+            "rescue" => Self::Rescue,
+            s if s.starts_with("no permissions") => Self::NoPermissions,
+            other => Self::Unknown(other.to_string()),
+        }
+    }
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Device => "device",
+            Self::Unauthorized => "unauthorized",
+            Self::Offline => "offline",
+            Self::Authorizing => "authorizing",
+            Self::Connecting => "connecting",
+            Self::Recovery => "recovery",
+            Self::Sideload => "sideload",
+            Self::Bootloader => "bootloader",
+            Self::Rescue => "rescue",
+            Self::NoPermissions => "no permissions",
+            Self::Unknown(s) => s,
+        }
+    }
+    pub fn is_online(&self) -> bool {
+        matches!(self, Self::Device)
+    }
+    pub fn guidance(&self) -> Option<&'static str> {
+        match self {
+            Self::Device => None,
+            Self::Unauthorized => Some("请解锁手机并在\"允许 USB 调试\"弹窗中点击允许；若无弹窗，请撤销 USB 调试授权后重新插拔"),
+            Self::Authorizing => Some("正在等待授权，请查看手机屏幕上的 RSA 指纹确认弹窗"),
+            Self::Offline => Some("设备已连接但无响应，请重新插拔数据线、更换 USB 口或执行 adb reconnect"),
+            Self::Connecting => Some("正在建立连接，请稍候"),
+            Self::Recovery => Some("设备处于 Recovery 模式，仅提供部分功能"),
+            Self::Sideload => Some("设备处于 Sideload 模式，可刷入 OTA 包或重启"),
+            Self::Bootloader => Some("设备处于 Bootloader 模式，请使用 fastboot 操作或重启"),
+            Self::Rescue => Some("设备处于 Rescue 模式，仅可重启"),
+            Self::NoPermissions => Some("当前用户无权访问该 USB 设备，请配置 udev 规则 (Linux) 或以管理员身份运行"),
+            Self::Unknown(_) => Some("未知的设备状态，请重新插拔设备"),
+        }
+    }
+    pub fn limited_operations(&self) -> &'static [DeviceOperation] {
+        match self {
+            Self::Recovery => &[
+                DeviceOperation::Properties,
+                DeviceOperation::RebootSystem,
+                DeviceOperation::RebootBootloader,
+            ],
+            Self::Sideload => &[DeviceOperation::Sideload, DeviceOperation::RebootSystem],
+            Self::Bootloader => &[DeviceOperation::RebootSystem, DeviceOperation::RebootRecovery],
+            Self::Rescue => &[DeviceOperation::RebootSystem],
+            _ => &[],
+        }
+    }
+}
+impl std::fmt::Display for DeviceState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+impl From<String> for DeviceState {
+    fn from(state: String) -> Self {
+        Self::parse(&state)
+    }
+}
+impl From<DeviceState> for String {
+    fn from(state: DeviceState) -> Self {
+        state.as_str().to_string()
+    }
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceOperation {
+    Properties,
+    RebootSystem,
+    RebootRecovery,
+    RebootBootloader,
+    Sideload,
+}
+impl DeviceOperation {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Properties => "查看设备属性",
+            Self::RebootSystem => "重启到系统",
+            Self::RebootRecovery => "重启到 Recovery",
+            Self::RebootBootloader => "重启到 Bootloader",
+            Self::Sideload => "刷入 OTA 包 (adb sideload)",
+        }
+    }
 }
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeviceListing {
+    pub serial: String,
+    pub state: String,
+    pub usb: Option<String>,
+    pub product: Option<String>,
+    pub model: Option<String>,
+    pub device: Option<String>,
+    pub transport_id: Option<String>,
+}
+const LISTING_KEYS: &[&str] = &["usb", "product", "model", "device", "transport_id"];
+pub fn parse_devices_long(output: &str) -> Vec<DeviceListing> {
+    output
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('*') && !line.starts_with("List of devices"))
+        .filter_map(|line| {
+            let mut tokens = line.split_whitespace();
+            let mut listing = DeviceListing {
+                serial: tokens.next()?.to_string(),
+                ..DeviceListing::default()
+            };
+            let mut state = Vec::new();
+            let mut in_fields = false;
+            for token in tokens {
+                let field = token
+                    .split_once(':')
+                    .filter(|(key, _)| LISTING_KEYS.contains(key));
+                match field {
+                    Some(("usb", v)) => listing.usb = Some(v.to_string()),
+                    Some(("product", v)) => listing.product = Some(v.to_string()),
+                    Some(("model", v)) => listing.model = Some(v.to_string()),
+                    Some(("device", v)) => listing.device = Some(v.to_string()),
+                    Some((_, v)) => listing.transport_id = Some(v.to_string()),
+                    None if !in_fields => state.push(token),
+                    None => {}
+                }
+                in_fields |= field.is_some();
+            }
+            if state.is_empty() {
+                return None;
+            }
+            listing.state = state.join(" ");
+            Some(listing)
+        })
+        .collect()
+}
+pub fn parse_device_states(output: &str) -> Vec<(String, DeviceState)> {
+    parse_devices_long(output)
+        .into_iter()
+        .map(|l| {
+            let state = DeviceState::parse(&l.state);
+            (l.serial, state)
+        })
+        .collect()
+}
+#[derive(Debug, Clone)]
+struct CachedProperties {
+    transport_id: Option<String>,
+    props: Properties,
+}
+#[derive(Debug, Clone, Default)]
+pub struct PropertyCache {
+    entries: Arc<Mutex<HashMap<String, CachedProperties>>>,
+}
+impl PropertyCache {
+    fn get(&self, serial: &str, transport_id: Option<&str>) -> Option<Properties> {
+        let entries = self.entries.lock().ok()?;
+        entries
+            .get(serial)
+            .filter(|c| c.transport_id.as_deref() == transport_id)
+            .map(|c| c.props.clone())
+    }
+    fn insert(&self, serial: &str, transport_id: Option<&str>, props: Properties) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.insert(
+                serial.to_string(),
+                CachedProperties {
+                    transport_id: transport_id.map(str::to_string),
+                    props,
+                },
+            );
+        }
+    }
+    pub fn invalidate(&self, serial: &str) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.remove(serial);
+        }
+    }
+    pub fn invalidate_all(&self) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.clear();
+        }
+    }
+}
+#[derive(Clone)]
 pub struct DeviceManager {
-    client: AdbClient,
+    client: SharedTransport,
+    cache: PropertyCache,
+    remembered: Option<PathBuf>,
+    inventory: Option<PathBuf>,
 }
 impl DeviceManager {
-    pub fn new(client: AdbClient) -> Self {
-        Self { client }
+    pub fn new(client: SharedTransport) -> Self {
+        Self {
+            client,
+            cache: PropertyCache::default(),
+            remembered: None,
+            inventory: None,
+        }
+    }
+    pub fn with_cache(mut self, cache: PropertyCache) -> Self {
+        self.cache = cache;
+        self
+    }
+    pub fn with_remembered_file(mut self, path: Option<PathBuf>) -> Self {
+        self.remembered = path;
+        self
+    }
+    pub fn with_inventory_file(mut self, path: Option<PathBuf>) -> Self {
+        self.inventory = path;
+        self
+    }
+    pub fn cache(&self) -> &PropertyCache {
+        &self.cache
+    }
+    pub fn client(&self) -> &SharedTransport {
+        &self.client
+    }
+    // 一次检测内的各个检查器共用属性快照，不沿用设备列表里更早缓存的属性
+    pub fn scoped(&self) -> Self {
+        self.clone().with_cache(PropertyCache::default())
     }
     pub async fn list_devices(&self) -> Result<Vec<Device>> {
-        let (output, _) = self.client.execute(&["devices", "-l"]).await?;
-        let mut devices = Vec::new();
-        for line in output.lines().skip(1) {
-            let line = line.trim();
-            if line.is_empty() || line.starts_with('*') {
-                continue;
+        let output = self.client.devices_long().await?;
+        let listings = parse_devices_long(&output);
+        let lookups = listings.iter().map(|l| async move {
+            if DeviceState::parse(&l.state).is_online() {
+                self.cached_properties(&l.serial, l.transport_id.as_deref()).await
+            } else {
+                Properties::default()
             }
-            let parts: Vec<&str> = line.split_whitespace().collect();
-            if parts.len() < 2 {
-                continue;
+        });
+        let all_props = futures::future::join_all(lookups).await;
+        let devices: Vec<Device> = listings
+            .into_iter()
+            .zip(&all_props)
+            .map(|(listing, props)| Self::build_device(listing, props))
+            .collect();
+        // 资产清单写入失败不影响设备枚举
+        let _ = self.record_seen(&devices, &all_props);
+        Ok(devices)
+    }
+    fn record_seen(&self, devices: &[Device], all_props: &[Properties]) -> Result<()> {
+        let Some(path) = &self.inventory else {
+            return Ok(());
+        };
+        Inventory::edit(path, |inventory| {
+            for (device, props) in devices.iter().zip(all_props) {
+                let record = inventory.touch(&device.serial);
+                record.last_state = Some(device.state.to_string());
+                record.apply_properties(props);
+                if record.model.is_none() {
+                    record.model = device.model.clone();
+                }
             }
-            let serial = parts[0].to_string();
-            let state = parts[1].to_string();
-            if state != "device" {
-                continue;
+        })
+    }
+    pub fn update_inventory(&self, serial: &str, apply: impl FnOnce(&mut InventoryRecord)) -> Result<()> {
+        let Some(path) = &self.inventory else {
+            return Ok(());
+        };
+        Inventory::edit(path, |inventory| apply(inventory.touch(serial)))
+    }
+    pub fn inventory(&self) -> Result<Inventory> {
+        match &self.inventory {
+            Some(path) => Inventory::load(path),
+            None => Ok(Inventory::default()),
+        }
+    }
+    async fn cached_properties(&self, serial: &str, transport_id: Option<&str>) -> Properties {
+        if let Some(props) = self.cache.get(serial, transport_id) {
+            return props;
+        }
+        match self.get_properties(serial).await {
+            Ok(props) => {
+                self.cache.insert(serial, transport_id, props.clone());
+                props
             }
-            let props = self.get_properties(&serial).await.unwrap_or_default();
-            let model = props.get("ro.product.model").cloned();
-            let brand = props.get("ro.product.brand").cloned();
-            let marketing_name = Self::first_non_empty(&[
-                props.get("ro.product.marketname").cloned(),
-                props.get("ro.config.marketing_name").cloned(),
-                props.get("ro.product.vendor.model").cloned(),
-            ]);
-            let android_version = props.get("ro.build.version.release").cloned();
-            let sdk_version = props.get("ro.build.version.sdk").cloned();
-            devices.push(Device {
-                serial,
-                state,
-                model,
-                brand,
-                marketing_name,
-                android_version,
-                sdk_version,
-            });
+            Err(_) => Properties::default(),
         }
-        Ok(devices)
     }
-    pub async fn get_properties(&self, serial: &str) -> Result<HashMap<String, String>> {
+    fn build_device(listing: DeviceListing, props: &Properties) -> Device {
+        let model = props
+            .get("ro.product.model")
+            .map(str::to_string)
+            .or_else(|| listing.model.as_ref().map(|m| m.replace('_', " ")));
+        let marketing_name = props
+            .first(&["ro.product.marketname", "ro.config.marketing_name", "ro.product.vendor.model"])
+            .map(str::to_string);
+        Device {
+            state: DeviceState::parse(&listing.state),
+            serial: listing.serial,
+            usb: listing.usb,
+            product: listing.product,
+            device: listing.device,
+            transport_id: listing.transport_id,
+            model,
+            brand: props.get("ro.product.brand").map(str::to_string),
+            marketing_name,
+            android_version: props.get("ro.build.version.release").map(str::to_string),
+            sdk_version: props.get("ro.build.version.sdk").map(str::to_string),
+        }
+    }
+    pub async fn get_properties(&self, serial: &str) -> Result<Properties> {
         let output = self.client.shell(serial, &["getprop"]).await?;
-        let mut props = HashMap::new();
-        for line in output.lines() {
-            let line = line.trim();
-            if !line.starts_with('[') {
-                continue;
-            }
-            if let Some(idx) = line.find("]: [") {
-                let key = &line[1..idx];
-                let rest = &line[idx + 4..];
-                if let Some(end) = rest.rfind(']') {
-                    let value = &rest[..end];
-                    props.insert(key.to_string(), value.to_string());
-                }
-            }
+        Ok(Properties::parse(&output))
+    }
+    pub async fn properties(&self, serial: &str) -> Result<Properties> {
+        if let Some(props) = self.cache.get(serial, None) {
+            return Ok(props);
+        }
+        let props = self.get_properties(serial).await?;
+        // 开机早期 getprop 可能还没有输出，空结果不缓存
+        if !props.is_empty() {
+            self.cache.insert(serial, None, props.clone());
         }
         Ok(props)
     }
+    pub async fn get_property(&self, serial: &str, key: &str) -> Result<Option<String>> {
+        let output = self.client.shell(serial, &["getprop", key]).await?;
+        let value = output.strip_suffix('\n').unwrap_or(&output);
+        let value = value.strip_suffix('\r').unwrap_or(value);
+        Ok((!value.is_empty()).then(|| value.to_string()))
+    }
     pub async fn get_packages(&self, serial: &str) -> Result<Vec<String>> {
         let output = self.client.shell(serial, &["pm", "list", "packages"]).await?;
         let packages: Vec<String> = output
             .lines()
             .filter_map(|line| {
                 let line = line.trim();
-                if line.starts_with("package:") {
-                    Some(line[8..].trim().to_string())
-                } else {
-                    None
-                }
+                line.strip_prefix("package:").map(|p| p.trim().to_string())
             })
             .collect();
         Ok(packages)
     }
-    pub async fn file_exists(&self, serial: &str, path: &str) -> bool {
-        let (ok, _, stderr) = self
+    pub async fn reboot(&self, serial: &str, target: Option<&str>) -> Result<()> {
+        let mut args = vec!["-s", serial, "reboot"];
+        args.extend(target);
+        self.client.execute(&args).await?;
+        Ok(())
+    }
+    pub async fn sideload(&self, serial: &str, package: &str) -> Result<String> {
+        let (stdout, _) = self
             .client
-            .try_shell(serial, &["ls", "-l", path])
+            .execute_with_timeout(&["-s", serial, "sideload", package], SIDELOAD_TIMEOUT)
+            .await?;
+        Ok(stdout)
+    }
+    pub async fn pair(&self, address: &str, code: &str) -> Result<String> {
+        let (_, stdout, stderr) = self.client.try_execute(&["pair", address.trim(), code.trim()]).await?;
+        wireless::check_pair_output(&stdout, &stderr)
+    }
+    pub async fn connect(&self, address: &str) -> Result<String> {
+        let address = wireless::normalize_address(address);
+        let (_, stdout, stderr) = self.client.try_execute(&["connect", &address]).await?;
+        let message = wireless::check_connect_output(&stdout, &stderr)?;
+        let name = self
+            .cached_properties(&address, None)
             .await
-            .unwrap_or((false, String::new(), String::new()));
+            .get("ro.product.model")
+            .map(str::to_string);
+        self.update_remembered(|list| list.remember(&address, name))?;
+        Ok(message)
+    }
+    pub async fn disconnect(&self, address: Option<&str>) -> Result<String> {
+        let mut args = vec!["disconnect"];
+        let address = address.map(wireless::normalize_address);
+        args.extend(address.as_deref());
+        let (stdout, _) = self.client.execute(&args).await?;
+        match &address {
+            Some(address) => self.cache.invalidate(address),
+            None => self.cache.invalidate_all(),
+        }
+        Ok(stdout.trim().to_string())
+    }
+    pub async fn tcpip(&self, serial: &str, port: u16) -> Result<Option<String>> {
+        let address = self.wifi_address(serial).await.ok().flatten();
+        let port = port.to_string();
+        self.client.execute(&["-s", serial, "tcpip", &port]).await?;
+        Ok(address.map(|ip| format!("{}:{}", ip, port)))
+    }
+    pub async fn wifi_address(&self, serial: &str) -> Result<Option<String>> {
+        let output = self.client.shell(serial, &["ip", "-f", "inet", "addr", "show", "wlan0"]).await?;
+        Ok(wireless::parse_wlan_address(&output))
+    }
+    pub async fn mdns_services(&self) -> Result<Vec<MdnsService>> {
+        let (ok, stdout, _) = self.client.try_execute(&["mdns", "services"]).await?;
         if !ok {
-            let err_lower = stderr.to_lowercase();
-            return !err_lower.contains("no such file") && !err_lower.contains("not found");
+            return Ok(Vec::new());
+        }
+        Ok(wireless::parse_mdns_services(&stdout))
+    }
+    pub fn remembered_devices(&self) -> Result<Vec<NetworkDevice>> {
+        match &self.remembered {
+            Some(path) => Ok(RememberedDevices::load(path)?.devices),
+            None => Ok(Vec::new()),
+        }
+    }
+    pub fn forget(&self, address: &str) -> Result<bool> {
+        let address = wireless::normalize_address(address);
+        let mut removed = false;
+        self.update_remembered(|list| removed = list.forget(&address))?;
+        Ok(removed)
+    }
+    pub async fn reconnect_remembered(&self) -> Result<Vec<(String, Result<String>)>> {
+        let devices = self.remembered_devices()?;
+        let attempts = devices.iter().map(|d| async move { (d.address.clone(), self.connect(&d.address).await) });
+        Ok(futures::future::join_all(attempts).await)
+    }
+    fn update_remembered(&self, apply: impl FnOnce(&mut RememberedDevices)) -> Result<()> {
+        let Some(path) = &self.remembered else {
+            return Ok(());
+        };
+        let mut list = RememberedDevices::load(path)?;
+        apply(&mut list);
+        list.save(path)
+    }
+    pub async fn read_file(&self, serial: &str, path: &str) -> Result<Vec<u8>> {
+        self.client.exec_out(serial, &["cat", path]).await
+    }
+    pub async fn read_file_as_root(&self, serial: &str, path: &str) -> Result<Vec<u8>> {
+        let cmd = shell_quote(&format!("cat {}", shell_quote(path)));
+        self.client.exec_out(serial, &["su", "-c", &cmd]).await
+    }
+    pub async fn screencap(&self, serial: &str) -> Result<Vec<u8>> {
+        let png = self.client.exec_out(serial, &["screencap", "-p"]).await?;
+        if !png.starts_with(b"\x89PNG") {
+            return Err(AdbError::Parse("screencap did not return a PNG image".to_string()));
+        }
+        Ok(png)
+    }
+    // 保留的公开接口，命令行暂未使用
+    #[allow(dead_code)]
+    pub async fn file_exists(&self, serial: &str, path: &str) -> bool {
+        match self.client.shell_output(serial, &["ls", "-l", path]).await {
+            Ok(out) => !matches!(out.failure(), Some(AdbError::NotFoundOnDevice(_))),
+            Err(_) => false,
         }
-        true
     }
-    fn first_non_empty(values: &[Option<String>]) -> Option<String> {
-        values
-            .iter()
-            .find(|v| v.as_ref().map(|s| !s.is_empty()).unwrap_or(false))
-            .and_then(|v| v.clone())
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adb::fake::{fixture, FakeFixture};
+    use crate::adb::FakeTransport;
+    #[test]
+    fn devices_long_splits_state_and_fields() {
+        let output = "List of devices attached\n\
+            * daemon started successfully\n\
+            AAA device usb:1-1 product:panther model:Pixel_7 device:panther transport_id:1\n\
+            BBB no permissions (missing udev rules?) usb:1-2 transport_id:2\n\
+            192.168.1.5:5555 offline\n";
+        let listings = parse_devices_long(output);
+        assert_eq!(listings.len(), 3);
+        assert_eq!(listings[0].serial, "AAA");
+        assert_eq!(listings[0].state, "device");
+        assert_eq!(listings[0].model.as_deref(), Some("Pixel_7"));
+        assert_eq!(listings[0].transport_id.as_deref(), Some("1"));
+        assert_eq!(DeviceState::parse(&listings[1].state), DeviceState::NoPermissions);
+        assert_eq!(listings[1].usb.as_deref(), Some("1-2"));
+        assert_eq!(listings[2].state, "offline");
+        assert_eq!(listings[2].usb, None);
+    }
+    #[tokio::test]
+    async fn properties_are_parsed_from_getprop() {
+        let dm = DeviceManager::new(fixture("pixel7_kernelsu.json"));
+        let props = dm.get_properties("AAA").await.unwrap();
+        assert_eq!(props.get("ro.product.model"), Some("Pixel 7"));
+        assert_eq!(props.get("ro.build.version.sdk"), Some("34"));
+        assert_eq!(props.get("persist.sys.banner"), Some("first line\nsecond line"));
+        assert_eq!(props.get("init.svc.ksud"), Some("running"));
+    }
+    #[tokio::test]
+    async fn list_devices_only_queries_online_devices() {
+        let dm = DeviceManager::new(fixture("pixel7_kernelsu.json"));
+        let devices = dm.list_devices().await.unwrap();
+        assert_eq!(devices.len(), 2);
+        assert_eq!(devices[0].display_name(), "google Pixel 7");
+        assert_eq!(devices[0].android_version.as_deref(), Some("14"));
+        assert_eq!(devices[0].connection(), "usb:1-1");
+        assert_eq!(devices[1].state, DeviceState::Unauthorized);
+        assert_eq!(devices[1].model, None);
     }
-}
\ No newline at end of file
+    #[tokio::test]
+    async fn clones_share_a_property_snapshot_until_rescoped() {
+        let fake = Arc::new(FakeTransport::new(FakeFixture::default()).with_shell(
+            "AAA",
+            &["getprop"],
+            "[ro.product.model]: [Pixel 7]\n",
+        ));
+        let dm = DeviceManager::new(fake.clone()).scoped();
+        dm.properties("AAA").await.unwrap();
+        dm.clone().properties("AAA").await.unwrap();
+        assert_eq!(fake.calls().len(), 1);
+        dm.scoped().properties("AAA").await.unwrap();
+        assert_eq!(fake.calls().len(), 2);
+    }
+    #[tokio::test]
+    async fn screencap_rejects_non_png_output() {
+        let fake = FakeTransport::new(FakeFixture::default())
+            .with_response("-s AAA exec-out screencap -p", "Error: capture failed\n", "", 0)
+            .with_response("-s AAA exec-out cat /sdcard/a.txt", "hello\n", "", 0);
+        let dm = DeviceManager::new(Arc::new(fake));
+        assert!(matches!(dm.screencap("AAA").await, Err(AdbError::Parse(_))));
+        assert_eq!(dm.read_file("AAA", "/sdcard/a.txt").await.unwrap(), b"hello\n");
+    }
+}
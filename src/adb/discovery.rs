@@ -0,0 +1,296 @@
+﻿use serde::Serialize;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+pub const ADB_BINARY: &str = if cfg!(windows) { "adb.exe" } else { "adb" };
+#[derive(Debug, Clone, Serialize)]
+pub struct AdbCandidate {
+    pub source: String,
+    pub path: PathBuf,
+    pub accepted: bool,
+    pub reason: String,
+}
+#[derive(Debug, Clone, Serialize)]
+pub struct AdbDiscovery {
+    pub platform: String,
+    pub candidates: Vec<AdbCandidate>,
+    pub selected: Option<PathBuf>,
+}
+impl AdbDiscovery {
+    pub fn run(explicit: Option<&Path>) -> Self {
+        let exe_dir = std::env::current_exe().ok().and_then(|exe| exe.parent().map(Path::to_path_buf));
+        Self::discover(explicit, exe_dir.as_deref(), |name| std::env::var_os(name))
+    }
+    // 环境变量和程序目录由调用方传入，查找顺序可以脱离真实环境测试
+    fn discover(explicit: Option<&Path>, exe_dir: Option<&Path>, env: impl Fn(&str) -> Option<OsString>) -> Self {
+        let mut discovery = Self {
+            platform: format!("{}-{}", std::env::consts::OS, std::env::consts::ARCH),
+            candidates: Vec::new(),
+            selected: None,
+        };
+        if let Some(path) = explicit {
+            discovery.consider("配置 adb_path", path.to_path_buf());
+            if discovery.selected.is_none() {
+                return discovery;
+            }
+        }
+        if let Some(parent) = exe_dir {
+            discovery.consider("程序目录 platform-tools", parent.join("platform-tools").join(ADB_BINARY));
+            discovery.consider("程序目录 vendor", parent.join("vendor").join("platform-tools").join(ADB_BINARY));
+            if let Some(project_root) = parent.parent().and_then(|p| p.parent()) {
+                discovery.consider(
+                    "工程目录 vendor",
+                    project_root.join("vendor").join("platform-tools").join(ADB_BINARY),
+                );
+            }
+        }
+        discovery.consider("当前目录 vendor", PathBuf::from("vendor").join("platform-tools").join(ADB_BINARY));
+        for var in ["ANDROID_SDK_ROOT", "ANDROID_HOME"] {
+            match env(var) {
+                Some(root) if !root.is_empty() => {
+                    discovery.consider(&format!("${}", var), Path::new(&root).join("platform-tools").join(ADB_BINARY));
+                }
+                _ => discovery.skip(&format!("${}", var), "环境变量未设置"),
+            }
+        }
+        match env("PATH") {
+            Some(paths) => {
+                for dir in std::env::split_paths(&paths) {
+                    if !dir.as_os_str().is_empty() {
+                        discovery.consider("PATH", dir.join(ADB_BINARY));
+                    }
+                }
+            }
+            None => discovery.skip("PATH", "环境变量未设置"),
+        }
+        for path in Self::platform_defaults(&env) {
+            discovery.consider("平台默认 SDK 位置", path);
+        }
+        discovery
+    }
+    fn platform_defaults(env: &impl Fn(&str) -> Option<OsString>) -> Vec<PathBuf> {
+        let home = env(if cfg!(windows) { "USERPROFILE" } else { "HOME" }).map(PathBuf::from);
+        let mut paths = Vec::new();
+        if cfg!(windows) {
+            if let Some(local) = env("LOCALAPPDATA") {
+                paths.push(Path::new(&local).join("Android").join("Sdk").join("platform-tools").join(ADB_BINARY));
+            }
+        } else if cfg!(target_os = "macos") {
+            if let Some(home) = &home {
+                paths.push(home.join("Library/Android/sdk/platform-tools").join(ADB_BINARY));
+            }
+            paths.push(PathBuf::from("/opt/homebrew/bin/adb"));
+            paths.push(PathBuf::from("/usr/local/bin/adb"));
+        } else {
+            if let Some(home) = &home {
+                paths.push(home.join("Android/Sdk/platform-tools").join(ADB_BINARY));
+            }
+            paths.push(PathBuf::from("/usr/lib/android-sdk/platform-tools/adb"));
+            paths.push(PathBuf::from("/opt/android-sdk/platform-tools/adb"));
+        }
+        paths
+    }
+    fn consider(&mut self, source: &str, path: PathBuf) {
+        if self.candidates.iter().any(|c| c.path == path) {
+            return;
+        }
+        if self.selected.is_some() {
+            self.push(source, path, false, "已选用更高优先级的 adb");
+            return;
+        }
+        match Self::check(&path) {
+            Ok(()) => {
+                let resolved = std::fs::canonicalize(&path).unwrap_or_else(|_| path.clone());
+                self.selected = Some(resolved);
+                self.push(source, path, true, "可用");
+            }
+            Err(reason) => self.push(source, path, false, &reason),
+        }
+    }
+    fn skip(&mut self, source: &str, reason: &str) {
+        self.push(source, PathBuf::new(), false, reason);
+    }
+    fn push(&mut self, source: &str, path: PathBuf, accepted: bool, reason: &str) {
+        self.candidates.push(AdbCandidate {
+            source: source.to_string(),
+            path,
+            accepted,
+            reason: reason.to_string(),
+        });
+    }
+    fn check(path: &Path) -> Result<(), String> {
+        let meta = std::fs::metadata(path).map_err(|_| "文件不存在".to_string())?;
+        if !meta.is_file() {
+            return Err("不是文件".to_string());
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            if meta.permissions().mode() & 0o111 == 0 {
+                return Err("没有执行权限".to_string());
+            }
+        }
+        Ok(())
+    }
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        out.push_str(&format!("平台: {}\n\n候选 adb:\n", self.platform));
+        for c in &self.candidates {
+            let mark = if c.accepted { "✓" } else { "✗" };
+            let path = if c.path.as_os_str().is_empty() {
+                "-".to_string()
+            } else {
+                c.path.display().to_string()
+            };
+            out.push_str(&format!("  {} [{}] {} — {}\n", mark, c.source, path, c.reason));
+        }
+        out.push_str(&format!(
+            "\n选用: {}\n",
+            self.selected
+                .as_ref()
+                .map(|p| p.display().to_string())
+                .unwrap_or_else(|| "未找到可用的 adb".to_string())
+        ));
+        out
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    struct Sandbox {
+        root: PathBuf,
+        env: HashMap<&'static str, OsString>,
+    }
+    impl Sandbox {
+        fn new(name: &str) -> Self {
+            let root = std::env::temp_dir().join(format!("androidchecker-discovery-{}-{}", name, std::process::id()));
+            std::fs::create_dir_all(&root).unwrap();
+            Self { root, env: HashMap::new() }
+        }
+        fn adb(&self, dir: &str) -> PathBuf {
+            let dir = self.root.join(dir);
+            std::fs::create_dir_all(&dir).unwrap();
+            let path = dir.join(ADB_BINARY);
+            std::fs::write(&path, "#!/bin/sh\n").unwrap();
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+            }
+            path
+        }
+        fn set(&mut self, name: &'static str, value: impl Into<OsString>) {
+            self.env.insert(name, value.into());
+        }
+        fn discover(&self, explicit: Option<&Path>, exe_dir: Option<&Path>) -> AdbDiscovery {
+            AdbDiscovery::discover(explicit, exe_dir, |name| self.env.get(name).cloned())
+        }
+    }
+    impl Drop for Sandbox {
+        fn drop(&mut self) {
+            std::fs::remove_dir_all(&self.root).ok();
+        }
+    }
+    fn selected(discovery: &AdbDiscovery) -> PathBuf {
+        discovery.selected.clone().expect("adb selected")
+    }
+    fn canonical(path: &Path) -> PathBuf {
+        std::fs::canonicalize(path).unwrap()
+    }
+    #[test]
+    fn explicit_path_wins_and_later_candidates_are_still_listed() {
+        let mut sandbox = Sandbox::new("explicit");
+        let explicit = sandbox.adb("custom");
+        let on_path = sandbox.adb("bin");
+        sandbox.set("PATH", sandbox.root.join("bin"));
+        let discovery = sandbox.discover(Some(&explicit), None);
+        assert_eq!(selected(&discovery), canonical(&explicit));
+        assert_eq!(discovery.candidates[0].source, "配置 adb_path");
+        assert!(discovery.candidates[0].accepted);
+        let path_candidate = discovery.candidates.iter().find(|c| c.path == on_path).unwrap();
+        assert!(!path_candidate.accepted);
+        assert_eq!(path_candidate.reason, "已选用更高优先级的 adb");
+        assert_eq!(discovery.candidates.iter().filter(|c| c.accepted).count(), 1);
+    }
+    #[test]
+    fn invalid_explicit_path_stops_the_search() {
+        let mut sandbox = Sandbox::new("missing");
+        sandbox.adb("bin");
+        sandbox.set("PATH", sandbox.root.join("bin"));
+        let discovery = sandbox.discover(Some(&sandbox.root.join("nope").join(ADB_BINARY)), None);
+        assert!(discovery.selected.is_none());
+        assert_eq!(discovery.candidates.len(), 1);
+        assert_eq!(discovery.candidates[0].reason, "文件不存在");
+        assert!(discovery.render().contains("未找到可用的 adb"));
+    }
+    #[test]
+    fn program_directory_comes_before_sdk_variables() {
+        let mut sandbox = Sandbox::new("order");
+        let bundled = sandbox.adb("target/release/platform-tools");
+        sandbox.adb("sdk/platform-tools");
+        sandbox.set("ANDROID_HOME", sandbox.root.join("sdk"));
+        let discovery = sandbox.discover(None, Some(&sandbox.root.join("target").join("release")));
+        assert_eq!(selected(&discovery), canonical(&bundled));
+        let sources: Vec<&str> = discovery.candidates.iter().map(|c| c.source.as_str()).collect();
+        assert_eq!(
+            &sources[..6],
+            [
+                "程序目录 platform-tools",
+                "程序目录 vendor",
+                "工程目录 vendor",
+                "当前目录 vendor",
+                "$ANDROID_SDK_ROOT",
+                "$ANDROID_HOME",
+            ]
+        );
+    }
+    #[test]
+    fn sdk_variable_overrides_path() {
+        let mut sandbox = Sandbox::new("env");
+        let sdk = sandbox.adb("sdk/platform-tools");
+        let on_path = sandbox.adb("bin");
+        sandbox.set("ANDROID_HOME", sandbox.root.join("sdk"));
+        sandbox.set("PATH", sandbox.root.join("bin"));
+        let discovery = sandbox.discover(None, None);
+        assert_eq!(selected(&discovery), canonical(&sdk));
+        let unset = discovery.candidates.iter().find(|c| c.source == "$ANDROID_SDK_ROOT").unwrap();
+        assert_eq!(unset.reason, "环境变量未设置");
+        assert!(!discovery.candidates.iter().find(|c| c.path == on_path).unwrap().accepted);
+        // 两个变量都设置时 ANDROID_SDK_ROOT 优先
+        let newer = sandbox.adb("sdk2/platform-tools");
+        sandbox.set("ANDROID_SDK_ROOT", sandbox.root.join("sdk2"));
+        assert_eq!(selected(&sandbox.discover(None, None)), canonical(&newer));
+    }
+    #[test]
+    fn path_is_searched_in_order_when_nothing_else_matches() {
+        let mut sandbox = Sandbox::new("path");
+        std::fs::create_dir_all(sandbox.root.join("empty")).unwrap();
+        std::fs::create_dir_all(sandbox.root.join("dir").join(ADB_BINARY)).unwrap();
+        let second = sandbox.adb("second");
+        let third = sandbox.adb("third");
+        let dirs = ["empty", "dir", "second", "third", "second"].map(|d| sandbox.root.join(d));
+        sandbox.set("PATH", std::env::join_paths(dirs).unwrap());
+        let discovery = sandbox.discover(None, None);
+        assert_eq!(selected(&discovery), canonical(&second));
+        let from_path: Vec<&AdbCandidate> = discovery.candidates.iter().filter(|c| c.source == "PATH").collect();
+        // 重复的目录只列一次
+        assert_eq!(from_path.len(), 4);
+        assert_eq!(from_path[0].reason, "文件不存在");
+        assert_eq!(from_path[1].reason, "不是文件");
+        assert!(from_path[2].accepted);
+        assert_eq!(from_path[3].path, third);
+        assert!(!from_path[3].accepted);
+    }
+    #[cfg(unix)]
+    #[test]
+    fn files_without_execute_permission_are_rejected() {
+        use std::os::unix::fs::PermissionsExt;
+        let mut sandbox = Sandbox::new("mode");
+        let adb = sandbox.adb("bin");
+        std::fs::set_permissions(&adb, std::fs::Permissions::from_mode(0o644)).unwrap();
+        sandbox.set("PATH", sandbox.root.join("bin"));
+        let discovery = sandbox.discover(None, None);
+        let candidate = discovery.candidates.iter().find(|c| c.path == adb).unwrap();
+        assert_eq!(candidate.reason, "没有执行权限");
+    }
+}
@@ -0,0 +1,101 @@
+﻿use super::transport::{command_key, exec_out_key, shell_key, AdbTransport, ShellOutput};
+use crate::core::{AdbError, Result};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Mutex;
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FakeResponse {
+    #[serde(default)]
+    pub stdout: String,
+    #[serde(default)]
+    pub stderr: String,
+    #[serde(default)]
+    pub exit_code: i32,
+}
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FakeFixture {
+    #[serde(default)]
+    pub commands: HashMap<String, FakeResponse>,
+    #[serde(default)]
+    pub fallback: Option<FakeResponse>,
+}
+pub struct FakeTransport {
+    fixture: FakeFixture,
+    calls: Mutex<Vec<String>>,
+}
+impl FakeTransport {
+    pub fn new(fixture: FakeFixture) -> Self {
+        Self {
+            fixture,
+            calls: Mutex::new(Vec::new()),
+        }
+    }
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        let fixture: FakeFixture = serde_json::from_str(&content)?;
+        Ok(Self::new(fixture))
+    }
+    #[cfg(test)]
+    pub fn with_response(mut self, command: &str, stdout: &str, stderr: &str, exit_code: i32) -> Self {
+        self.fixture.commands.insert(
+            command.to_string(),
+            FakeResponse {
+                stdout: stdout.to_string(),
+                stderr: stderr.to_string(),
+                exit_code,
+            },
+        );
+        self
+    }
+    #[cfg(test)]
+    pub fn with_shell(self, serial: &str, cmd: &[&str], stdout: &str) -> Self {
+        let key = shell_key(serial, cmd);
+        self.with_response(&key, stdout, "", 0)
+    }
+    #[cfg(test)]
+    pub fn calls(&self) -> Vec<String> {
+        self.calls.lock().map(|c| c.clone()).unwrap_or_default()
+    }
+    fn respond(&self, key: String) -> Result<(bool, String, String)> {
+        let out = self.respond_output(key)?;
+        Ok((out.success(), out.stdout, out.stderr))
+    }
+    fn respond_output(&self, key: String) -> Result<ShellOutput> {
+        if let Ok(mut calls) = self.calls.lock() {
+            calls.push(key.clone());
+        }
+        let resp = self
+            .fixture
+            .commands
+            .get(&key)
+            .or(self.fixture.fallback.as_ref())
+            .ok_or_else(|| AdbError::CommandFailed(format!("no fixture for `{}`", key)))?;
+        Ok(ShellOutput {
+            stdout: resp.stdout.clone(),
+            stderr: resp.stderr.clone(),
+            exit_code: resp.exit_code,
+        })
+    }
+}
+#[async_trait]
+impl AdbTransport for FakeTransport {
+    async fn try_execute(&self, args: &[&str]) -> Result<(bool, String, String)> {
+        self.respond(command_key(args))
+    }
+    async fn try_shell(&self, serial: &str, cmd: &[&str]) -> Result<(bool, String, String)> {
+        self.respond(shell_key(serial, cmd))
+    }
+    async fn shell_output(&self, serial: &str, cmd: &[&str]) -> Result<ShellOutput> {
+        self.respond_output(shell_key(serial, cmd))
+    }
+    async fn exec_out(&self, serial: &str, cmd: &[&str]) -> Result<Vec<u8>> {
+        Ok(self.respond_output(exec_out_key(serial, cmd))?.into_result()?.into_bytes())
+    }
+}
+#[cfg(test)]
+pub fn fixture(name: &str) -> super::SharedTransport {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(name);
+    std::sync::Arc::new(FakeTransport::from_file(&path).expect("fixture"))
+}
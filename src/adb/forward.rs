@@ -0,0 +1,169 @@
+﻿use super::transport::SharedTransport;
+use crate::core::{AdbError, Result};
+use serde::{Deserialize, Serialize};
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ForwardDirection {
+    Forward,
+    Reverse,
+}
+impl ForwardDirection {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Forward => "forward",
+            Self::Reverse => "reverse",
+        }
+    }
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "forward" => Some(Self::Forward),
+            "reverse" => Some(Self::Reverse),
+            _ => None,
+        }
+    }
+}
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Endpoint {
+    Tcp(u16),
+    LocalAbstract(String),
+    Jdwp(u32),
+}
+impl Endpoint {
+    pub fn parse(spec: &str) -> Result<Self> {
+        let invalid = || AdbError::Parse(format!("无效的端点 \"{}\"，可用 tcp:<端口>、localabstract:<名称>、jdwp:<pid>", spec));
+        let (kind, value) = spec.trim().split_once(':').ok_or_else(invalid)?;
+        match kind {
+            "tcp" => value.parse().map(Self::Tcp).map_err(|_| invalid()),
+            "localabstract" if !value.is_empty() => Ok(Self::LocalAbstract(value.to_string())),
+            "jdwp" => value.parse().map(Self::Jdwp).map_err(|_| invalid()),
+            _ => Err(invalid()),
+        }
+    }
+}
+impl std::fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Tcp(port) => write!(f, "tcp:{}", port),
+            Self::LocalAbstract(name) => write!(f, "localabstract:{}", name),
+            Self::Jdwp(pid) => write!(f, "jdwp:{}", pid),
+        }
+    }
+}
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PortRule {
+    pub direction: ForwardDirection,
+    pub listen: Endpoint,
+    pub target: Endpoint,
+}
+impl PortRule {
+    pub fn is_jdwp(&self) -> bool {
+        matches!(self.target, Endpoint::Jdwp(_))
+    }
+    pub fn new(direction: ForwardDirection, listen: Endpoint, target: Endpoint) -> Result<Self> {
+        if matches!(listen, Endpoint::Jdwp(_)) {
+            return Err(AdbError::Parse("jdwp 只能作为转发目标".to_string()));
+        }
+        if direction == ForwardDirection::Reverse && matches!(target, Endpoint::Jdwp(_)) {
+            return Err(AdbError::Parse("reverse 规则不支持 jdwp 目标".to_string()));
+        }
+        Ok(Self {
+            direction,
+            listen,
+            target,
+        })
+    }
+    pub fn parse(spec: &str) -> Result<Self> {
+        let parts: Vec<&str> = spec.split_whitespace().collect();
+        let [direction, listen, target] = parts.as_slice() else {
+            return Err(AdbError::Parse(format!(
+                "无效的规则 \"{}\"，格式: forward|reverse <监听端点> <目标端点>",
+                spec
+            )));
+        };
+        let direction = ForwardDirection::parse(direction)
+            .ok_or_else(|| AdbError::Parse(format!("未知的规则方向 \"{}\"", direction)))?;
+        Self::new(direction, Endpoint::parse(listen)?, Endpoint::parse(target)?)
+    }
+    pub fn describe(&self) -> String {
+        match self.direction {
+            ForwardDirection::Forward => format!("电脑 {} → 手机 {}", self.listen, self.target),
+            ForwardDirection::Reverse => format!("手机 {} → 电脑 {}", self.listen, self.target),
+        }
+    }
+}
+impl std::fmt::Display for PortRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} {}", self.direction.as_str(), self.listen, self.target)
+    }
+}
+pub fn parse_forward_list(output: &str, direction: ForwardDirection, serial: Option<&str>) -> Vec<PortRule> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            let owner = parts.next()?;
+            if serial.is_some_and(|s| s != owner) {
+                return None;
+            }
+            let listen = Endpoint::parse(parts.next()?).ok()?;
+            let target = Endpoint::parse(parts.next()?).ok()?;
+            Some(PortRule {
+                direction,
+                listen,
+                target,
+            })
+        })
+        .collect()
+}
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RuleSet {
+    #[serde(default)]
+    pub devices: Vec<String>,
+    #[serde(default = "default_auto_apply")]
+    pub auto_apply: bool,
+    pub rules: Vec<String>,
+}
+fn default_auto_apply() -> bool {
+    true
+}
+impl RuleSet {
+    pub fn parsed_rules(&self) -> Result<Vec<PortRule>> {
+        self.rules.iter().map(|r| PortRule::parse(r)).collect()
+    }
+    // jdwp:<pid> 的进程号在设备重连后即失效，这类规则不能自动应用
+    pub fn validate(&self) -> Result<Vec<PortRule>> {
+        let rules = self.parsed_rules()?;
+        if self.auto_apply {
+            if let Some(rule) = rules.iter().find(|r| r.is_jdwp()) {
+                return Err(AdbError::Config(format!("{}: jdwp 进程号在重连后失效，不能设为自动应用", rule)));
+            }
+        }
+        Ok(rules)
+    }
+    pub fn applies_to(&self, serial: &str) -> bool {
+        self.devices.is_empty() || self.devices.iter().any(|d| d == serial)
+    }
+}
+pub async fn apply_rules(client: &SharedTransport, serial: &str, rules: &[PortRule]) -> Vec<(PortRule, Result<()>)> {
+    let mut results = Vec::with_capacity(rules.len());
+    for rule in rules {
+        let result = client.add_forward(serial, rule).await;
+        results.push((rule.clone(), result));
+    }
+    results
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn auto_applied_rule_sets_reject_jdwp_targets() {
+        let mut set = RuleSet {
+            devices: Vec::new(),
+            auto_apply: true,
+            rules: vec!["forward tcp:9222 localabstract:chrome_devtools_remote".to_string(), "forward tcp:8700 jdwp:4321".to_string()],
+        };
+        assert!(matches!(set.validate(), Err(AdbError::Config(_))));
+        set.auto_apply = false;
+        assert_eq!(set.validate().unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,253 @@
+﻿use crate::adb::props::{Properties, VerifiedBootState};
+use crate::core::Result;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+pub const INVENTORY_FILE: &str = "inventory.json";
+// 同一进程内的并发任务（如批量审计）共用一个文件，读改写需串行
+static INVENTORY_LOCK: Mutex<()> = Mutex::new(());
+const CSV_HEADER: [&str; 15] = [
+    "serial",
+    "brand",
+    "model",
+    "marketing_name",
+    "android_version",
+    "sdk_version",
+    "security_patch",
+    "bootloader",
+    "last_state",
+    "last_audit",
+    "last_audit_at",
+    "last_backup",
+    "last_backup_at",
+    "first_seen",
+    "last_seen",
+];
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InventoryRecord {
+    pub serial: String,
+    #[serde(default)]
+    pub brand: Option<String>,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub marketing_name: Option<String>,
+    #[serde(default)]
+    pub android_version: Option<String>,
+    #[serde(default)]
+    pub sdk_version: Option<String>,
+    #[serde(default)]
+    pub security_patch: Option<String>,
+    #[serde(default)]
+    pub bootloader: Option<String>,
+    #[serde(default)]
+    pub last_state: Option<String>,
+    #[serde(default)]
+    pub last_audit: Option<String>,
+    #[serde(default)]
+    pub last_audit_at: Option<String>,
+    #[serde(default)]
+    pub last_backup: Option<String>,
+    #[serde(default)]
+    pub last_backup_at: Option<String>,
+    pub first_seen: String,
+    pub last_seen: String,
+}
+impl InventoryRecord {
+    pub fn apply_properties(&mut self, props: &Properties) {
+        let set = |field: &mut Option<String>, value: Option<&str>| {
+            if let Some(v) = value {
+                *field = Some(v.to_string());
+            }
+        };
+        set(&mut self.brand, props.get("ro.product.brand"));
+        set(&mut self.model, props.get("ro.product.model"));
+        set(
+            &mut self.marketing_name,
+            props.first(&["ro.product.marketname", "ro.config.marketing_name", "ro.product.vendor.model"]),
+        );
+        set(&mut self.android_version, props.get("ro.build.version.release"));
+        set(&mut self.sdk_version, props.get("ro.build.version.sdk"));
+        set(&mut self.security_patch, props.get("ro.build.version.security_patch"));
+        set(&mut self.bootloader, bootloader_state(props));
+    }
+    pub fn display_name(&self) -> String {
+        match (&self.brand, &self.marketing_name, &self.model) {
+            (Some(brand), Some(name), _) => format!("{} {}", brand, name),
+            (_, _, Some(model)) => model.clone(),
+            _ => "-".to_string(),
+        }
+    }
+    fn csv_fields(&self) -> [String; 15] {
+        let opt = |v: &Option<String>| v.clone().unwrap_or_default();
+        [
+            self.serial.clone(),
+            opt(&self.brand),
+            opt(&self.model),
+            opt(&self.marketing_name),
+            opt(&self.android_version),
+            opt(&self.sdk_version),
+            opt(&self.security_patch),
+            opt(&self.bootloader),
+            opt(&self.last_state),
+            opt(&self.last_audit),
+            opt(&self.last_audit_at),
+            opt(&self.last_backup),
+            opt(&self.last_backup_at),
+            self.first_seen.clone(),
+            self.last_seen.clone(),
+        ]
+    }
+    fn matches(&self, filter: &str) -> bool {
+        let filter = filter.to_lowercase();
+        [&Some(self.serial.clone()), &self.brand, &self.model, &self.marketing_name]
+            .into_iter()
+            .flatten()
+            .any(|v| v.to_lowercase().contains(&filter))
+    }
+}
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Inventory {
+    pub devices: BTreeMap<String, InventoryRecord>,
+}
+impl Inventory {
+    pub fn default_path() -> Option<PathBuf> {
+        crate::core::config::Config::user_dir().map(|dir| dir.join(INVENTORY_FILE))
+    }
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.is_file() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+    pub fn edit(path: &Path, apply: impl FnOnce(&mut Self)) -> Result<()> {
+        let _guard = INVENTORY_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let mut inventory = Self::load(path)?;
+        apply(&mut inventory);
+        inventory.save(path)
+    }
+    pub fn touch(&mut self, serial: &str) -> &mut InventoryRecord {
+        let now = now();
+        let record = self.devices.entry(serial.to_string()).or_insert_with(|| InventoryRecord {
+            serial: serial.to_string(),
+            first_seen: now.clone(),
+            ..InventoryRecord::default()
+        });
+        record.last_seen = now;
+        record
+    }
+    pub fn get(&self, serial: &str) -> Option<&InventoryRecord> {
+        self.devices.get(serial)
+    }
+    pub fn query(&self, filter: Option<&str>) -> Vec<&InventoryRecord> {
+        self.devices
+            .values()
+            .filter(|r| filter.is_none_or(|f| r.matches(f)))
+            .collect()
+    }
+    pub fn to_csv(records: &[&InventoryRecord]) -> String {
+        let mut out = CSV_HEADER.join(",");
+        out.push('\n');
+        for record in records {
+            let fields: Vec<String> = record.csv_fields().iter().map(|f| csv_escape(f)).collect();
+            out.push_str(&fields.join(","));
+            out.push('\n');
+        }
+        out
+    }
+}
+pub fn now() -> String {
+    chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string()
+}
+pub fn bootloader_state(props: &Properties) -> Option<&'static str> {
+    let locked = props
+        .bool("ro.boot.flash.locked")
+        .or_else(|| match props.get("ro.boot.vbmeta.device_state") {
+            Some("locked") => Some(true),
+            Some("unlocked") => Some(false),
+            _ => None,
+        })
+        .or_else(|| match props.verified_boot_state() {
+            Some(VerifiedBootState::Green) => Some(true),
+            Some(VerifiedBootState::Orange) => Some(false),
+            _ => None,
+        })?;
+    Some(if locked { "locked" } else { "unlocked" })
+}
+fn csv_escape(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    fn record(serial: &str, brand: &str, model: &str) -> InventoryRecord {
+        InventoryRecord {
+            serial: serial.to_string(),
+            brand: Some(brand.to_string()),
+            model: Some(model.to_string()),
+            first_seen: "2026-10-01 09:00:00".to_string(),
+            last_seen: "2026-10-18 09:00:00".to_string(),
+            ..InventoryRecord::default()
+        }
+    }
+    #[test]
+    fn csv_fields_are_escaped() {
+        assert_eq!(csv_escape("Pixel 7"), "Pixel 7");
+        assert_eq!(csv_escape("Root: KernelSU, Hook: 无"), "\"Root: KernelSU, Hook: 无\"");
+        assert_eq!(csv_escape("6.7\" panel"), "\"6.7\"\" panel\"");
+        assert_eq!(csv_escape("line one\nline two"), "\"line one\nline two\"");
+        assert_eq!(csv_escape("cr\r"), "\"cr\r\"");
+        assert_eq!(csv_escape(""), "");
+    }
+    #[test]
+    fn csv_export_has_a_header_and_one_row_per_record() {
+        let mut pixel = record("AAA", "google", "Pixel 7");
+        pixel.last_audit = Some("已 Root, 未发现, 真机, \"orange\"".to_string());
+        let tablet = record("TAB", "samsung", "SM-X200");
+        let csv = Inventory::to_csv(&[&pixel, &tablet]);
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], CSV_HEADER.join(","));
+        assert_eq!(
+            lines[1],
+            "AAA,google,Pixel 7,,,,,,,\"已 Root, 未发现, 真机, \"\"orange\"\"\",,,,2026-10-01 09:00:00,2026-10-18 09:00:00"
+        );
+        assert!(lines[2].starts_with("TAB,samsung,SM-X200,"));
+        assert_eq!(Inventory::to_csv(&[]), format!("{}\n", CSV_HEADER.join(",")));
+    }
+    #[test]
+    fn query_matches_serial_brand_model_and_name_case_insensitively() {
+        let mut inventory = Inventory::default();
+        let mut pixel = record("AAA", "google", "Pixel 7");
+        pixel.marketing_name = Some("Pixel 7 Pro".to_string());
+        pixel.security_patch = Some("2024-05-05".to_string());
+        inventory.devices.insert("AAA".to_string(), pixel);
+        inventory.devices.insert("TAB".to_string(), record("TAB", "samsung", "SM-X200"));
+        inventory.devices.insert("emulator-5554".to_string(), InventoryRecord {
+            serial: "emulator-5554".to_string(),
+            ..InventoryRecord::default()
+        });
+        let serials = |filter: Option<&str>| inventory.query(filter).iter().map(|r| r.serial.clone()).collect::<Vec<_>>();
+        assert_eq!(serials(None), ["AAA", "TAB", "emulator-5554"]);
+        assert_eq!(serials(Some("GOOGLE")), ["AAA"]);
+        assert_eq!(serials(Some("pro")), ["AAA"]);
+        assert_eq!(serials(Some("sm-x")), ["TAB"]);
+        assert_eq!(serials(Some("emulator")), ["emulator-5554"]);
+        // 只按序列号、品牌、型号和市场名称筛选
+        assert!(serials(Some("2024-05")).is_empty());
+        assert!(serials(Some("nokia")).is_empty());
+    }
+}
@@ -1,4 +1,21 @@
 ﻿pub mod client;
 pub mod device;
+pub mod discovery;
+pub mod fake;
+pub mod forward;
+pub mod inventory;
+pub mod policy;
+pub mod props;
+pub mod protocol;
+pub mod recorder;
+pub mod sync;
+pub mod transport;
+pub mod watcher;
+pub mod wireless;
 pub use client::AdbClient;
-pub use device::{Device, DeviceManager};
\ No newline at end of file
+pub use device::{Device, DeviceManager, DeviceOperation};
+pub use discovery::AdbDiscovery;
+pub use fake::FakeTransport;
+pub use recorder::{RecordingTransport, ReplayTransport};
+pub use transport::SharedTransport;
+pub use watcher::{DeviceEvent, DeviceWatcher};
\ No newline at end of file
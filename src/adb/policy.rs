@@ -0,0 +1,101 @@
+﻿use crate::core::AdbError;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(200),
+            max_backoff: Duration::from_secs(5),
+        }
+    }
+}
+impl RetryPolicy {
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Self::default()
+        }
+    }
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        self.initial_backoff.saturating_mul(factor).min(self.max_backoff)
+    }
+    // 只重试命令到达设备之前的失败（连不上 adb server、server 重启、设备仍在连接）；
+    // 执行中断开（Protocol、流提前结束）时命令可能已经执行，重试会重复 reboot、rm 等操作
+    pub fn is_transient(err: &AdbError) -> bool {
+        matches!(err, AdbError::Offline(_) | AdbError::ServerDied(_)) || Self::is_connect_error(err)
+    }
+    pub fn is_connect_error(err: &AdbError) -> bool {
+        matches!(err, AdbError::Io(e) if matches!(
+            e.kind(),
+            std::io::ErrorKind::ConnectionRefused
+                | std::io::ErrorKind::ConnectionReset
+                | std::io::ErrorKind::ConnectionAborted
+                | std::io::ErrorKind::NotConnected
+                | std::io::ErrorKind::BrokenPipe
+                | std::io::ErrorKind::UnexpectedEof
+        ))
+    }
+    // 服务端在 host:transport 阶段回 "closed"（子进程打印 error: closed）表示设备传输刚断开或尚未就绪，
+    // 此时服务还没打开，命令没有送达设备
+    pub fn is_transport_closed(err: &AdbError) -> bool {
+        match err {
+            AdbError::Protocol(msg) | AdbError::CommandFailed(msg) => {
+                let msg = msg.trim().to_lowercase();
+                msg == "closed" || msg.ends_with("error: closed")
+            }
+            _ => false,
+        }
+    }
+    pub fn is_transient_message(stderr: &str) -> bool {
+        let err = AdbError::classify(stderr);
+        Self::is_transient(&err) || Self::is_transport_closed(&err)
+    }
+}
+#[derive(Debug, Clone)]
+pub struct CallOptions {
+    pub timeout: Duration,
+    pub retry: RetryPolicy,
+    pub cancel: CancellationToken,
+}
+impl Default for CallOptions {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_secs(30),
+            retry: RetryPolicy::default(),
+            cancel: CancellationToken::new(),
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn closed_transport_is_retried_before_the_service() {
+        assert!(RetryPolicy::is_transient_message("adb: error: closed\n"));
+        assert!(RetryPolicy::is_transient_message("error: closed"));
+        assert!(RetryPolicy::is_transport_closed(&AdbError::classify("closed")));
+        assert!(RetryPolicy::is_transient_message("error: device offline"));
+    }
+    #[test]
+    fn failures_after_the_service_are_not_retried() {
+        assert!(!RetryPolicy::is_transient_message("error: protocol fault (couldn't read status)"));
+        assert!(!RetryPolicy::is_transient_message("connection reset by peer"));
+        assert!(!RetryPolicy::is_transient_message("ls: /nope: No such file or directory"));
+        assert!(!RetryPolicy::is_transport_closed(&AdbError::Protocol("stream closed early".into())));
+    }
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.backoff(1), Duration::from_millis(200));
+        assert_eq!(policy.backoff(2), Duration::from_millis(400));
+        assert_eq!(policy.backoff(10), Duration::from_secs(5));
+    }
+}
@@ -0,0 +1,195 @@
+﻿use serde::Serialize;
+use std::collections::HashMap;
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Properties {
+    values: HashMap<String, String>,
+}
+impl Properties {
+    pub fn parse(output: &str) -> Self {
+        Self {
+            values: parse_getprop(output),
+        }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+    pub fn raw(&self, key: &str) -> Option<&str> {
+        self.values.get(key).map(String::as_str)
+    }
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.raw(key).map(str::trim).filter(|v| !v.is_empty())
+    }
+    pub fn string(&self, key: &str) -> String {
+        self.get(key).unwrap_or_default().to_string()
+    }
+    pub fn first(&self, keys: &[&str]) -> Option<&str> {
+        keys.iter().find_map(|k| self.get(k))
+    }
+    pub fn bool(&self, key: &str) -> Option<bool> {
+        parse_bool(self.get(key)?)
+    }
+    pub fn int(&self, key: &str) -> Option<i64> {
+        self.get(key)?.parse().ok()
+    }
+    pub fn version(&self, key: &str) -> Option<Version> {
+        Version::parse(self.get(key)?)
+    }
+    pub fn sdk(&self) -> Option<u32> {
+        self.int("ro.build.version.sdk")?.try_into().ok()
+    }
+    pub fn verified_boot_state(&self) -> Option<VerifiedBootState> {
+        self.get("ro.boot.verifiedbootstate").map(VerifiedBootState::parse)
+    }
+    pub fn keys_sorted(&self) -> Vec<&str> {
+        let mut keys: Vec<&str> = self.values.keys().map(String::as_str).collect();
+        keys.sort_unstable();
+        keys
+    }
+}
+pub fn parse_bool(value: &str) -> Option<bool> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "1" | "true" | "y" | "yes" | "on" => Some(true),
+        "0" | "false" | "n" | "no" | "off" => Some(false),
+        _ => None,
+    }
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub struct Version {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+impl Version {
+    pub fn parse(s: &str) -> Option<Self> {
+        let mut parts = s.trim().split(['.', '-', '_', ' ']).map(|p| {
+            let digits: String = p.chars().take_while(|c| c.is_ascii_digit()).collect();
+            digits.parse::<u32>().ok()
+        });
+        let major = parts.next()??;
+        let minor = parts.next().flatten().unwrap_or(0);
+        let patch = parts.next().flatten().unwrap_or(0);
+        Some(Self { major, minor, patch })
+    }
+}
+impl std::fmt::Display for Version {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VerifiedBootState {
+    Green,
+    Yellow,
+    Orange,
+    Red,
+    Other(String),
+}
+impl VerifiedBootState {
+    pub fn parse(s: &str) -> Self {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "green" => Self::Green,
+            "yellow" => Self::Yellow,
+            "orange" => Self::Orange,
+            "red" => Self::Red,
+            _ => Self::Other(s.trim().to_string()),
+        }
+    }
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Green => "green",
+            Self::Yellow => "yellow",
+            Self::Orange => "orange",
+            Self::Red => "red",
+            Self::Other(s) => s,
+        }
+    }
+}
+// 每条记录以行首的 `[属性名]: [` 开始，直到下一条记录前的内容都属于该值，值可以跨行或包含 `]: [`
+pub fn parse_getprop(output: &str) -> HashMap<String, String> {
+    let mut props = HashMap::new();
+    let mut current: Option<(String, String)> = None;
+    for line in output.split('\n') {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        if let Some((key, rest)) = record_start(line) {
+            if let Some((k, v)) = current.take() {
+                props.insert(k, close_value(v));
+            }
+            current = Some((key.to_string(), rest.to_string()));
+        } else if let Some((_, value)) = current.as_mut() {
+            value.push('\n');
+            value.push_str(line);
+        }
+    }
+    if let Some((k, v)) = current {
+        props.insert(k, close_value(v));
+    }
+    props
+}
+fn record_start(line: &str) -> Option<(&str, &str)> {
+    let rest = line.strip_prefix('[')?;
+    let end = rest.find("]: [")?;
+    let key = &rest[..end];
+    let valid = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-' | ':' | '@' | '+'));
+    valid.then(|| (key, &rest[end + 4..]))
+}
+fn close_value(value: String) -> String {
+    let trimmed = value.trim_end_matches(['\n', '\r', ' ']);
+    trimmed.strip_suffix(']').unwrap_or(trimmed).to_string()
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn multiline_values_and_embedded_separators_are_kept() {
+        let output = "[ro.build.version.sdk]: [34]\r\n\
+                      [ro.test.banner]: [line one\n\
+                      [not a record\n\
+                      line two]\n\
+                      [ro.test.tricky]: [a]: [b]\n\
+                      [ro.test.empty]: []\n\
+                      [ro.test.last]: [x]";
+        let props = Properties::parse(output);
+        assert_eq!(props.get("ro.build.version.sdk"), Some("34"));
+        assert_eq!(props.get("ro.test.banner"), Some("line one\n[not a record\nline two"));
+        assert_eq!(props.get("ro.test.tricky"), Some("a]: [b"));
+        assert_eq!(props.raw("ro.test.empty"), Some(""));
+        assert_eq!(props.get("ro.test.empty"), None);
+        assert_eq!(props.get("ro.test.last"), Some("x"));
+        assert_eq!(props.keys_sorted().len(), 5);
+        assert!(Properties::parse("").is_empty());
+    }
+    #[test]
+    fn typed_accessors_parse_or_return_none() {
+        let props = Properties::parse(
+            "[ro.build.version.sdk]: [ 34 ]\n\
+             [ro.build.version.release]: [14]\n\
+             [ro.build.version.security_patch]: [2024-05-05]\n\
+             [ro.debuggable]: [0]\n\
+             [ro.secure]: [yes]\n\
+             [ro.boot.verifiedbootstate]: [ORANGE]\n\
+             [ro.bad.int]: [12abc]\n\
+             [ro.build.fingerprint]: [google/panther/panther:14/UQ1A.240105.004/11206848:user/release-keys]\n",
+        );
+        assert_eq!(props.sdk(), Some(34));
+        assert_eq!(props.int("ro.bad.int"), None);
+        assert_eq!(props.int("ro.missing"), None);
+        assert_eq!(props.bool("ro.debuggable"), Some(false));
+        assert_eq!(props.bool("ro.secure"), Some(true));
+        assert_eq!(props.bool("ro.build.version.release"), None);
+        assert_eq!(props.version("ro.build.version.release"), Some(Version { major: 14, minor: 0, patch: 0 }));
+        let patch = props.version("ro.build.version.security_patch").unwrap();
+        assert_eq!(patch, Version { major: 2024, minor: 5, patch: 5 });
+        assert!(patch > Version::parse("2023.12.1").unwrap());
+        assert_eq!(Version::parse("v1.2"), None);
+        assert_eq!(Version::parse("5.10.198-android14-11").unwrap().to_string(), "5.10.198");
+        let state = props.verified_boot_state().unwrap();
+        assert_eq!(state, VerifiedBootState::Orange);
+        assert_eq!(state.as_str(), "orange");
+        assert_eq!(VerifiedBootState::parse(" unknown ").as_str(), "unknown");
+        assert_eq!(props.first(&["ro.missing", "ro.build.version.release"]), Some("14"));
+    }
+}
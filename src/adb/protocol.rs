@@ -0,0 +1,377 @@
+﻿use super::transport::ShellOutput;
+use crate::core::{AdbError, Result};
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+pub const DEFAULT_SERVER_PORT: u16 = 5037;
+const CONNECT_TIMEOUT_SECS: u64 = 3;
+pub const EXIT_SENTINEL: &str = "__ANDROIDCHECKER_EXIT__:";
+const SHELL_V2_STDOUT: u8 = 1;
+const SHELL_V2_STDERR: u8 = 2;
+const SHELL_V2_EXIT: u8 = 3;
+pub fn default_server_addr() -> SocketAddr {
+    let host = std::env::var("ANDROID_ADB_SERVER_ADDRESS").unwrap_or_else(|_| "127.0.0.1".to_string());
+    let port = std::env::var("ANDROID_ADB_SERVER_PORT")
+        .ok()
+        .and_then(|p| p.trim().parse::<u16>().ok())
+        .unwrap_or(DEFAULT_SERVER_PORT);
+    format!("{}:{}", host, port)
+        .parse()
+        .unwrap_or_else(|_| SocketAddr::from(([127, 0, 0, 1], port)))
+}
+pub struct AdbConnection {
+    stream: TcpStream,
+}
+impl AdbConnection {
+    pub async fn connect(addr: SocketAddr) -> Result<Self> {
+        let stream = timeout(Duration::from_secs(CONNECT_TIMEOUT_SECS), TcpStream::connect(addr))
+            .await
+            .map_err(|_| AdbError::Timeout {
+                command: format!("connect {}", addr),
+                secs: CONNECT_TIMEOUT_SECS,
+            })??;
+        stream.set_nodelay(true)?;
+        Ok(Self { stream })
+    }
+    pub async fn send(&mut self, request: &str) -> Result<()> {
+        // 长度前缀固定为 4 位十六进制
+        if request.len() > 0xffff {
+            return Err(AdbError::Protocol(format!("request too long: {} bytes", request.len())));
+        }
+        let payload = format!("{:04x}{}", request.len(), request);
+        self.stream.write_all(payload.as_bytes()).await?;
+        Ok(())
+    }
+    pub async fn read_status(&mut self) -> Result<()> {
+        let mut status = [0u8; 4];
+        self.stream.read_exact(&mut status).await?;
+        match &status {
+            b"OKAY" => Ok(()),
+            b"FAIL" => {
+                let msg = self.read_length_prefixed().await.unwrap_or_default();
+                Err(AdbError::classify(&msg))
+            }
+            other => Err(AdbError::Parse(format!(
+                "unexpected adb server status: {}",
+                String::from_utf8_lossy(other)
+            ))),
+        }
+    }
+    pub async fn request(&mut self, request: &str) -> Result<()> {
+        self.send(request).await?;
+        self.read_status().await
+    }
+    pub async fn read_length_prefixed(&mut self) -> Result<String> {
+        let mut len_buf = [0u8; 4];
+        self.stream.read_exact(&mut len_buf).await?;
+        let len = Self::parse_hex_len(&len_buf)?;
+        let mut body = vec![0u8; len];
+        self.stream.read_exact(&mut body).await?;
+        Ok(String::from_utf8_lossy(&body).to_string())
+    }
+    pub async fn read_to_end(&mut self) -> Result<Vec<u8>> {
+        let mut buf = Vec::new();
+        self.stream.read_to_end(&mut buf).await?;
+        Ok(buf)
+    }
+    pub async fn read_shell_v2(&mut self) -> Result<ShellOutput> {
+        let mut stdout = Vec::new();
+        let mut stderr = Vec::new();
+        loop {
+            let mut header = [0u8; 5];
+            if let Err(e) = self.stream.read_exact(&mut header).await {
+                return Err(match e.kind() {
+                    std::io::ErrorKind::UnexpectedEof => {
+                        AdbError::Protocol("shell v2 stream closed without exit status".to_string())
+                    }
+                    _ => e.into(),
+                });
+            }
+            let len = u32::from_le_bytes([header[1], header[2], header[3], header[4]]) as usize;
+            let mut data = vec![0u8; len];
+            self.stream.read_exact(&mut data).await?;
+            match header[0] {
+                SHELL_V2_STDOUT => stdout.extend_from_slice(&data),
+                SHELL_V2_STDERR => stderr.extend_from_slice(&data),
+                SHELL_V2_EXIT => {
+                    return Ok(ShellOutput {
+                        stdout: String::from_utf8_lossy(&stdout).to_string(),
+                        stderr: String::from_utf8_lossy(&stderr).to_string(),
+                        exit_code: data.first().copied().unwrap_or(0) as i32,
+                    });
+                }
+                _ => {}
+            }
+        }
+    }
+    pub async fn read_sentinel_output(&mut self) -> Result<ShellOutput> {
+        let out = self.read_to_end().await?;
+        let (stdout, exit_code) = split_exit_sentinel(&String::from_utf8_lossy(&out));
+        let exit_code = exit_code
+            .ok_or_else(|| AdbError::Protocol("shell output ended without exit status".to_string()))?;
+        Ok(ShellOutput {
+            stdout,
+            stderr: String::new(),
+            exit_code,
+        })
+    }
+    pub fn into_stream(self) -> TcpStream {
+        self.stream
+    }
+    fn parse_hex_len(buf: &[u8; 4]) -> Result<usize> {
+        let s = std::str::from_utf8(buf).map_err(|e| AdbError::Parse(e.to_string()))?;
+        usize::from_str_radix(s, 16).map_err(|_| AdbError::Parse(format!("invalid length prefix: {}", s)))
+    }
+}
+#[derive(Debug, Clone, Copy)]
+pub struct AdbServer {
+    addr: SocketAddr,
+}
+impl AdbServer {
+    pub fn new(addr: SocketAddr) -> Self {
+        Self { addr }
+    }
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+    pub async fn connect(&self) -> Result<AdbConnection> {
+        AdbConnection::connect(self.addr).await
+    }
+    pub async fn version(&self) -> Result<u32> {
+        let mut conn = self.connect().await?;
+        conn.request("host:version").await?;
+        let body = conn.read_length_prefixed().await?;
+        u32::from_str_radix(body.trim(), 16)
+            .map_err(|_| AdbError::Parse(format!("invalid server version: {}", body)))
+    }
+    pub async fn host_query(&self, service: &str) -> Result<String> {
+        let mut conn = self.connect().await?;
+        conn.request(&format!("host:{}", service)).await?;
+        conn.read_length_prefixed().await
+    }
+    pub async fn devices_long(&self) -> Result<String> {
+        self.host_query("devices-l").await
+    }
+    pub async fn features(&self, serial: &str) -> Result<Vec<String>> {
+        let mut conn = self.connect().await?;
+        conn.request(&format!("host-serial:{}:features", serial)).await?;
+        let body = conn.read_length_prefixed().await?;
+        Ok(body
+            .split(',')
+            .map(|f| f.trim().to_string())
+            .filter(|f| !f.is_empty())
+            .collect())
+    }
+    pub async fn track_devices(&self) -> Result<AdbConnection> {
+        let mut conn = self.connect().await?;
+        conn.request("host:track-devices").await?;
+        Ok(conn)
+    }
+    pub async fn open_transport(&self, serial: &str) -> Result<AdbConnection> {
+        let mut conn = self.connect().await?;
+        conn.request(&format!("host:transport:{}", serial)).await?;
+        Ok(conn)
+    }
+    pub async fn open_service(&self, serial: &str, service: &str) -> Result<AdbConnection> {
+        let mut conn = self.open_transport(serial).await?;
+        conn.request(service).await?;
+        Ok(conn)
+    }
+}
+pub fn join_command(cmd: &[&str]) -> String {
+    cmd.join(" ")
+}
+pub fn shell_v2_service(cmd: &[&str]) -> String {
+    format!("shell,v2,raw:{}", join_command(cmd))
+}
+pub fn shell_service(cmd: &[&str]) -> String {
+    format!("shell:{}", join_command(cmd))
+}
+pub fn sentinel_service(cmd: &[&str]) -> String {
+    shell_service(&[&sentinel_command(cmd)])
+}
+pub fn exec_service(cmd: &[&str]) -> String {
+    format!("exec:{}", join_command(cmd))
+}
+pub fn shell_quote(arg: &str) -> String {
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
+pub fn sentinel_command(cmd: &[&str]) -> String {
+    format!("{} ; echo {}$?", join_command(cmd), EXIT_SENTINEL)
+}
+pub fn split_exit_sentinel(output: &str) -> (String, Option<i32>) {
+    match output.rfind(EXIT_SENTINEL) {
+        Some(idx) => {
+            let code = output[idx + EXIT_SENTINEL.len()..].trim().parse::<i32>().ok();
+            (output[..idx].to_string(), code)
+        }
+        None => (output.to_string(), None),
+    }
+}
+#[cfg(test)]
+pub mod test_server {
+    use std::future::Future;
+    use std::net::SocketAddr;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+    // 本地回环上的假 adb server，每个连接交给 handler 按脚本应答
+    pub async fn spawn<F, Fut>(handler: F) -> SocketAddr
+    where
+        F: Fn(TcpStream) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(handler(stream));
+            }
+        });
+        addr
+    }
+    pub async fn read_request(stream: &mut TcpStream) -> String {
+        let mut len = [0u8; 4];
+        stream.read_exact(&mut len).await.unwrap();
+        let len = usize::from_str_radix(std::str::from_utf8(&len).unwrap(), 16).unwrap();
+        let mut body = vec![0u8; len];
+        stream.read_exact(&mut body).await.unwrap();
+        String::from_utf8(body).unwrap()
+    }
+    pub async fn okay(stream: &mut TcpStream) {
+        stream.write_all(b"OKAY").await.unwrap();
+    }
+    pub async fn fail(stream: &mut TcpStream, msg: &str) {
+        stream.write_all(format!("FAIL{:04x}{}", msg.len(), msg).as_bytes()).await.unwrap();
+    }
+    pub async fn length_prefixed(stream: &mut TcpStream, body: &str) {
+        stream.write_all(format!("{:04x}{}", body.len(), body).as_bytes()).await.unwrap();
+    }
+    pub async fn shell_v2_packet(stream: &mut TcpStream, id: u8, data: &[u8]) {
+        let mut packet = vec![id];
+        packet.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        packet.extend_from_slice(data);
+        stream.write_all(&packet).await.unwrap();
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::test_server::{self, fail, length_prefixed, okay, read_request, shell_v2_packet};
+    use super::*;
+    #[tokio::test]
+    async fn version_uses_length_prefix_and_okay() {
+        let addr = test_server::spawn(|mut stream| async move {
+            assert_eq!(read_request(&mut stream).await, "host:version");
+            okay(&mut stream).await;
+            length_prefixed(&mut stream, "0029").await;
+        })
+        .await;
+        assert_eq!(AdbServer::new(addr).version().await.unwrap(), 0x29);
+    }
+    #[tokio::test]
+    async fn fail_status_is_classified() {
+        let addr = test_server::spawn(|mut stream| async move {
+            assert_eq!(read_request(&mut stream).await, "host:transport:ZZZ");
+            fail(&mut stream, "device 'ZZZ' not found").await;
+        })
+        .await;
+        let err = AdbServer::new(addr).open_transport("ZZZ").await.err().unwrap();
+        assert!(matches!(err, AdbError::DeviceNotFound(_)), "{:?}", err);
+    }
+    #[tokio::test]
+    async fn unexpected_status_is_a_parse_error() {
+        let addr = test_server::spawn(|mut stream| async move {
+            read_request(&mut stream).await;
+            stream.write_all(b"WHAT").await.unwrap();
+        })
+        .await;
+        let err = AdbServer::new(addr).host_query("devices-l").await.err().unwrap();
+        assert!(matches!(err, AdbError::Parse(_)), "{:?}", err);
+    }
+    #[tokio::test]
+    async fn oversized_request_is_rejected() {
+        let addr = test_server::spawn(|_| async {}).await;
+        let mut conn = AdbConnection::connect(addr).await.unwrap();
+        let err = conn.send(&"x".repeat(0x10000)).await.err().unwrap();
+        assert!(matches!(err, AdbError::Protocol(_)), "{:?}", err);
+        assert!(conn.send(&"x".repeat(0xffff)).await.is_ok());
+    }
+    #[tokio::test]
+    async fn shell_v2_demuxes_streams_and_exit_code() {
+        let addr = test_server::spawn(|mut stream| async move {
+            assert_eq!(read_request(&mut stream).await, "host:transport:AAA");
+            okay(&mut stream).await;
+            assert_eq!(read_request(&mut stream).await, "shell,v2,raw:ls /nope");
+            okay(&mut stream).await;
+            shell_v2_packet(&mut stream, SHELL_V2_STDOUT, b"part one\n").await;
+            shell_v2_packet(&mut stream, SHELL_V2_STDERR, b"ls: /nope: No such file or directory\n").await;
+            shell_v2_packet(&mut stream, SHELL_V2_STDOUT, b"part two\n").await;
+            shell_v2_packet(&mut stream, SHELL_V2_EXIT, &[1]).await;
+        })
+        .await;
+        let mut conn = AdbServer::new(addr)
+            .open_service("AAA", &shell_v2_service(&["ls", "/nope"]))
+            .await
+            .unwrap();
+        let out = conn.read_shell_v2().await.unwrap();
+        assert_eq!(out.stdout, "part one\npart two\n");
+        assert_eq!(out.stderr, "ls: /nope: No such file or directory\n");
+        assert_eq!(out.exit_code, 1);
+    }
+    #[tokio::test]
+    async fn shell_v2_without_exit_packet_is_a_protocol_error() {
+        let addr = test_server::spawn(|mut stream| async move {
+            read_request(&mut stream).await;
+            okay(&mut stream).await;
+            read_request(&mut stream).await;
+            okay(&mut stream).await;
+            shell_v2_packet(&mut stream, SHELL_V2_STDOUT, b"partial").await;
+        })
+        .await;
+        let mut conn = AdbServer::new(addr).open_service("AAA", &shell_v2_service(&["id"])).await.unwrap();
+        let err = conn.read_shell_v2().await.err().unwrap();
+        assert!(matches!(err, AdbError::Protocol(_)), "{:?}", err);
+    }
+    #[tokio::test]
+    async fn sentinel_fallback_recovers_exit_code() {
+        let addr = test_server::spawn(|mut stream| async move {
+            read_request(&mut stream).await;
+            okay(&mut stream).await;
+            let service = read_request(&mut stream).await;
+            assert_eq!(service, format!("shell:id ; echo {}$?", EXIT_SENTINEL));
+            okay(&mut stream).await;
+            stream
+                .write_all(format!("uid=2000(shell)\n{}0\n", EXIT_SENTINEL).as_bytes())
+                .await
+                .unwrap();
+        })
+        .await;
+        let mut conn = AdbServer::new(addr).open_service("AAA", &sentinel_service(&["id"])).await.unwrap();
+        let out = conn.read_sentinel_output().await.unwrap();
+        assert_eq!(out.stdout, "uid=2000(shell)\n");
+        assert_eq!(out.exit_code, 0);
+    }
+    #[tokio::test]
+    async fn sentinel_missing_is_a_protocol_error() {
+        let addr = test_server::spawn(|mut stream| async move {
+            read_request(&mut stream).await;
+            okay(&mut stream).await;
+            read_request(&mut stream).await;
+            okay(&mut stream).await;
+            stream.write_all(b"truncated output").await.unwrap();
+        })
+        .await;
+        let mut conn = AdbServer::new(addr).open_service("AAA", &sentinel_service(&["id"])).await.unwrap();
+        let err = conn.read_sentinel_output().await.err().unwrap();
+        assert!(matches!(err, AdbError::Protocol(_)), "{:?}", err);
+    }
+    #[test]
+    fn split_exit_sentinel_takes_last_marker() {
+        let text = format!("a{}1\nb\n{}127\n", EXIT_SENTINEL, EXIT_SENTINEL);
+        let (stdout, code) = split_exit_sentinel(&text);
+        assert_eq!(stdout, format!("a{}1\nb\n", EXIT_SENTINEL));
+        assert_eq!(code, Some(127));
+        assert_eq!(split_exit_sentinel("plain"), ("plain".to_string(), None));
+    }
+}
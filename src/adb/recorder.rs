@@ -0,0 +1,399 @@
+﻿use super::protocol::AdbConnection;
+use super::sync::{ProgressFn, SyncOptions, TransferReport};
+use super::transport::{command_key, exec_out_key, shell_key, AdbTransport, SharedTransport, ShellOutput};
+use crate::core::{AdbError, Result};
+use async_trait::async_trait;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TranscriptEntry {
+    pub command: String,
+    pub method: String,
+    #[serde(default)]
+    pub stdout: String,
+    // exec_out 的原始字节，截图、bu backup 等二进制输出不能经过 String 保存
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub stdout_base64: Option<String>,
+    #[serde(default)]
+    pub stderr: String,
+    #[serde(default)]
+    pub exit_code: i32,
+    #[serde(default)]
+    pub error: Option<String>,
+    pub offset_ms: u64,
+    pub duration_ms: u64,
+}
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TranscriptHeader {
+    pub version: String,
+    pub recorded_at: String,
+}
+#[derive(Debug, Clone, Default)]
+pub struct Transcript {
+    pub entries: Vec<TranscriptEntry>,
+}
+impl Transcript {
+    // JSON Lines：首行为文件头，其后每条命令一行
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)?;
+        let mut lines = content.lines().filter(|l| !l.trim().is_empty());
+        if let Some(header) = lines.next() {
+            serde_json::from_str::<TranscriptHeader>(header)?;
+        }
+        let entries = lines.map(serde_json::from_str).collect::<std::result::Result<Vec<_>, _>>()?;
+        Ok(Self { entries })
+    }
+}
+pub struct RecordingTransport {
+    inner: SharedTransport,
+    path: PathBuf,
+    started: Instant,
+    file: Mutex<File>,
+}
+impl RecordingTransport {
+    pub fn new(inner: SharedTransport, path: &Path) -> Result<Self> {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        let header = TranscriptHeader {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            recorded_at: chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+        };
+        let recorder = Self {
+            inner,
+            path: path.to_path_buf(),
+            started: Instant::now(),
+            file: Mutex::new(File::create(path)?),
+        };
+        recorder.append(&serde_json::to_string(&header)?)?;
+        Ok(recorder)
+    }
+    fn record(&self, command: String, method: &str, t0: Instant, result: &Result<(bool, String, String)>) -> Result<()> {
+        let as_output = result
+            .as_ref()
+            .map(|(ok, out, err)| ShellOutput {
+                stdout: out.clone(),
+                stderr: err.clone(),
+                exit_code: if *ok { 0 } else { 1 },
+            })
+            .map_err(clone_error);
+        self.record_output(command, method, t0, &as_output)
+    }
+    fn record_transfer(&self, command: String, method: &str, t0: Instant, result: &Result<TransferReport>) -> Result<()> {
+        let as_try = result
+            .as_ref()
+            .map(|r| (r.failures.is_empty(), r.summary(), String::new()))
+            .map_err(clone_error);
+        self.record(command, method, t0, &as_try)
+    }
+    fn record_output(&self, command: String, method: &str, t0: Instant, result: &Result<ShellOutput>) -> Result<()> {
+        let mut entry = self.entry(command, method, t0);
+        match result {
+            Ok(out) => {
+                entry.stdout = out.stdout.clone();
+                entry.stderr = out.stderr.clone();
+                entry.exit_code = out.exit_code;
+            }
+            Err(e) => {
+                entry.exit_code = -1;
+                entry.error = Some(e.to_string());
+            }
+        }
+        self.write(&entry)
+    }
+    fn record_bytes(&self, command: String, t0: Instant, result: &Result<Vec<u8>>) -> Result<()> {
+        let mut entry = self.entry(command, "exec_out", t0);
+        match result {
+            Ok(bytes) => entry.stdout_base64 = Some(BASE64.encode(bytes)),
+            Err(e) => {
+                entry.exit_code = -1;
+                entry.error = Some(e.to_string());
+            }
+        }
+        self.write(&entry)
+    }
+    fn entry(&self, command: String, method: &str, t0: Instant) -> TranscriptEntry {
+        TranscriptEntry {
+            command,
+            method: method.to_string(),
+            stdout: String::new(),
+            stdout_base64: None,
+            stderr: String::new(),
+            exit_code: 0,
+            error: None,
+            offset_ms: t0.duration_since(self.started).as_millis() as u64,
+            duration_ms: t0.elapsed().as_millis() as u64,
+        }
+    }
+    fn write(&self, entry: &TranscriptEntry) -> Result<()> {
+        self.append(&serde_json::to_string(entry)?)
+    }
+    // 每条记录追加一行，不再整体重写文件；写入失败直接返回给调用方
+    fn append(&self, line: &str) -> Result<()> {
+        let mut file = self.file.lock().map_err(|e| AdbError::CommandFailed(e.to_string()))?;
+        writeln!(file, "{}", line).map_err(|e| {
+            AdbError::Io(std::io::Error::new(
+                e.kind(),
+                format!("failed to write transcript {}: {}", self.path.display(), e),
+            ))
+        })
+    }
+}
+#[async_trait]
+impl AdbTransport for RecordingTransport {
+    async fn try_execute(&self, args: &[&str]) -> Result<(bool, String, String)> {
+        let t0 = Instant::now();
+        let result = self.inner.try_execute(args).await;
+        self.record(command_key(args), "try_execute", t0, &result)?;
+        result
+    }
+    async fn try_shell(&self, serial: &str, cmd: &[&str]) -> Result<(bool, String, String)> {
+        let t0 = Instant::now();
+        let result = self.inner.try_shell(serial, cmd).await;
+        self.record(shell_key(serial, cmd), "try_shell", t0, &result)?;
+        result
+    }
+    async fn shell_output(&self, serial: &str, cmd: &[&str]) -> Result<ShellOutput> {
+        let t0 = Instant::now();
+        let result = self.inner.shell_output(serial, cmd).await;
+        self.record_output(shell_key(serial, cmd), "shell_output", t0, &result)?;
+        result
+    }
+    async fn execute(&self, args: &[&str]) -> Result<(String, String)> {
+        let t0 = Instant::now();
+        let result = self.inner.execute(args).await;
+        let as_try = result.as_ref().map(|(o, e)| (true, o.clone(), e.clone())).map_err(clone_error);
+        self.record(command_key(args), "execute", t0, &as_try)?;
+        result
+    }
+    async fn shell(&self, serial: &str, cmd: &[&str]) -> Result<String> {
+        let t0 = Instant::now();
+        let result = self.inner.shell(serial, cmd).await;
+        let as_try = result.as_ref().map(|o| (true, o.clone(), String::new())).map_err(clone_error);
+        self.record(shell_key(serial, cmd), "shell", t0, &as_try)?;
+        result
+    }
+    async fn exec_out(&self, serial: &str, cmd: &[&str]) -> Result<Vec<u8>> {
+        let t0 = Instant::now();
+        let result = self.inner.exec_out(serial, cmd).await;
+        self.record_bytes(exec_out_key(serial, cmd), t0, &result)?;
+        result
+    }
+    async fn pull(
+        &self,
+        serial: &str,
+        remote: &str,
+        local: &Path,
+        options: &SyncOptions,
+        progress: ProgressFn<'_>,
+    ) -> Result<TransferReport> {
+        let t0 = Instant::now();
+        let result = self.inner.pull(serial, remote, local, options, progress).await;
+        let source = format!("{}/.", remote.trim_end_matches('/'));
+        let key = command_key(&["-s", serial, "pull", &source, &local.to_string_lossy()]);
+        self.record_transfer(key, "pull", t0, &result)?;
+        result
+    }
+    async fn push(
+        &self,
+        serial: &str,
+        local: &Path,
+        remote: &str,
+        options: &SyncOptions,
+        progress: ProgressFn<'_>,
+    ) -> Result<TransferReport> {
+        let t0 = Instant::now();
+        let result = self.inner.push(serial, local, remote, options, progress).await;
+        let source = format!("{}/.", local.to_string_lossy().trim_end_matches(['/', '\\']));
+        let key = command_key(&["-s", serial, "push", &source, remote]);
+        self.record_transfer(key, "push", t0, &result)?;
+        result
+    }
+    async fn execute_with_timeout(&self, args: &[&str], timeout: Duration) -> Result<(String, String)> {
+        let t0 = Instant::now();
+        let result = self.inner.execute_with_timeout(args, timeout).await;
+        let as_try = result.as_ref().map(|(o, e)| (true, o.clone(), e.clone())).map_err(clone_error);
+        self.record(command_key(args), "execute", t0, &as_try)?;
+        result
+    }
+    async fn try_execute_with_timeout(&self, args: &[&str], timeout: Duration) -> Result<(bool, String, String)> {
+        let t0 = Instant::now();
+        let result = self.inner.try_execute_with_timeout(args, timeout).await;
+        self.record(command_key(args), "try_execute", t0, &result)?;
+        result
+    }
+    async fn devices_long(&self) -> Result<String> {
+        let t0 = Instant::now();
+        let result = self.inner.devices_long().await;
+        let as_try = result.as_ref().map(|o| (true, o.clone(), String::new())).map_err(clone_error);
+        self.record(command_key(&["devices", "-l"]), "devices_long", t0, &as_try)?;
+        result
+    }
+    // 设备事件流直接交给内层，录制时 watcher 不退回轮询 devices -l，转录里也就没有这些轮询
+    async fn track_devices(&self) -> Result<Option<AdbConnection>> {
+        self.inner.track_devices().await
+    }
+}
+fn clone_error(e: &AdbError) -> AdbError {
+    AdbError::classify(&e.to_string())
+}
+pub struct ReplayTransport {
+    entries: HashMap<String, Vec<TranscriptEntry>>,
+    cursors: Mutex<HashMap<String, usize>>,
+    honor_timing: bool,
+}
+impl ReplayTransport {
+    pub fn new(transcript: Transcript) -> Self {
+        let mut entries: HashMap<String, Vec<TranscriptEntry>> = HashMap::new();
+        for entry in transcript.entries {
+            entries.entry(entry.command.clone()).or_default().push(entry);
+        }
+        Self {
+            entries,
+            cursors: Mutex::new(HashMap::new()),
+            honor_timing: false,
+        }
+    }
+    pub fn from_file(path: &Path) -> Result<Self> {
+        Ok(Self::new(Transcript::load(path)?))
+    }
+    pub fn with_timing(mut self, honor_timing: bool) -> Self {
+        self.honor_timing = honor_timing;
+        self
+    }
+    async fn next(&self, key: String) -> Result<TranscriptEntry> {
+        let list = self
+            .entries
+            .get(&key)
+            .ok_or_else(|| AdbError::CommandFailed(format!("command not in transcript: `{}`", key)))?;
+        let entry = {
+            let mut cursors = self.cursors.lock().map_err(|e| AdbError::CommandFailed(e.to_string()))?;
+            let cursor = cursors.entry(key).or_insert(0);
+            let entry = list[(*cursor).min(list.len() - 1)].clone();
+            *cursor += 1;
+            entry
+        };
+        if self.honor_timing && entry.duration_ms > 0 {
+            tokio::time::sleep(Duration::from_millis(entry.duration_ms)).await;
+        }
+        Ok(entry)
+    }
+    async fn replay(&self, key: String) -> Result<(bool, String, String)> {
+        let out = self.replay_output(key).await?;
+        Ok((out.success(), out.stdout, out.stderr))
+    }
+    async fn replay_output(&self, key: String) -> Result<ShellOutput> {
+        let entry = self.next(key).await?;
+        if let Some(err) = entry.error {
+            return Err(AdbError::classify(&err));
+        }
+        Ok(ShellOutput {
+            stdout: entry.stdout,
+            stderr: entry.stderr,
+            exit_code: entry.exit_code,
+        })
+    }
+}
+#[async_trait]
+impl AdbTransport for ReplayTransport {
+    async fn try_execute(&self, args: &[&str]) -> Result<(bool, String, String)> {
+        self.replay(command_key(args)).await
+    }
+    async fn try_shell(&self, serial: &str, cmd: &[&str]) -> Result<(bool, String, String)> {
+        self.replay(shell_key(serial, cmd)).await
+    }
+    async fn shell(&self, serial: &str, cmd: &[&str]) -> Result<String> {
+        self.replay_output(shell_key(serial, cmd)).await?.into_result()
+    }
+    async fn shell_output(&self, serial: &str, cmd: &[&str]) -> Result<ShellOutput> {
+        self.replay_output(shell_key(serial, cmd)).await
+    }
+    async fn exec_out(&self, serial: &str, cmd: &[&str]) -> Result<Vec<u8>> {
+        let entry = self.next(exec_out_key(serial, cmd)).await?;
+        if let Some(err) = entry.error {
+            return Err(AdbError::classify(&err));
+        }
+        match entry.stdout_base64 {
+            Some(encoded) => BASE64
+                .decode(encoded)
+                .map_err(|e| AdbError::Parse(format!("transcript exec_out payload: {}", e))),
+            None => Ok(entry.stdout.into_bytes()),
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    const SCREENCAP: &[u8] = &[0x89, b'P', b'N', b'G', 0x00, 0xff, 0xfe, 0x0d, 0x0a];
+    struct BinaryDevice;
+    #[async_trait]
+    impl AdbTransport for BinaryDevice {
+        async fn try_execute(&self, _args: &[&str]) -> Result<(bool, String, String)> {
+            Ok((true, String::new(), String::new()))
+        }
+        async fn try_shell(&self, _serial: &str, _cmd: &[&str]) -> Result<(bool, String, String)> {
+            Ok((false, String::new(), "ls: /data: Permission denied".to_string()))
+        }
+        async fn exec_out(&self, _serial: &str, _cmd: &[&str]) -> Result<Vec<u8>> {
+            Ok(SCREENCAP.to_vec())
+        }
+    }
+    #[tokio::test]
+    async fn transcript_replays_binary_output_and_exit_codes() {
+        let path = std::env::temp_dir().join(format!("androidchecker-transcript-{}.jsonl", std::process::id()));
+        let recorder = RecordingTransport::new(Arc::new(BinaryDevice), &path).unwrap();
+        assert_eq!(recorder.exec_out("AAA", &["screencap", "-p"]).await.unwrap(), SCREENCAP);
+        assert_eq!(recorder.shell_output("AAA", &["ls", "/data"]).await.unwrap().exit_code, 1);
+        assert!(!recorder.try_shell("AAA", &["ls", "/data"]).await.unwrap().0);
+        drop(recorder);
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(content.lines().count(), 4);
+        let replay = ReplayTransport::from_file(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+        assert_eq!(replay.exec_out("AAA", &["screencap", "-p"]).await.unwrap(), SCREENCAP);
+        let err = replay.shell("AAA", &["ls", "/data"]).await.unwrap_err();
+        assert!(matches!(err, AdbError::PermissionDenied(_)), "{:?}", err);
+    }
+    struct TrackingServer(std::net::SocketAddr);
+    #[async_trait]
+    impl AdbTransport for TrackingServer {
+        async fn try_execute(&self, args: &[&str]) -> Result<(bool, String, String)> {
+            panic!("unexpected adb {:?}", args);
+        }
+        async fn try_shell(&self, _serial: &str, cmd: &[&str]) -> Result<(bool, String, String)> {
+            panic!("unexpected shell {:?}", cmd);
+        }
+        async fn exec_out(&self, _serial: &str, cmd: &[&str]) -> Result<Vec<u8>> {
+            panic!("unexpected exec-out {:?}", cmd);
+        }
+        async fn track_devices(&self) -> Result<Option<AdbConnection>> {
+            Ok(Some(crate::adb::protocol::AdbServer::new(self.0).track_devices().await?))
+        }
+    }
+    #[tokio::test]
+    async fn device_tracking_is_forwarded_and_not_recorded() {
+        use crate::adb::protocol::test_server::{self, length_prefixed, okay, read_request};
+        let addr = test_server::spawn(|mut stream| async move {
+            assert_eq!(read_request(&mut stream).await, "host:track-devices");
+            okay(&mut stream).await;
+            length_prefixed(&mut stream, "AAA\tdevice\n").await;
+        })
+        .await;
+        let path = std::env::temp_dir().join(format!("androidchecker-tracking-{}.jsonl", std::process::id()));
+        let recorder = RecordingTransport::new(Arc::new(TrackingServer(addr)), &path).unwrap();
+        let mut conn = recorder.track_devices().await.unwrap().expect("stream from inner transport");
+        assert_eq!(conn.read_length_prefixed().await.unwrap(), "AAA\tdevice\n");
+        drop(recorder);
+        let content = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+        assert_eq!(content.lines().count(), 1, "{}", content);
+    }
+}
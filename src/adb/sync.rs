@@ -0,0 +1,1030 @@
+﻿use super::protocol::{self, shell_quote, AdbServer};
+use crate::core::{AdbError, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+pub const SYNC_DATA_MAX: usize = 64 * 1024;
+pub const SYNC_MANIFEST: &str = ".androidchecker-sync.json";
+const S_IFMT: u32 = 0o170000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFREG: u32 = 0o100000;
+const S_IFLNK: u32 = 0o120000;
+// sync_stat_v2: id error dev ino mode nlink uid gid size atime mtime ctime
+const STAT_V2_LEN: usize = 72;
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RemoteStat {
+    pub mode: u32,
+    pub size: u64,
+    pub mtime: u64,
+}
+impl RemoteStat {
+    pub fn exists(&self) -> bool {
+        self.mode != 0
+    }
+    pub fn is_dir(&self) -> bool {
+        self.mode & S_IFMT == S_IFDIR
+    }
+    pub fn is_file(&self) -> bool {
+        self.mode & S_IFMT == S_IFREG
+    }
+    pub fn is_symlink(&self) -> bool {
+        self.mode & S_IFMT == S_IFLNK
+    }
+}
+#[derive(Debug, Clone)]
+pub struct RemoteEntry {
+    pub name: Vec<u8>,
+    pub stat: RemoteStat,
+}
+impl RemoteEntry {
+    pub fn name_lossy(&self) -> String {
+        String::from_utf8_lossy(&self.name).to_string()
+    }
+}
+// 没有 sync 连接时用 ls -1a 的原始输出列目录，只有名称没有元数据
+pub fn parse_ls(output: &[u8]) -> Vec<RemoteEntry> {
+    output
+        .split(|b| *b == b'\n')
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+        .filter(|name| !name.is_empty() && *name != b"." && *name != b"..")
+        .map(|name| RemoteEntry {
+            name: name.to_vec(),
+            stat: RemoteStat::default(),
+        })
+        .collect()
+}
+#[derive(Debug, Clone)]
+pub struct SyncOptions {
+    pub resume: bool,
+    pub verify: bool,
+    pub timeout: Duration,
+}
+impl Default for SyncOptions {
+    fn default() -> Self {
+        Self {
+            resume: true,
+            verify: false,
+            timeout: Duration::from_secs(6 * 60 * 60),
+        }
+    }
+}
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TransferProgress {
+    pub file: String,
+    pub file_bytes: u64,
+    pub file_total: u64,
+    pub files_done: usize,
+    pub files_total: usize,
+    pub bytes_done: u64,
+    pub bytes_total: u64,
+}
+pub type ProgressFn<'a> = &'a (dyn Fn(&TransferProgress) + Send + Sync);
+#[derive(Debug, Clone, Serialize)]
+pub struct TransferFailure {
+    pub path: String,
+    pub error: String,
+}
+impl TransferFailure {
+    fn checksum(path: String) -> Self {
+        Self {
+            path,
+            error: "SHA-256 校验不一致".to_string(),
+        }
+    }
+}
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct TransferReport {
+    pub transferred: Vec<String>,
+    pub skipped: Vec<String>,
+    pub failures: Vec<TransferFailure>,
+    pub verified: usize,
+    pub bytes: u64,
+}
+impl TransferReport {
+    pub fn whole(path: &str) -> Self {
+        Self {
+            transferred: vec![path.to_string()],
+            ..Self::default()
+        }
+    }
+    pub fn summary(&self) -> String {
+        format!(
+            "传输 {} 个文件 ({} 字节)，跳过 {} 个，失败 {} 个，校验 {} 个",
+            self.transferred.len(),
+            self.bytes,
+            self.skipped.len(),
+            self.failures.len(),
+            self.verified
+        )
+    }
+}
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ManifestEntry {
+    size: u64,
+    mtime: u64,
+    sha256: String,
+}
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct SyncManifest {
+    files: BTreeMap<String, ManifestEntry>,
+}
+impl SyncManifest {
+    fn load(root: &Path) -> Self {
+        std::fs::read_to_string(root.join(SYNC_MANIFEST))
+            .ok()
+            .and_then(|c| serde_json::from_str(&c).ok())
+            .unwrap_or_default()
+    }
+    fn save(&self, root: &Path) -> Result<()> {
+        std::fs::write(root.join(SYNC_MANIFEST), serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
+pub struct SyncConnection {
+    stream: TcpStream,
+    stat_v2: bool,
+    ls_v2: bool,
+}
+impl SyncConnection {
+    pub async fn open(server: &AdbServer, serial: &str) -> Result<Self> {
+        // 旧版 STAT/LIST 的大小和时间只有 32 位，设备支持时改用 STA2/LIS2
+        let features = server.features(serial).await.unwrap_or_default();
+        let conn = server.open_service(serial, "sync:").await?;
+        Ok(Self {
+            stream: conn.into_stream(),
+            stat_v2: features.iter().any(|f| f == "stat_v2"),
+            ls_v2: features.iter().any(|f| f == "ls_v2"),
+        })
+    }
+    async fn send_request(&mut self, id: &[u8; 4], payload: &[u8]) -> Result<()> {
+        let mut buf = Vec::with_capacity(8 + payload.len());
+        buf.extend_from_slice(id);
+        buf.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        buf.extend_from_slice(payload);
+        self.stream.write_all(&buf).await?;
+        Ok(())
+    }
+    async fn read_header(&mut self) -> Result<([u8; 4], u32)> {
+        let mut header = [0u8; 8];
+        self.stream.read_exact(&mut header).await?;
+        let id = [header[0], header[1], header[2], header[3]];
+        Ok((id, u32::from_le_bytes([header[4], header[5], header[6], header[7]])))
+    }
+    async fn read_u32(&mut self) -> Result<u32> {
+        let mut buf = [0u8; 4];
+        self.stream.read_exact(&mut buf).await?;
+        Ok(u32::from_le_bytes(buf))
+    }
+    async fn read_failure(&mut self, len: u32) -> Result<AdbError> {
+        let mut msg = vec![0u8; len as usize];
+        self.stream.read_exact(&mut msg).await?;
+        Ok(AdbError::classify(&String::from_utf8_lossy(&msg)))
+    }
+    pub async fn stat(&mut self, path: &[u8]) -> Result<RemoteStat> {
+        if self.stat_v2 {
+            return self.stat2(path).await;
+        }
+        self.send_request(b"STAT", path).await?;
+        let mut resp = [0u8; 16];
+        self.stream.read_exact(&mut resp).await?;
+        if &resp[..4] != b"STAT" {
+            return Err(AdbError::Protocol(format!("unexpected sync reply to STAT: {:?}", &resp[..4])));
+        }
+        let word = |i: usize| u32::from_le_bytes([resp[i], resp[i + 1], resp[i + 2], resp[i + 3]]);
+        Ok(RemoteStat {
+            mode: word(4),
+            size: word(8) as u64,
+            mtime: word(12) as u64,
+        })
+    }
+    async fn stat2(&mut self, path: &[u8]) -> Result<RemoteStat> {
+        self.send_request(b"STA2", path).await?;
+        let mut resp = [0u8; STAT_V2_LEN];
+        self.stream.read_exact(&mut resp).await?;
+        if &resp[..4] != b"STA2" {
+            return Err(AdbError::Protocol(format!("unexpected sync reply to STA2: {:?}", &resp[..4])));
+        }
+        Ok(parse_stat_v2(&resp).unwrap_or_default())
+    }
+    pub async fn list(&mut self, path: &[u8]) -> Result<Vec<RemoteEntry>> {
+        if self.ls_v2 {
+            return self.list2(path).await;
+        }
+        self.send_request(b"LIST", path).await?;
+        let mut entries = Vec::new();
+        loop {
+            let mut id = [0u8; 4];
+            self.stream.read_exact(&mut id).await?;
+            let mode = self.read_u32().await?;
+            let size = self.read_u32().await?;
+            let mtime = self.read_u32().await?;
+            let name_len = self.read_u32().await?;
+            match &id {
+                b"DENT" => {
+                    let mut name = vec![0u8; name_len as usize];
+                    self.stream.read_exact(&mut name).await?;
+                    if name == b"." || name == b".." {
+                        continue;
+                    }
+                    entries.push(RemoteEntry {
+                        name,
+                        stat: RemoteStat {
+                            mode,
+                            size: size as u64,
+                            mtime: mtime as u64,
+                        },
+                    });
+                }
+                b"DONE" => return Ok(entries),
+                other => return Err(AdbError::Protocol(format!("unexpected sync reply to LIST: {:?}", other))),
+            }
+        }
+    }
+    async fn list2(&mut self, path: &[u8]) -> Result<Vec<RemoteEntry>> {
+        self.send_request(b"LIS2", path).await?;
+        let mut entries = Vec::new();
+        loop {
+            let mut dent = [0u8; STAT_V2_LEN];
+            self.stream.read_exact(&mut dent).await?;
+            let name_len = self.read_u32().await?;
+            match &dent[..4] {
+                b"DNT2" => {
+                    let mut name = vec![0u8; name_len as usize];
+                    self.stream.read_exact(&mut name).await?;
+                    // lstat 失败的条目没有可用的元数据
+                    let Some(stat) = parse_stat_v2(&dent) else {
+                        continue;
+                    };
+                    if name == b"." || name == b".." {
+                        continue;
+                    }
+                    entries.push(RemoteEntry { name, stat });
+                }
+                b"DONE" => return Ok(entries),
+                other => return Err(AdbError::Protocol(format!("unexpected sync reply to LIS2: {:?}", other))),
+            }
+        }
+    }
+    pub async fn recv<W>(&mut self, path: &[u8], out: &mut W, mut on_chunk: impl FnMut(&[u8])) -> Result<u64>
+    where
+        W: AsyncWriteExt + Unpin,
+    {
+        self.send_request(b"RECV", path).await?;
+        let mut total = 0u64;
+        let mut buf = vec![0u8; SYNC_DATA_MAX];
+        loop {
+            let (id, len) = self.read_header().await?;
+            match &id {
+                b"DATA" => {
+                    let len = len as usize;
+                    if len > SYNC_DATA_MAX {
+                        return Err(AdbError::Protocol(format!("sync DATA chunk too large: {}", len)));
+                    }
+                    self.stream.read_exact(&mut buf[..len]).await?;
+                    out.write_all(&buf[..len]).await?;
+                    on_chunk(&buf[..len]);
+                    total += len as u64;
+                }
+                b"DONE" => return Ok(total),
+                b"FAIL" => return Err(self.read_failure(len).await?),
+                other => return Err(AdbError::Protocol(format!("unexpected sync reply to RECV: {:?}", other))),
+            }
+        }
+    }
+    pub async fn send<R>(
+        &mut self,
+        path: &[u8],
+        mode: u32,
+        mtime: u64,
+        input: &mut R,
+        mut on_chunk: impl FnMut(&[u8]),
+    ) -> Result<u64>
+    where
+        R: AsyncReadExt + Unpin,
+    {
+        let mut spec = path.to_vec();
+        spec.extend_from_slice(format!(",{}", mode).as_bytes());
+        self.send_request(b"SEND", &spec).await?;
+        let mut total = 0u64;
+        let mut buf = vec![0u8; SYNC_DATA_MAX];
+        loop {
+            let n = input.read(&mut buf).await?;
+            if n == 0 {
+                break;
+            }
+            self.send_request(b"DATA", &buf[..n]).await?;
+            on_chunk(&buf[..n]);
+            total += n as u64;
+        }
+        let mut done = Vec::with_capacity(8);
+        done.extend_from_slice(b"DONE");
+        done.extend_from_slice(&(mtime as u32).to_le_bytes());
+        self.stream.write_all(&done).await?;
+        let (id, len) = self.read_header().await?;
+        match &id {
+            b"OKAY" => Ok(total),
+            b"FAIL" => Err(self.read_failure(len).await?),
+            other => Err(AdbError::Protocol(format!("unexpected sync reply to SEND: {:?}", other))),
+        }
+    }
+    pub async fn quit(mut self) -> Result<()> {
+        self.send_request(b"QUIT", &[]).await
+    }
+}
+enum PushOutcome {
+    UpToDate,
+    Sent { bytes: u64, sha256: String },
+}
+struct PlannedFile {
+    remote: Vec<u8>,
+    relative: Vec<u8>,
+    local: PathBuf,
+    size: u64,
+    mtime: u64,
+    mode: u32,
+}
+pub struct SyncClient {
+    server: AdbServer,
+    serial: String,
+}
+impl SyncClient {
+    pub fn new(server: AdbServer, serial: &str) -> Self {
+        Self {
+            server,
+            serial: serial.to_string(),
+        }
+    }
+    async fn connect(&self) -> Result<SyncConnection> {
+        SyncConnection::open(&self.server, &self.serial).await
+    }
+    pub async fn stat(&self, path: &str) -> Result<RemoteStat> {
+        let mut conn = self.connect().await?;
+        let stat = conn.stat(path.as_bytes()).await?;
+        conn.quit().await?;
+        Ok(stat)
+    }
+    pub async fn list(&self, path: &str) -> Result<Vec<RemoteEntry>> {
+        let mut conn = self.connect().await?;
+        let entries = conn.list(path.as_bytes()).await?;
+        conn.quit().await?;
+        Ok(entries)
+    }
+    pub async fn pull(
+        &self,
+        remote: &str,
+        local: &Path,
+        options: &SyncOptions,
+        progress: ProgressFn<'_>,
+    ) -> Result<TransferReport> {
+        let mut conn = self.connect().await?;
+        let remote = remote.trim_end_matches('/');
+        let remote = if remote.is_empty() { "/" } else { remote };
+        let mut root_stat = conn.stat(remote.as_bytes()).await?;
+        if !root_stat.exists() {
+            return Err(AdbError::NotFoundOnDevice(remote.to_string()));
+        }
+        // STAT 按 lstat 返回，/sdcard 这类指向目录的符号链接要加上 / 再查一次才能当作目录拉取
+        if root_stat.is_symlink() {
+            let target = conn.stat(format!("{}/", remote).as_bytes()).await?;
+            if target.is_dir() {
+                root_stat = target;
+            }
+        }
+        let tree = root_stat.is_dir();
+        let (root, files) = if tree {
+            let files = Self::plan_pull(&mut conn, remote.as_bytes(), local).await?;
+            (local.to_path_buf(), files)
+        } else {
+            let name = remote.rsplit('/').next().unwrap_or(remote);
+            let file = PlannedFile {
+                remote: remote.as_bytes().to_vec(),
+                relative: name.as_bytes().to_vec(),
+                local: local.to_path_buf(),
+                size: root_stat.size,
+                mtime: root_stat.mtime,
+                mode: root_stat.mode,
+            };
+            (local.parent().map(Path::to_path_buf).unwrap_or_default(), vec![file])
+        };
+        tokio::fs::create_dir_all(&root).await?;
+        let mut manifest = if options.resume && tree { SyncManifest::load(&root) } else { SyncManifest::default() };
+        let mut state = TransferProgress {
+            files_total: files.len(),
+            bytes_total: files.iter().map(|f| f.size).sum(),
+            ..TransferProgress::default()
+        };
+        let mut report = TransferReport::default();
+        for file in &files {
+            let key = String::from_utf8_lossy(&file.relative).to_string();
+            state.file = key.clone();
+            state.file_bytes = 0;
+            state.file_total = file.size;
+            let up_to_date = manifest
+                .files
+                .get(&key)
+                .is_some_and(|m| m.size == file.size && m.mtime == file.mtime)
+                && std::fs::metadata(&file.local).is_ok_and(|m| m.len() == file.size);
+            if options.resume && up_to_date {
+                state.files_done += 1;
+                state.bytes_done += file.size;
+                progress(&state);
+                report.skipped.push(key);
+                continue;
+            }
+            match self.pull_file(&mut conn, file, &mut state, progress).await {
+                Ok(sha256) => match self.verify(options, &file.remote, &sha256).await {
+                    Some(false) => {
+                        let _ = std::fs::remove_file(&file.local);
+                        manifest.files.remove(&key);
+                        report.failures.push(TransferFailure::checksum(key));
+                    }
+                    verified => {
+                        report.verified += usize::from(verified.is_some());
+                        manifest.files.insert(
+                            key.clone(),
+                            ManifestEntry {
+                                size: file.size,
+                                mtime: file.mtime,
+                                sha256,
+                            },
+                        );
+                        if tree {
+                            manifest.save(&root)?;
+                        }
+                        report.bytes += file.size;
+                        report.transferred.push(key);
+                    }
+                },
+                Err(e) => {
+                    report.failures.push(TransferFailure {
+                        path: key,
+                        error: e.to_string(),
+                    });
+                    conn = self.connect().await?;
+                }
+            }
+            state.files_done += 1;
+            progress(&state);
+        }
+        if tree {
+            manifest.save(&root)?;
+        }
+        let _ = conn.quit().await;
+        Ok(report)
+    }
+    async fn plan_pull(conn: &mut SyncConnection, remote: &[u8], local: &Path) -> Result<Vec<PlannedFile>> {
+        let mut files = Vec::new();
+        let mut pending: Vec<Vec<u8>> = vec![Vec::new()];
+        while let Some(relative) = pending.pop() {
+            let dir = join_remote(remote, &relative);
+            for entry in conn.list(&dir).await? {
+                let child = if relative.is_empty() {
+                    entry.name.clone()
+                } else {
+                    join_remote(&relative, &entry.name)
+                };
+                if entry.stat.is_dir() {
+                    tokio::fs::create_dir_all(local.join(local_relative(&child))).await?;
+                    pending.push(child);
+                } else if entry.stat.is_file() {
+                    files.push(PlannedFile {
+                        remote: join_remote(remote, &child),
+                        local: local.join(local_relative(&child)),
+                        relative: child,
+                        size: entry.stat.size,
+                        mtime: entry.stat.mtime,
+                        mode: entry.stat.mode,
+                    });
+                }
+            }
+        }
+        files.sort_by(|a, b| a.relative.cmp(&b.relative));
+        Ok(files)
+    }
+    async fn pull_file(
+        &self,
+        conn: &mut SyncConnection,
+        file: &PlannedFile,
+        state: &mut TransferProgress,
+        progress: ProgressFn<'_>,
+    ) -> Result<String> {
+        if let Some(parent) = file.local.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let mut part_name = file.local.as_os_str().to_owned();
+        part_name.push(".part");
+        let part = PathBuf::from(part_name);
+        let mut out = tokio::fs::File::create(&part).await?;
+        let mut hasher = Sha256::new();
+        let received = conn
+            .recv(&file.remote, &mut out, |chunk| {
+                hasher.update(chunk);
+                state.file_bytes += chunk.len() as u64;
+                state.bytes_done += chunk.len() as u64;
+                progress(state);
+            })
+            .await;
+        if let Err(e) = received {
+            drop(out);
+            let _ = tokio::fs::remove_file(&part).await;
+            return Err(e);
+        }
+        out.flush().await?;
+        drop(out);
+        tokio::fs::rename(&part, &file.local).await?;
+        let mtime = UNIX_EPOCH + Duration::from_secs(file.mtime);
+        std::fs::File::options().write(true).open(&file.local)?.set_modified(mtime)?;
+        Ok(hex(&hasher.finalize()))
+    }
+    pub async fn push(
+        &self,
+        local: &Path,
+        remote: &str,
+        options: &SyncOptions,
+        progress: ProgressFn<'_>,
+    ) -> Result<TransferReport> {
+        let remote = remote.trim_end_matches('/');
+        let files = Self::plan_push(local, remote.as_bytes())?;
+        let mut conn = self.connect().await?;
+        let mut state = TransferProgress {
+            files_total: files.len(),
+            bytes_total: files.iter().map(|f| f.size).sum(),
+            ..TransferProgress::default()
+        };
+        let mut report = TransferReport::default();
+        for file in &files {
+            let key = String::from_utf8_lossy(&file.relative).to_string();
+            state.file = key.clone();
+            state.file_bytes = 0;
+            state.file_total = file.size;
+            match self.push_file(&mut conn, file, options, &mut state, progress).await {
+                Ok(PushOutcome::UpToDate) => {
+                    state.bytes_done += file.size;
+                    report.skipped.push(key);
+                }
+                Ok(PushOutcome::Sent { bytes, sha256 }) => match self.verify(options, &file.remote, &sha256).await {
+                    Some(false) => {
+                        // 不留下内容损坏的文件，下次续传时会重新发送
+                        self.remove_remote(&file.remote).await;
+                        report.failures.push(TransferFailure::checksum(key));
+                    }
+                    verified => {
+                        report.verified += usize::from(verified.is_some());
+                        report.bytes += bytes;
+                        report.transferred.push(key);
+                    }
+                },
+                Err(e) => {
+                    report.failures.push(TransferFailure {
+                        path: key,
+                        error: e.to_string(),
+                    });
+                    conn = self.connect().await?;
+                }
+            }
+            state.files_done += 1;
+            progress(&state);
+        }
+        let _ = conn.quit().await;
+        Ok(report)
+    }
+    async fn push_file(
+        &self,
+        conn: &mut SyncConnection,
+        file: &PlannedFile,
+        options: &SyncOptions,
+        state: &mut TransferProgress,
+        progress: ProgressFn<'_>,
+    ) -> Result<PushOutcome> {
+        if options.resume {
+            let existing = conn.stat(&file.remote).await?;
+            if existing.is_file() && existing.size == file.size && existing.mtime == file.mtime {
+                return Ok(PushOutcome::UpToDate);
+            }
+        }
+        let mut input = tokio::fs::File::open(&file.local).await?;
+        let mut hasher = Sha256::new();
+        let bytes = conn
+            .send(&file.remote, file.mode, file.mtime, &mut input, |chunk| {
+                hasher.update(chunk);
+                state.file_bytes += chunk.len() as u64;
+                state.bytes_done += chunk.len() as u64;
+                progress(state);
+            })
+            .await?;
+        Ok(PushOutcome::Sent {
+            bytes,
+            sha256: hex(&hasher.finalize()),
+        })
+    }
+    fn plan_push(local: &Path, remote: &[u8]) -> Result<Vec<PlannedFile>> {
+        let mut files = Vec::new();
+        let meta = std::fs::metadata(local)?;
+        if meta.is_file() {
+            files.push(Self::planned_local(local, remote.to_vec(), Vec::new(), &meta));
+            return Ok(files);
+        }
+        let mut pending = vec![PathBuf::new()];
+        while let Some(relative) = pending.pop() {
+            for entry in std::fs::read_dir(local.join(&relative))? {
+                let entry = entry?;
+                let child = relative.join(entry.file_name());
+                let meta = entry.metadata()?;
+                if meta.is_dir() {
+                    pending.push(child);
+                } else if meta.is_file() && entry.file_name() != SYNC_MANIFEST {
+                    let rel = remote_relative(&child);
+                    files.push(Self::planned_local(&local.join(&child), join_remote(remote, &rel), rel, &meta));
+                }
+            }
+        }
+        files.sort_by(|a, b| a.relative.cmp(&b.relative));
+        Ok(files)
+    }
+    fn planned_local(local: &Path, remote: Vec<u8>, relative: Vec<u8>, meta: &std::fs::Metadata) -> PlannedFile {
+        #[cfg(unix)]
+        let mode = {
+            use std::os::unix::fs::PermissionsExt;
+            S_IFREG | (meta.permissions().mode() & 0o777)
+        };
+        #[cfg(not(unix))]
+        let mode = S_IFREG | 0o644;
+        let relative = if relative.is_empty() {
+            local.file_name().map(|n| n.to_string_lossy().as_bytes().to_vec()).unwrap_or_default()
+        } else {
+            relative
+        };
+        PlannedFile {
+            remote,
+            relative,
+            local: local.to_path_buf(),
+            size: meta.len(),
+            mtime: meta
+                .modified()
+                .ok()
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map(|d| d.as_secs())
+                .unwrap_or_else(|| SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)),
+            mode,
+        }
+    }
+    // 未要求校验或设备无法计算摘要时返回 None
+    async fn verify(&self, options: &SyncOptions, remote: &[u8], sha256: &str) -> Option<bool> {
+        if !options.verify {
+            return None;
+        }
+        self.remote_sha256(remote).await.map(|remote_sha| remote_sha == sha256)
+    }
+    async fn remote_shell(&self, cmd: &[&str]) -> Result<Vec<u8>> {
+        let mut conn = self
+            .server
+            .open_service(&self.serial, &protocol::shell_service(cmd))
+            .await?;
+        conn.read_to_end().await
+    }
+    async fn remove_remote(&self, path: &[u8]) {
+        let quoted = shell_quote(&String::from_utf8_lossy(path));
+        let _ = self.remote_shell(&["rm", "-f", &quoted]).await;
+    }
+    async fn remote_sha256(&self, path: &[u8]) -> Option<String> {
+        let quoted = shell_quote(&String::from_utf8_lossy(path));
+        let out = self.remote_shell(&["sha256sum", &quoted]).await.ok()?;
+        let text = String::from_utf8_lossy(&out);
+        let digest = text.split_whitespace().next()?;
+        (digest.len() == 64 && digest.chars().all(|c| c.is_ascii_hexdigit())).then(|| digest.to_lowercase())
+    }
+}
+fn join_remote(base: &[u8], name: &[u8]) -> Vec<u8> {
+    let mut path = base.to_vec();
+    if !path.ends_with(b"/") {
+        path.push(b'/');
+    }
+    path.extend_from_slice(name);
+    path
+}
+#[cfg(unix)]
+fn local_relative(relative: &[u8]) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+    PathBuf::from(std::ffi::OsStr::from_bytes(relative))
+}
+#[cfg(not(unix))]
+fn local_relative(relative: &[u8]) -> PathBuf {
+    String::from_utf8_lossy(relative).split('/').collect()
+}
+fn remote_relative(relative: &Path) -> Vec<u8> {
+    relative
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().to_string())
+        .collect::<Vec<_>>()
+        .join("/")
+        .into_bytes()
+}
+fn parse_stat_v2(buf: &[u8; STAT_V2_LEN]) -> Option<RemoteStat> {
+    let u32_at = |i: usize| u32::from_le_bytes([buf[i], buf[i + 1], buf[i + 2], buf[i + 3]]);
+    let u64_at = |i: usize| u64::from_le_bytes(buf[i..i + 8].try_into().unwrap_or_default());
+    if u32_at(4) != 0 {
+        return None;
+    }
+    Some(RemoteStat {
+        mode: u32_at(24),
+        size: u64_at(40),
+        mtime: u64_at(56),
+    })
+}
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adb::protocol::test_server::{self, length_prefixed, okay, read_request};
+    use std::net::SocketAddr;
+    use std::sync::{Arc, Mutex};
+    async fn read_sync_request(stream: &mut TcpStream) -> (Vec<u8>, Vec<u8>) {
+        let mut header = [0u8; 8];
+        stream.read_exact(&mut header).await.unwrap();
+        // SEND 结束时的 DONE 后面跟的是 mtime，不是长度
+        if &header[..4] == b"DONE" {
+            return (header[..4].to_vec(), header[4..].to_vec());
+        }
+        let len = u32::from_le_bytes(header[4..].try_into().unwrap()) as usize;
+        let mut payload = vec![0u8; len];
+        stream.read_exact(&mut payload).await.unwrap();
+        (header[..4].to_vec(), payload)
+    }
+    fn stat_v2_reply(id: &[u8; 4], mode: u32, size: u64, mtime: u64) -> Vec<u8> {
+        let mut reply = vec![0u8; STAT_V2_LEN];
+        reply[..4].copy_from_slice(id);
+        reply[24..28].copy_from_slice(&mode.to_le_bytes());
+        reply[40..48].copy_from_slice(&size.to_le_bytes());
+        reply[56..64].copy_from_slice(&mtime.to_le_bytes());
+        reply
+    }
+    // 先应答 features 查询，其余连接在 host:transport 之后返回服务名
+    async fn serve(stream: &mut TcpStream, features: &str) -> Option<String> {
+        let request = read_request(stream).await;
+        if request.ends_with(":features") {
+            okay(stream).await;
+            length_prefixed(stream, features).await;
+            return None;
+        }
+        assert_eq!(request, "host:transport:AAA");
+        okay(stream).await;
+        let service = read_request(stream).await;
+        okay(stream).await;
+        Some(service)
+    }
+    #[tokio::test]
+    async fn stat_and_list_use_v2_when_advertised() {
+        let addr = test_server::spawn(|mut stream| async move {
+            let Some(service) = serve(&mut stream, "shell_v2,stat_v2,ls_v2").await else {
+                return;
+            };
+            assert_eq!(service, "sync:");
+            let (id, path) = read_sync_request(&mut stream).await;
+            assert_eq!((id.as_slice(), path.as_slice()), (&b"STA2"[..], &b"/sdcard/big.img"[..]));
+            stream.write_all(&stat_v2_reply(b"STA2", S_IFREG | 0o644, 5 << 30, 1 << 33)).await.unwrap();
+            let (id, _) = read_sync_request(&mut stream).await;
+            assert_eq!(id, b"LIS2");
+            for (name, mode) in [(&b"."[..], S_IFDIR), (b"big.img", S_IFREG | 0o644)] {
+                let mut dent = stat_v2_reply(b"DNT2", mode, 5 << 30, 1 << 33);
+                dent.extend_from_slice(&(name.len() as u32).to_le_bytes());
+                dent.extend_from_slice(name);
+                stream.write_all(&dent).await.unwrap();
+            }
+            let mut done = stat_v2_reply(b"DONE", 0, 0, 0);
+            done.extend_from_slice(&0u32.to_le_bytes());
+            stream.write_all(&done).await.unwrap();
+        })
+        .await;
+        let mut conn = SyncConnection::open(&AdbServer::new(addr), "AAA").await.unwrap();
+        let stat = conn.stat(b"/sdcard/big.img").await.unwrap();
+        assert!(stat.is_file());
+        assert_eq!((stat.size, stat.mtime), (5 << 30, 1 << 33));
+        let entries = conn.list(b"/sdcard").await.unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].name, b"big.img");
+        assert_eq!(entries[0].stat.size, 5 << 30);
+    }
+    fn push_server(shell: Arc<Mutex<Vec<String>>>) -> impl std::future::Future<Output = SocketAddr> {
+        test_server::spawn(move |mut stream| {
+            let shell = shell.clone();
+            async move {
+                let Some(service) = serve(&mut stream, "shell_v2").await else {
+                    return;
+                };
+                if let Some(cmd) = service.strip_prefix("shell:") {
+                    shell.lock().unwrap().push(cmd.to_string());
+                    if cmd.starts_with("sha256sum") {
+                        stream.write_all(format!("{}  /sdcard/a.txt\n", "0".repeat(64)).as_bytes()).await.unwrap();
+                    }
+                    return;
+                }
+                assert_eq!(service, "sync:");
+                loop {
+                    let (id, payload) = read_sync_request(&mut stream).await;
+                    match id.as_slice() {
+                        // a.txt 的 STAT 应答损坏，模拟单个文件出错
+                        b"STAT" if payload.ends_with(b"a.txt") => stream.write_all(&[b'X'; 16]).await.unwrap(),
+                        b"STAT" => stream.write_all(b"STAT\0\0\0\0\0\0\0\0\0\0\0\0").await.unwrap(),
+                        b"SEND" | b"DATA" => {}
+                        b"DONE" => stream.write_all(b"OKAY\0\0\0\0").await.unwrap(),
+                        _ => return,
+                    }
+                }
+            }
+        })
+    }
+    #[tokio::test]
+    async fn push_checksum_mismatch_removes_remote_file_and_still_counts_progress() {
+        let shell = Arc::new(Mutex::new(Vec::new()));
+        let addr = push_server(shell.clone()).await;
+        let local = std::env::temp_dir().join(format!("androidchecker-push-{}.txt", std::process::id()));
+        std::fs::write(&local, b"hello").unwrap();
+        let options = SyncOptions {
+            resume: false,
+            verify: true,
+            ..SyncOptions::default()
+        };
+        let last = Mutex::new(TransferProgress::default());
+        let progress = |p: &TransferProgress| *last.lock().unwrap() = p.clone();
+        let report = SyncClient::new(AdbServer::new(addr), "AAA")
+            .push(&local, "/sdcard/a.txt", &options, &progress)
+            .await
+            .unwrap();
+        let _ = std::fs::remove_file(&local);
+        assert!(report.transferred.is_empty());
+        assert_eq!(report.failures.len(), 1);
+        assert_eq!(report.failures[0].error, "SHA-256 校验不一致");
+        assert_eq!(last.lock().unwrap().files_done, 1);
+        let shell = shell.lock().unwrap();
+        assert_eq!(shell.last().map(String::as_str), Some("rm -f '/sdcard/a.txt'"));
+    }
+    // v1 协议的假设备：/sdcard 是指向 /storage/emulated/0 的符号链接
+    const A_MTIME: u32 = 1_600_000_000;
+    const B_MTIME: u32 = 1_600_000_100;
+    fn pull_server(recv: Arc<Mutex<Vec<String>>>) -> impl std::future::Future<Output = SocketAddr> {
+        test_server::spawn(move |mut stream| {
+            let recv = recv.clone();
+            async move {
+                let Some(service) = serve(&mut stream, "shell_v2").await else {
+                    return;
+                };
+                assert_eq!(service, "sync:");
+                loop {
+                    let (id, payload) = read_sync_request(&mut stream).await;
+                    let path = String::from_utf8(payload).unwrap();
+                    match id.as_slice() {
+                        b"STAT" => {
+                            let (mode, size, mtime) = match path.as_str() {
+                                "/sdcard" => (S_IFLNK | 0o777, 21, 0),
+                                "/sdcard/" | "/sdcard/DCIM" => (S_IFDIR | 0o771, 4096, 0),
+                                "/sdcard/a.txt" => (S_IFREG | 0o660, 5, A_MTIME),
+                                _ => (0, 0, 0),
+                            };
+                            let mut reply = b"STAT".to_vec();
+                            for word in [mode, size, mtime] {
+                                reply.extend_from_slice(&u32::to_le_bytes(word));
+                            }
+                            stream.write_all(&reply).await.unwrap();
+                        }
+                        b"LIST" => {
+                            let entries: &[(&str, u32, u32, u32)] = match path.as_str() {
+                                "/sdcard" | "/sdcard/" => &[
+                                    (".", S_IFDIR | 0o771, 4096, 0),
+                                    ("DCIM", S_IFDIR | 0o771, 4096, 0),
+                                    ("a.txt", S_IFREG | 0o660, 5, A_MTIME),
+                                    ("latest", S_IFLNK | 0o777, 10, 0),
+                                ],
+                                "/sdcard/DCIM" => &[("b.jpg", S_IFREG | 0o660, 3, B_MTIME)],
+                                _ => &[],
+                            };
+                            let mut reply = Vec::new();
+                            for (name, mode, size, mtime) in entries {
+                                reply.extend_from_slice(b"DENT");
+                                for word in [*mode, *size, *mtime, name.len() as u32] {
+                                    reply.extend_from_slice(&word.to_le_bytes());
+                                }
+                                reply.extend_from_slice(name.as_bytes());
+                            }
+                            reply.extend_from_slice(b"DONE");
+                            reply.extend_from_slice(&[0u8; 16]);
+                            stream.write_all(&reply).await.unwrap();
+                        }
+                        b"RECV" => {
+                            let data: &[u8] = match path.as_str() {
+                                "/sdcard/a.txt" => b"hello",
+                                "/sdcard/DCIM/b.jpg" => b"\xff\xd8\xff",
+                                _ => b"",
+                            };
+                            recv.lock().unwrap().push(path.clone());
+                            let mut reply = b"DATA".to_vec();
+                            reply.extend_from_slice(&(data.len() as u32).to_le_bytes());
+                            reply.extend_from_slice(data);
+                            reply.extend_from_slice(b"DONE\0\0\0\0");
+                            stream.write_all(&reply).await.unwrap();
+                        }
+                        _ => return,
+                    }
+                }
+            }
+        })
+    }
+    fn mtime_of(path: &Path) -> u64 {
+        let modified = std::fs::metadata(path).unwrap().modified().unwrap();
+        modified.duration_since(UNIX_EPOCH).unwrap().as_secs()
+    }
+    #[tokio::test]
+    async fn pull_follows_symlinked_root_and_keeps_mtimes() {
+        let recv = Arc::new(Mutex::new(Vec::new()));
+        let addr = pull_server(recv.clone()).await;
+        let local = std::env::temp_dir().join(format!("androidchecker-pull-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&local);
+        let report = SyncClient::new(AdbServer::new(addr), "AAA")
+            .pull("/sdcard/", &local, &SyncOptions::default(), &|_| {})
+            .await
+            .unwrap();
+        assert_eq!(report.transferred, vec!["DCIM/b.jpg".to_string(), "a.txt".to_string()]);
+        assert!(report.failures.is_empty());
+        assert_eq!(report.bytes, 8);
+        assert_eq!(std::fs::read(local.join("a.txt")).unwrap(), b"hello");
+        assert_eq!(std::fs::read(local.join("DCIM/b.jpg")).unwrap(), b"\xff\xd8\xff");
+        assert_eq!(mtime_of(&local.join("a.txt")), A_MTIME as u64);
+        assert_eq!(mtime_of(&local.join("DCIM/b.jpg")), B_MTIME as u64);
+        assert!(!local.join("latest").exists());
+        assert!(local.join(SYNC_MANIFEST).is_file());
+        let _ = std::fs::remove_dir_all(&local);
+    }
+    #[tokio::test]
+    async fn pull_resume_skips_files_recorded_in_manifest() {
+        let recv = Arc::new(Mutex::new(Vec::new()));
+        let addr = pull_server(recv.clone()).await;
+        let local = std::env::temp_dir().join(format!("androidchecker-resume-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&local);
+        let sync = SyncClient::new(AdbServer::new(addr), "AAA");
+        sync.pull("/sdcard", &local, &SyncOptions::default(), &|_| {}).await.unwrap();
+        assert_eq!(recv.lock().unwrap().len(), 2);
+        // 模拟中断：b.jpg 丢失，a.txt 完整
+        std::fs::remove_file(local.join("DCIM/b.jpg")).unwrap();
+        let last = Mutex::new(TransferProgress::default());
+        let progress = |p: &TransferProgress| *last.lock().unwrap() = p.clone();
+        let report = sync.pull("/sdcard", &local, &SyncOptions::default(), &progress).await.unwrap();
+        assert_eq!(report.skipped, vec!["a.txt".to_string()]);
+        assert_eq!(report.transferred, vec!["DCIM/b.jpg".to_string()]);
+        assert_eq!(recv.lock().unwrap().last().map(String::as_str), Some("/sdcard/DCIM/b.jpg"));
+        assert_eq!(recv.lock().unwrap().len(), 3);
+        let last = last.lock().unwrap();
+        assert_eq!((last.files_done, last.bytes_done, last.bytes_total), (2, 8, 8));
+        let _ = std::fs::remove_dir_all(&local);
+    }
+    #[tokio::test]
+    async fn pull_single_file_and_missing_root() {
+        let recv = Arc::new(Mutex::new(Vec::new()));
+        let addr = pull_server(recv.clone()).await;
+        let local = std::env::temp_dir().join(format!("androidchecker-single-{}", std::process::id())).join("copy.txt");
+        let sync = SyncClient::new(AdbServer::new(addr), "AAA");
+        let report = sync.pull("/sdcard/a.txt", &local, &SyncOptions::default(), &|_| {}).await.unwrap();
+        assert_eq!(report.transferred, vec!["a.txt".to_string()]);
+        assert_eq!(std::fs::read(&local).unwrap(), b"hello");
+        assert_eq!(mtime_of(&local), A_MTIME as u64);
+        let err = sync.pull("/sdcard/nope", &local, &SyncOptions::default(), &|_| {}).await.err().unwrap();
+        assert!(matches!(err, AdbError::NotFoundOnDevice(_)), "{:?}", err);
+        let stat = sync.stat("/sdcard").await.unwrap();
+        assert!(stat.is_symlink());
+        let names: Vec<String> = sync.list("/sdcard").await.unwrap().iter().map(RemoteEntry::name_lossy).collect();
+        assert_eq!(names, vec!["DCIM", "a.txt", "latest"]);
+        let _ = std::fs::remove_dir_all(local.parent().unwrap());
+    }
+    #[test]
+    fn ls_output_is_split_on_raw_bytes() {
+        let entries = parse_ls(b".\r\n..\r\nDCIM\r\nna\xefme\r\n");
+        let names: Vec<&[u8]> = entries.iter().map(|e| e.name.as_slice()).collect();
+        assert_eq!(names, vec![&b"DCIM"[..], &b"na\xefme"[..]]);
+        assert_eq!(entries[1].name_lossy(), "na\u{fffd}me");
+        assert!(!entries[0].stat.exists());
+    }
+    #[tokio::test]
+    async fn push_records_per_file_errors_and_continues() {
+        let shell = Arc::new(Mutex::new(Vec::new()));
+        let addr = push_server(shell.clone()).await;
+        let local = std::env::temp_dir().join(format!("androidchecker-push-dir-{}", std::process::id()));
+        std::fs::create_dir_all(&local).unwrap();
+        std::fs::write(local.join("a.txt"), b"first").unwrap();
+        std::fs::write(local.join("b.txt"), b"second").unwrap();
+        let last = Mutex::new(TransferProgress::default());
+        let progress = |p: &TransferProgress| *last.lock().unwrap() = p.clone();
+        let report = SyncClient::new(AdbServer::new(addr), "AAA")
+            .push(&local, "/sdcard/dir", &SyncOptions::default(), &progress)
+            .await
+            .unwrap();
+        let _ = std::fs::remove_dir_all(&local);
+        assert_eq!(report.failures.len(), 1);
+        assert_eq!(report.failures[0].path, "a.txt");
+        assert_eq!(report.transferred, vec!["b.txt".to_string()]);
+        assert_eq!(last.lock().unwrap().files_done, 2);
+    }
+}
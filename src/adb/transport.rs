@@ -0,0 +1,150 @@
+﻿use super::forward::{self, Endpoint, ForwardDirection, PortRule};
+use super::protocol::AdbConnection;
+use super::sync::{self, ProgressFn, RemoteEntry, SyncOptions, TransferReport};
+use crate::core::{AdbError, Result};
+use async_trait::async_trait;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+pub type SharedTransport = Arc<dyn AdbTransport>;
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ShellOutput {
+    pub stdout: String,
+    pub stderr: String,
+    pub exit_code: i32,
+}
+impl ShellOutput {
+    pub fn success(&self) -> bool {
+        self.exit_code == 0
+    }
+    pub fn failure(&self) -> Option<AdbError> {
+        if self.success() {
+            return None;
+        }
+        let detail = if self.stderr.trim().is_empty() { &self.stdout } else { &self.stderr };
+        if detail.trim().is_empty() {
+            Some(AdbError::CommandFailed(format!("remote exit code {}", self.exit_code)))
+        } else {
+            Some(AdbError::classify(detail))
+        }
+    }
+    pub fn into_result(self) -> Result<String> {
+        match self.failure() {
+            Some(err) => Err(err),
+            None => Ok(self.stdout),
+        }
+    }
+}
+#[async_trait]
+pub trait AdbTransport: Send + Sync {
+    async fn try_execute(&self, args: &[&str]) -> Result<(bool, String, String)>;
+    async fn try_shell(&self, serial: &str, cmd: &[&str]) -> Result<(bool, String, String)>;
+    // 字节流输出不能经过 String 解码，每个实现都必须直接返回原始字节
+    async fn exec_out(&self, serial: &str, cmd: &[&str]) -> Result<Vec<u8>>;
+    async fn shell_output(&self, serial: &str, cmd: &[&str]) -> Result<ShellOutput> {
+        let (ok, stdout, stderr) = self.try_shell(serial, cmd).await?;
+        Ok(ShellOutput {
+            stdout,
+            stderr,
+            exit_code: if ok { 0 } else { 1 },
+        })
+    }
+    async fn execute(&self, args: &[&str]) -> Result<(String, String)> {
+        let (ok, stdout, stderr) = self.try_execute(args).await?;
+        if !ok {
+            return Err(AdbError::classify(&stderr));
+        }
+        Ok((stdout, stderr))
+    }
+    async fn shell(&self, serial: &str, cmd: &[&str]) -> Result<String> {
+        let mut args = vec!["-s", serial, "shell"];
+        args.extend_from_slice(cmd);
+        let (stdout, _) = self.execute(&args).await?;
+        Ok(stdout)
+    }
+    async fn pull(
+        &self,
+        serial: &str,
+        remote: &str,
+        local: &Path,
+        options: &SyncOptions,
+        _progress: ProgressFn<'_>,
+    ) -> Result<TransferReport> {
+        std::fs::create_dir_all(local)?;
+        let source = format!("{}/.", remote.trim_end_matches('/'));
+        let target = local.to_string_lossy();
+        self.execute_with_timeout(&["-s", serial, "pull", &source, &target], options.timeout)
+            .await?;
+        Ok(TransferReport::whole(remote))
+    }
+    async fn push(
+        &self,
+        serial: &str,
+        local: &Path,
+        remote: &str,
+        options: &SyncOptions,
+        _progress: ProgressFn<'_>,
+    ) -> Result<TransferReport> {
+        let source = format!("{}/.", local.to_string_lossy().trim_end_matches(['/', '\\']));
+        self.execute_with_timeout(&["-s", serial, "push", &source, remote], options.timeout)
+            .await?;
+        Ok(TransferReport::whole(&local.to_string_lossy()))
+    }
+    async fn list_dir(&self, serial: &str, remote: &str) -> Result<Vec<RemoteEntry>> {
+        Ok(sync::parse_ls(&self.exec_out(serial, &["ls", "-1a", remote]).await?))
+    }
+    async fn execute_with_timeout(&self, args: &[&str], _timeout: Duration) -> Result<(String, String)> {
+        self.execute(args).await
+    }
+    async fn try_execute_with_timeout(&self, args: &[&str], _timeout: Duration) -> Result<(bool, String, String)> {
+        self.try_execute(args).await
+    }
+    async fn devices_long(&self) -> Result<String> {
+        let (stdout, _) = self.execute(&["devices", "-l"]).await?;
+        Ok(stdout)
+    }
+    async fn track_devices(&self) -> Result<Option<AdbConnection>> {
+        Ok(None)
+    }
+    async fn list_forwards(&self, serial: &str, direction: ForwardDirection) -> Result<Vec<PortRule>> {
+        let (stdout, _) = self.execute(&["-s", serial, direction.as_str(), "--list"]).await?;
+        let owner = (direction == ForwardDirection::Forward).then_some(serial);
+        Ok(forward::parse_forward_list(&stdout, direction, owner))
+    }
+    async fn add_forward(&self, serial: &str, rule: &PortRule) -> Result<()> {
+        let (listen, target) = (rule.listen.to_string(), rule.target.to_string());
+        self.execute(&["-s", serial, rule.direction.as_str(), &listen, &target])
+            .await?;
+        Ok(())
+    }
+    async fn remove_forward(&self, serial: &str, direction: ForwardDirection, listen: &Endpoint) -> Result<()> {
+        let listen = listen.to_string();
+        self.execute(&["-s", serial, direction.as_str(), "--remove", &listen])
+            .await?;
+        Ok(())
+    }
+    async fn remove_all_forwards(&self, serial: &str, direction: ForwardDirection) -> Result<()> {
+        self.execute(&["-s", serial, direction.as_str(), "--remove-all"]).await?;
+        Ok(())
+    }
+    async fn parallel_shell(&self, serial: &str, commands: Vec<Vec<String>>) -> Vec<Result<String>> {
+        let tasks = commands.iter().map(|cmd| async move {
+            let cmd_refs: Vec<&str> = cmd.iter().map(|s| s.as_str()).collect();
+            self.shell(serial, &cmd_refs).await
+        });
+        futures::future::join_all(tasks).await
+    }
+}
+pub fn command_key(args: &[&str]) -> String {
+    args.join(" ")
+}
+pub fn exec_out_key(serial: &str, cmd: &[&str]) -> String {
+    let mut args = vec!["-s", serial, "exec-out"];
+    args.extend_from_slice(cmd);
+    command_key(&args)
+}
+pub fn shell_key(serial: &str, cmd: &[&str]) -> String {
+    let mut args = vec!["-s", serial, "shell"];
+    args.extend_from_slice(cmd);
+    command_key(&args)
+}
@@ -0,0 +1,262 @@
+﻿use super::device::{parse_device_states, DeviceState};
+use super::transport::SharedTransport;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio_util::sync::CancellationToken;
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const EVENT_CAPACITY: usize = 64;
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum DeviceEvent {
+    Connected { serial: String, state: DeviceState },
+    Disconnected { serial: String, last_state: DeviceState },
+    StateChanged { serial: String, from: DeviceState, to: DeviceState },
+}
+impl DeviceEvent {
+    pub fn serial(&self) -> &str {
+        match self {
+            Self::Connected { serial, .. } | Self::Disconnected { serial, .. } | Self::StateChanged { serial, .. } => {
+                serial
+            }
+        }
+    }
+    pub fn describe(&self) -> String {
+        match self {
+            Self::Connected { serial, state } => format!("设备已连接: {} ({})", serial, state),
+            Self::Disconnected { serial, .. } => format!("设备已断开: {}", serial),
+            Self::StateChanged { serial, from, to } => format!("设备状态变化: {} {} → {}", serial, from, to),
+        }
+    }
+    pub fn is_loss_of(&self, serial: &str) -> bool {
+        self.serial() == serial
+            && match self {
+                Self::Disconnected { .. } => true,
+                Self::StateChanged { to, .. } => !to.is_online(),
+                Self::Connected { .. } => false,
+            }
+    }
+}
+pub struct DeviceWatcher {
+    events: broadcast::Sender<DeviceEvent>,
+    devices: Arc<Mutex<HashMap<String, DeviceState>>>,
+    primed: Arc<AtomicBool>,
+    cancel: CancellationToken,
+}
+impl DeviceWatcher {
+    pub fn start(client: SharedTransport) -> Self {
+        let (events, _) = broadcast::channel(EVENT_CAPACITY);
+        let devices = Arc::new(Mutex::new(HashMap::new()));
+        let primed = Arc::new(AtomicBool::new(false));
+        let cancel = CancellationToken::new();
+        let tracker = Tracker {
+            client,
+            events: events.clone(),
+            devices: devices.clone(),
+            primed: primed.clone(),
+        };
+        let token = cancel.clone();
+        tokio::spawn(async move {
+            tokio::select! {
+                _ = token.cancelled() => {}
+                _ = tracker.run() => {}
+            }
+        });
+        Self {
+            events,
+            devices,
+            primed,
+            cancel,
+        }
+    }
+    pub fn subscribe(&self) -> broadcast::Receiver<DeviceEvent> {
+        self.events.subscribe()
+    }
+    pub fn devices(&self) -> HashMap<String, DeviceState> {
+        self.devices.lock().map(|d| d.clone()).unwrap_or_default()
+    }
+    pub fn state(&self, serial: &str) -> Option<DeviceState> {
+        self.devices.lock().ok().and_then(|d| d.get(serial).cloned())
+    }
+    pub fn is_online(&self, serial: &str) -> bool {
+        self.state(serial).is_some_and(|s| s.is_online())
+    }
+    pub fn is_lost(&self, serial: &str) -> bool {
+        self.primed.load(Ordering::Relaxed) && !self.is_online(serial)
+    }
+    pub async fn wait_for_online(&self) -> Option<String> {
+        let mut rx = self.subscribe();
+        if let Some(serial) = self.devices().into_iter().find(|(_, s)| s.is_online()).map(|(serial, _)| serial) {
+            return Some(serial);
+        }
+        loop {
+            match rx.recv().await {
+                Ok(DeviceEvent::Connected { serial, state: DeviceState::Device })
+                | Ok(DeviceEvent::StateChanged { serial, to: DeviceState::Device, .. }) => return Some(serial),
+                Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => {}
+                Err(broadcast::error::RecvError::Closed) => return None,
+            }
+        }
+    }
+    pub fn stop(&self) {
+        self.cancel.cancel();
+    }
+}
+impl Drop for DeviceWatcher {
+    fn drop(&mut self) {
+        self.cancel.cancel();
+    }
+}
+struct Tracker {
+    client: SharedTransport,
+    events: broadcast::Sender<DeviceEvent>,
+    devices: Arc<Mutex<HashMap<String, DeviceState>>>,
+    primed: Arc<AtomicBool>,
+}
+impl Tracker {
+    async fn run(&self) {
+        loop {
+            match self.client.track_devices().await {
+                Ok(Some(mut conn)) => {
+                    while let Ok(snapshot) = conn.read_length_prefixed().await {
+                        self.apply(&snapshot);
+                    }
+                    tokio::time::sleep(RECONNECT_DELAY).await;
+                }
+                _ => {
+                    if let Ok(snapshot) = self.client.devices_long().await {
+                        self.apply(&snapshot);
+                    }
+                    tokio::time::sleep(POLL_INTERVAL).await;
+                }
+            }
+        }
+    }
+    fn apply(&self, snapshot: &str) {
+        let current: HashMap<String, DeviceState> = parse_device_states(snapshot).into_iter().collect();
+        let Ok(mut known) = self.devices.lock() else {
+            return;
+        };
+        let mut events = Vec::new();
+        for (serial, state) in &current {
+            match known.get(serial) {
+                None => events.push(DeviceEvent::Connected {
+                    serial: serial.clone(),
+                    state: state.clone(),
+                }),
+                Some(prev) if prev != state => events.push(DeviceEvent::StateChanged {
+                    serial: serial.clone(),
+                    from: prev.clone(),
+                    to: state.clone(),
+                }),
+                Some(_) => {}
+            }
+        }
+        for (serial, state) in known.iter() {
+            if !current.contains_key(serial) {
+                events.push(DeviceEvent::Disconnected {
+                    serial: serial.clone(),
+                    last_state: state.clone(),
+                });
+            }
+        }
+        *known = current;
+        drop(known);
+        self.primed.store(true, Ordering::Relaxed);
+        for event in events {
+            let _ = self.events.send(event);
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adb::fake::FakeFixture;
+    use crate::adb::FakeTransport;
+    fn tracker() -> (Tracker, broadcast::Receiver<DeviceEvent>) {
+        let (events, rx) = broadcast::channel(EVENT_CAPACITY);
+        let tracker = Tracker {
+            client: Arc::new(FakeTransport::new(FakeFixture::default())),
+            events,
+            devices: Arc::new(Mutex::new(HashMap::new())),
+            primed: Arc::new(AtomicBool::new(false)),
+        };
+        (tracker, rx)
+    }
+    fn drain(rx: &mut broadcast::Receiver<DeviceEvent>) -> Vec<DeviceEvent> {
+        let mut events = Vec::new();
+        while let Ok(event) = rx.try_recv() {
+            events.push(event);
+        }
+        events.sort_by(|a, b| a.serial().cmp(b.serial()));
+        events
+    }
+    #[test]
+    fn successive_snapshots_produce_events() {
+        let (tracker, mut rx) = tracker();
+        assert!(!tracker.primed.load(Ordering::Relaxed));
+        tracker.apply("AAA\tunauthorized\n");
+        assert!(tracker.primed.load(Ordering::Relaxed));
+        assert_eq!(
+            drain(&mut rx),
+            vec![DeviceEvent::Connected {
+                serial: "AAA".to_string(),
+                state: DeviceState::Unauthorized,
+            }]
+        );
+        tracker.apply("AAA\tdevice\n192.168.1.5:5555\toffline\n");
+        assert_eq!(
+            drain(&mut rx),
+            vec![
+                DeviceEvent::Connected {
+                    serial: "192.168.1.5:5555".to_string(),
+                    state: DeviceState::Offline,
+                },
+                DeviceEvent::StateChanged {
+                    serial: "AAA".to_string(),
+                    from: DeviceState::Unauthorized,
+                    to: DeviceState::Device,
+                },
+            ]
+        );
+        // 相同的快照不产生事件
+        tracker.apply("AAA\tdevice\n192.168.1.5:5555\toffline\n");
+        assert!(drain(&mut rx).is_empty());
+        tracker.apply("192.168.1.5:5555\tdevice\n");
+        let events = drain(&mut rx);
+        assert_eq!(
+            events,
+            vec![
+                DeviceEvent::StateChanged {
+                    serial: "192.168.1.5:5555".to_string(),
+                    from: DeviceState::Offline,
+                    to: DeviceState::Device,
+                },
+                DeviceEvent::Disconnected {
+                    serial: "AAA".to_string(),
+                    last_state: DeviceState::Device,
+                },
+            ]
+        );
+        assert!(events[1].is_loss_of("AAA"));
+        assert!(!events[0].is_loss_of("192.168.1.5:5555"));
+        let known = tracker.devices.lock().unwrap().clone();
+        assert_eq!(known.len(), 1);
+        assert_eq!(known.get("192.168.1.5:5555"), Some(&DeviceState::Device));
+    }
+    #[test]
+    fn empty_snapshot_disconnects_everything() {
+        let (tracker, mut rx) = tracker();
+        tracker.apply("AAA\tdevice\nBBB\trecovery\n");
+        drain(&mut rx);
+        tracker.apply("");
+        let events = drain(&mut rx);
+        assert_eq!(events.len(), 2);
+        assert!(events.iter().all(|e| matches!(e, DeviceEvent::Disconnected { .. })));
+        assert!(tracker.devices.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,198 @@
+﻿use crate::core::{AdbError, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+pub const DEFAULT_TCPIP_PORT: u16 = 5555;
+pub const REMEMBERED_FILE: &str = "network_devices.json";
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NetworkDevice {
+    pub address: String,
+    #[serde(default)]
+    pub name: Option<String>,
+    pub last_connected: String,
+}
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RememberedDevices {
+    pub devices: Vec<NetworkDevice>,
+}
+impl RememberedDevices {
+    pub fn default_path() -> Option<PathBuf> {
+        crate::core::config::Config::user_dir().map(|dir| dir.join(REMEMBERED_FILE))
+    }
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.is_file() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&content)?)
+    }
+    pub fn save(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+    pub fn remember(&mut self, address: &str, name: Option<String>) {
+        let now = chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+        match self.devices.iter_mut().find(|d| d.address == address) {
+            Some(device) => {
+                device.last_connected = now;
+                if name.is_some() {
+                    device.name = name;
+                }
+            }
+            None => self.devices.push(NetworkDevice {
+                address: address.to_string(),
+                name,
+                last_connected: now,
+            }),
+        }
+    }
+    pub fn forget(&mut self, address: &str) -> bool {
+        let before = self.devices.len();
+        self.devices.retain(|d| d.address != address);
+        self.devices.len() != before
+    }
+}
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MdnsService {
+    pub name: String,
+    pub service_type: String,
+    pub address: String,
+}
+impl MdnsService {
+    pub fn is_pairing(&self) -> bool {
+        self.service_type.starts_with("_adb-tls-pairing")
+    }
+    pub fn is_connect(&self) -> bool {
+        self.service_type.starts_with("_adb-tls-connect") || self.service_type.starts_with("_adb._tcp")
+    }
+}
+pub fn parse_mdns_services(output: &str) -> Vec<MdnsService> {
+    output
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with("List of discovered"))
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            let name = parts.next()?;
+            let service_type = parts.next()?;
+            let address = parts.next()?;
+            Some(MdnsService {
+                name: name.to_string(),
+                service_type: service_type.trim_end_matches('.').to_string(),
+                address: address.to_string(),
+            })
+        })
+        .collect()
+}
+pub fn normalize_address(address: &str) -> String {
+    let address = address.trim();
+    let has_port = address
+        .rsplit_once(':')
+        .is_some_and(|(host, port)| port.parse::<u16>().is_ok() && (!host.contains(':') || host.ends_with(']')));
+    if has_port || address.contains("._adb") {
+        address.to_string()
+    } else if address.parse::<std::net::Ipv6Addr>().is_ok() {
+        // 不带端口的 IPv6 地址要加方括号，否则最后一段会被当成端口
+        format!("[{}]:{}", address, DEFAULT_TCPIP_PORT)
+    } else {
+        format!("{}:{}", address, DEFAULT_TCPIP_PORT)
+    }
+}
+pub fn is_network_serial(serial: &str) -> bool {
+    serial.contains(':') || serial.contains("._adb-tls-connect.")
+}
+pub fn check_pair_output(stdout: &str, stderr: &str) -> Result<String> {
+    let text = format!("{}{}", stdout, stderr);
+    match text.lines().find(|l| l.contains("Successfully paired")) {
+        Some(line) => Ok(line.trim().to_string()),
+        None => Err(failure(&text, "配对失败")),
+    }
+}
+pub fn check_connect_output(stdout: &str, stderr: &str) -> Result<String> {
+    let text = format!("{}{}", stdout, stderr);
+    let line = text.lines().map(str::trim).find(|l| !l.is_empty()).unwrap_or_default();
+    if line.starts_with("connected to") || line.starts_with("already connected to") {
+        Ok(line.to_string())
+    } else {
+        Err(failure(&text, "连接失败"))
+    }
+}
+fn failure(text: &str, fallback: &str) -> AdbError {
+    let detail = text.trim();
+    if detail.is_empty() {
+        return AdbError::CommandFailed(fallback.to_string());
+    }
+    match AdbError::classify(detail) {
+        AdbError::CommandFailed(_) => AdbError::CommandFailed(format!("{}: {}", fallback, detail)),
+        err => err,
+    }
+}
+pub fn parse_wlan_address(output: &str) -> Option<String> {
+    output.lines().map(str::trim).find_map(|line| {
+        let rest = line.strip_prefix("inet ")?;
+        let addr = rest.split(['/', ' ']).next()?;
+        (!addr.starts_with("127.")).then(|| addr.to_string())
+    })
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn mdns_services_are_parsed() {
+        let output = "List of discovered mdns services\n\
+                      adb-2B0ABC-xYz\t_adb-tls-connect._tcp.\t192.168.1.20:37099\n\
+                      adb-2B0ABC-xYz\t_adb-tls-pairing._tcp.\t192.168.1.20:41235\n\
+                      \n\
+                      truncated-line\t_adb._tcp.\n\
+                      emulator\t_adb._tcp.\t[fe80::1]:5555\n";
+        let services = parse_mdns_services(output);
+        assert_eq!(services.len(), 3);
+        assert_eq!(services[0].service_type, "_adb-tls-connect._tcp");
+        assert_eq!(services[0].address, "192.168.1.20:37099");
+        assert!(services[0].is_connect() && !services[0].is_pairing());
+        assert!(services[1].is_pairing() && !services[1].is_connect());
+        assert!(services[2].is_connect());
+        assert_eq!(services[2].address, "[fe80::1]:5555");
+        assert!(parse_mdns_services("List of discovered mdns services\n").is_empty());
+    }
+    #[test]
+    fn addresses_get_the_default_port_only_when_missing() {
+        assert_eq!(normalize_address(" 192.168.1.20 "), "192.168.1.20:5555");
+        assert_eq!(normalize_address("192.168.1.20:5556"), "192.168.1.20:5556");
+        assert_eq!(normalize_address("phone.lan"), "phone.lan:5555");
+        assert_eq!(normalize_address("fe80::1"), "[fe80::1]:5555");
+        assert_eq!(normalize_address("[fe80::1]"), "[fe80::1]:5555");
+        assert_eq!(normalize_address("[fe80::1]:5556"), "[fe80::1]:5556");
+        assert_eq!(
+            normalize_address("adb-2B0ABC-xYz._adb-tls-connect._tcp"),
+            "adb-2B0ABC-xYz._adb-tls-connect._tcp"
+        );
+        assert!(is_network_serial("192.168.1.20:5555"));
+        assert!(!is_network_serial("2B0ABC"));
+    }
+    #[test]
+    fn connect_output_is_checked_on_both_streams() {
+        assert_eq!(check_connect_output("connected to 192.168.1.20:5555\n", "").unwrap(), "connected to 192.168.1.20:5555");
+        assert!(check_connect_output("already connected to 192.168.1.20:5555\n", "").is_ok());
+        // adb connect 失败时仍以 0 退出，错误写在标准输出
+        let err = check_connect_output("failed to connect to '192.168.1.20:5555': Connection refused\n", "").unwrap_err();
+        assert!(err.to_string().contains("Connection refused"), "{}", err);
+        let err = check_connect_output("", "failed to authenticate to 192.168.1.20:5555\n").unwrap_err();
+        assert!(err.to_string().contains("failed to authenticate"), "{}", err);
+        assert!(matches!(check_connect_output("", ""), Err(AdbError::CommandFailed(msg)) if msg == "连接失败"));
+        assert!(check_pair_output("Successfully paired to 192.168.1.20:41235 [guid=adb-2B0ABC]\n", "").is_ok());
+        assert!(check_pair_output("Failed: Wrong password or connection was dropped.\n", "").is_err());
+    }
+    #[test]
+    fn wlan_address_skips_loopback() {
+        let output = "    inet 127.0.0.1/8 scope host lo\n\
+                      32: wlan0: <BROADCAST,MULTICAST,UP,LOWER_UP> mtu 1500\n\
+                      \x20   inet 192.168.1.20/24 brd 192.168.1.255 scope global wlan0\n\
+                      \x20   inet6 fe80::1/64 scope link\n";
+        assert_eq!(parse_wlan_address(output).as_deref(), Some("192.168.1.20"));
+        assert_eq!(parse_wlan_address("    inet6 fe80::1/64 scope link\n"), None);
+        assert_eq!(parse_wlan_address(""), None);
+    }
+}
@@ -1,4 +1,7 @@
-﻿use crate::adb::{AdbClient, DeviceManager};
+﻿use crate::adb::inventory;
+use crate::adb::{DeviceManager, SharedTransport};
+use crate::checks::rules;
+use crate::checks::{BootloaderReport, EmulatorChecker, EmulatorReport, HookChecker, HookReport, RootChecker, RootReport};
 use crate::core::Result;
 use serde::{Deserialize, Serialize};
 use tokio::fs;
@@ -10,6 +13,10 @@ pub struct AuditReport {
     pub security_env: SecurityEnv,
     pub hardware: HardwareInfo,
     pub integrity: IntegrityInfo,
+    pub root_check: RootReport,
+    pub bootloader_check: BootloaderReport,
+    pub hook_check: HookReport,
+    pub emulator_check: EmulatorReport,
 }
 #[derive(Debug, Serialize, Deserialize)]
 pub struct DeviceInfo {
@@ -50,55 +57,107 @@ pub struct IntegrityInfo {
     pub build_tags: String,
     pub build_type: String,
 }
+impl AuditReport {
+    pub fn summary(&self) -> String {
+        let mut out = String::new();
+        out.push_str("\n审计报告:\n");
+        out.push_str(&format!("设备: {} {}\n", self.device_info.brand, self.device_info.marketing_name));
+        out.push_str(&format!("Android: {} (SDK {})\n", self.device_info.android, self.device_info.sdk));
+        out.push_str(&format!("Root 检测: {} (su={}, 可疑包={})\n",
+            self.root_check.verdict.label(),
+            if self.root.su_in_path.is_empty() { "否" } else { "是" },
+            self.root.suspicious_packages.len()));
+        for f in self.root_check.hits() {
+            out.push_str(&format!("  {}\n", f.render()));
+        }
+        out.push_str(&format!("Hook/注入框架: {}\n", self.hook_check.verdict.label()));
+        for f in self.hook_check.hits() {
+            out.push_str(&format!("  {}\n", f.render()));
+        }
+        out.push_str(&format!("模拟器检测: {} (得分 {})\n", self.emulator_check.verdict.label(), self.emulator_check.score));
+        for f in self.emulator_check.hits() {
+            out.push_str(&format!("  {}\n", f.render()));
+        }
+        out.push_str(&format!("Bootloader: {} (verifiedbootstate={})\n",
+            self.bootloader_check.analysis, self.boot.verifiedbootstate));
+        out.push_str(&format!("SELinux: {}\n", self.security_env.selinux));
+        out.push_str(&format!("安全补丁: {}\n", self.integrity.security_patch));
+        out
+    }
+    // 与各单项检测的退出码一致：任一检测判定命中即视为有发现
+    pub fn detected(&self) -> bool {
+        self.root_check.detected || self.bootloader_check.detected || self.hook_check.detected || self.emulator_check.detected
+    }
+    pub fn brief(&self) -> String {
+        format!(
+            "{}, {}, {}, {}, SELinux={}",
+            self.root_check.verdict.label(),
+            self.hook_check.verdict.label(),
+            self.emulator_check.verdict.label(),
+            self.bootloader_check.analysis,
+            self.security_env.selinux
+        )
+    }
+}
 pub struct AuditRunner {
-    client: AdbClient,
+    client: SharedTransport,
     device_mgr: DeviceManager,
+    root_checker: RootChecker,
+    hook_checker: HookChecker,
+    emulator_checker: EmulatorChecker,
 }
 impl AuditRunner {
-    pub fn new(c: AdbClient) -> Self {
-        let dm = DeviceManager::new(c.clone());
+    pub fn new(dm: DeviceManager) -> Self {
         Self {
-            client: c,
+            root_checker: RootChecker::new(dm.clone()),
+            hook_checker: HookChecker::new(dm.clone()),
+            emulator_checker: EmulatorChecker::new(dm.clone()),
+            client: dm.client().clone(),
             device_mgr: dm,
         }
     }
+    pub fn with_root_checker(mut self, checker: RootChecker) -> Self {
+        self.root_checker = checker;
+        self
+    }
+    pub fn with_hook_checker(mut self, checker: HookChecker) -> Self {
+        self.hook_checker = checker;
+        self
+    }
+    pub fn with_emulator_checker(mut self, checker: EmulatorChecker) -> Self {
+        self.emulator_checker = checker;
+        self
+    }
     pub async fn run(&self, serial: &str, save_dir: Option<&str>) -> Result<AuditReport> {
-        let props = self.device_mgr.get_properties(serial).await?;
-        let pkgs = self.device_mgr.get_packages(serial).await?;
+        let props = self.device_mgr.properties(serial).await?;
         let hw_cmds = vec![
             vec!["dumpsys".to_string(), "battery".to_string()],
             vec!["dumpsys".to_string(), "thermalservice".to_string()],
         ];
         let hw_out = self.client.parallel_shell(serial, hw_cmds).await;
+        let root_check = self.root_checker.detect(serial).await?;
+        let hook_check = self.hook_checker.detect(serial).await?;
+        let emulator_check = self.emulator_checker.detect(serial).await?;
         let report = AuditReport {
             device_info: DeviceInfo {
-                brand: props.get("ro.product.brand").cloned().unwrap_or_default(),
-                marketing_name: props.get("ro.product.marketname").cloned().unwrap_or_default(),
-                model: props.get("ro.product.model").cloned().unwrap_or_default(),
-                android: props.get("ro.build.version.release").cloned().unwrap_or_default(),
-                sdk: props.get("ro.build.version.sdk").cloned().unwrap_or_default(),
+                brand: props.string("ro.product.brand"),
+                marketing_name: props.string("ro.product.marketname"),
+                model: props.string("ro.product.model"),
+                android: props.string("ro.build.version.release"),
+                sdk: props.sdk().map(|sdk| sdk.to_string()).unwrap_or_default(),
             },
             root: RootInfo {
-                su_in_path: self
-                    .client
-                    .try_shell(serial, &["which", "su"])
-                    .await
-                    .ok()
-                    .and_then(|(ok, out, _)| if ok { Some(out.trim().to_string()) } else { None })
-                    .unwrap_or_default(),
-                suspicious_packages: pkgs
-                    .iter()
-                    .filter(|p| {
-                        p.contains("magisk") || p.contains("lsposed") || p.contains("supersu") || p.contains("busybox")
-                    })
-                    .cloned()
-                    .collect(),
+                su_in_path: root_check.evidence_of("su-in-path").join(", "),
+                suspicious_packages: root_check.evidence_of(rules::SUSPICIOUS_PACKAGES_RULE),
             },
             boot: BootInfo {
-                verifiedbootstate: props.get("ro.boot.verifiedbootstate").cloned().unwrap_or_default(),
-                vbmeta_device_state: props.get("ro.boot.vbmeta.device_state").cloned().unwrap_or_default(),
-                flash_locked: props.get("ro.boot.flash.locked").cloned().unwrap_or_default(),
-                veritymode: props.get("ro.boot.veritymode").cloned().unwrap_or_default(),
+                verifiedbootstate: props
+                    .verified_boot_state()
+                    .map(|state| state.as_str().to_string())
+                    .unwrap_or_default(),
+                vbmeta_device_state: props.string("ro.boot.vbmeta.device_state"),
+                flash_locked: props.string("ro.boot.flash.locked"),
+                veritymode: props.string("ro.boot.veritymode"),
             },
             security_env: SecurityEnv {
                 selinux: self
@@ -114,21 +173,40 @@ impl AuditRunner {
                         }
                     })
                     .unwrap_or_else(|| "unknown".to_string()),
-                crypto_state: props.get("ro.crypto.state").cloned().unwrap_or_default(),
-                crypto_type: props.get("ro.crypto.type").cloned().unwrap_or_default(),
-                debuggable: props.get("ro.debuggable").cloned().unwrap_or_default(),
-                secure: props.get("ro.secure").cloned().unwrap_or_default(),
+                crypto_state: props.string("ro.crypto.state"),
+                crypto_type: props.string("ro.crypto.type"),
+                debuggable: props.string("ro.debuggable"),
+                secure: props.string("ro.secure"),
             },
             hardware: HardwareInfo {
-                battery: hw_out.get(0).and_then(|r| r.as_ref().ok().cloned()).unwrap_or_default(),
+                battery: hw_out.first().and_then(|r| r.as_ref().ok().cloned()).unwrap_or_default(),
                 thermal: hw_out.get(1).and_then(|r| r.as_ref().ok().cloned()).unwrap_or_default(),
             },
             integrity: IntegrityInfo {
-                security_patch: props.get("ro.build.version.security_patch").cloned().unwrap_or_default(),
-                build_tags: props.get("ro.build.tags").cloned().unwrap_or_default(),
-                build_type: props.get("ro.build.type").cloned().unwrap_or_default(),
+                // 补丁日期统一成 YYYY-MM-DD，无法解析时保留原值
+                security_patch: props
+                    .version("ro.build.version.security_patch")
+                    .map(|v| format!("{:04}-{:02}-{:02}", v.major, v.minor, v.patch))
+                    .unwrap_or_else(|| props.string("ro.build.version.security_patch")),
+                build_tags: props.string("ro.build.tags"),
+                build_type: props.string("ro.build.type"),
             },
+            root_check,
+            bootloader_check: BootloaderReport::from_properties(serial, &props),
+            hook_check,
+            emulator_check,
         };
+        let brief = report.brief();
+        let locked = report.bootloader_check.verdict.is_locked();
+        // 审计结果已生成，资产清单写入失败时不影响返回
+        let _ = self.device_mgr.update_inventory(serial, |record| {
+            record.apply_properties(&props);
+            if let Some(locked) = locked {
+                record.bootloader = Some(if locked { "locked" } else { "unlocked" }.to_string());
+            }
+            record.last_audit = Some(brief);
+            record.last_audit_at = Some(inventory::now());
+        });
         if let Some(dir) = save_dir {
             let ts = chrono::Local::now().format("%Y%m%d-%H%M%S");
             let path = format!("{}/audit-{}.json", dir, ts);
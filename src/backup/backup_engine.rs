@@ -1,7 +1,9 @@
-﻿use crate::adb::{AdbClient, DeviceManager};
-use crate::core::Result;
+﻿use crate::adb::inventory;
+use crate::adb::{DeviceManager, SharedTransport};
+use crate::core::{AdbError, Result};
 use crate::ui::ConsoleUi;
-use super::models::{BackupItem, BackupMetadata};
+use super::models::{show_progress, sync_options, BackupItem, BackupMetadata, DeviceProgress, TRANSFER_TIMEOUT};
+use crate::adb::sync::{ProgressFn, SyncOptions, TransferProgress, SYNC_MANIFEST};
 use super::root_checker::RootChecker;
 use chrono::Local;
 use flate2::write::GzEncoder;
@@ -10,26 +12,48 @@ use std::fs::{self, File};
 use std::path::{Path, PathBuf};
 use tar::Builder;
 pub struct BackupEngine {
-    client: AdbClient,
+    client: SharedTransport,
     device_mgr: DeviceManager,
     root_checker: RootChecker,
+    output_dir: Option<PathBuf>,
+    sync: SyncOptions,
+    device_progress: bool,
 }
 impl BackupEngine {
-    pub fn new(client: AdbClient) -> Self {
-        let device_mgr = DeviceManager::new(client.clone());
+    pub fn new(device_mgr: DeviceManager) -> Self {
+        let client = device_mgr.client().clone();
         let root_checker = RootChecker::new(client.clone());
         Self {
             client,
             device_mgr,
             root_checker,
+            output_dir: None,
+            sync: sync_options(),
+            device_progress: false,
         }
     }
+    pub fn with_output_dir(mut self, dir: PathBuf) -> Self {
+        self.output_dir = Some(dir);
+        self
+    }
+    pub fn with_verify(mut self, verify: bool) -> Self {
+        self.sync.verify = verify;
+        self
+    }
+    pub fn with_device_progress(mut self, enabled: bool) -> Self {
+        self.device_progress = enabled;
+        self
+    }
     pub async fn start_backup(
         &self,
         serial: &str,
         items: Vec<BackupItem>,
     ) -> Result<PathBuf> {
-        let has_root = self.root_checker.has_root(serial).await.unwrap_or(false);
+        let has_root = match self.root_checker.has_root(serial).await {
+            Ok(has_root) => has_root,
+            Err(e) if e.is_device_unavailable() => return Err(e),
+            Err(_) => false,
+        };
         if !has_root {
             ConsoleUi::warn("未检测到 Root 权限，将跳过需要 Root 的项目");
         } else {
@@ -41,18 +65,16 @@ impl BackupEngine {
             .collect();
         if items.is_empty() {
             ConsoleUi::error("没有可备份的项目");
-            return Err(crate::core::AdbError::CommandFailed(
+            return Err(AdbError::CommandFailed(
                 "No items to backup".to_string(),
-            )
-            .into());
+            ));
         }
         let device_info = self.get_device_info(serial).await?;
         let backup_dir = self.create_backup_dir()?;
-        let temp_dir = backup_dir.join(format!(
-            "temp_{}_{}",
-            Local::now().format("%Y%m%d_%H%M%S"),
-            std::process::id()
-        ));
+        let temp_dir = backup_dir.join(format!("partial_{}", serial.replace([':', '/', '\\'], "_")));
+        if temp_dir.exists() {
+            ConsoleUi::info("发现未完成的备份，将继续传输");
+        }
         fs::create_dir_all(&temp_dir)?;
         ConsoleUi::info(&format!("备份目录: {}", backup_dir.display()));
         for item in &items {
@@ -72,25 +94,43 @@ impl BackupEngine {
         let metadata_file = temp_dir.join("metadata.json");
         fs::write(&metadata_file, metadata_json)?;
         ConsoleUi::info("正在压缩备份文件...");
+        let _ = fs::remove_file(temp_dir.join("sdcard").join(SYNC_MANIFEST));
         let backup_file = self.create_archive(&temp_dir, &backup_dir, serial)?;
         fs::remove_dir_all(&temp_dir)?;
         ConsoleUi::success(&format!("备份完成: {}", backup_file.display()));
+        let archive = backup_file.display().to_string();
+        if let Err(e) = self.device_mgr.update_inventory(serial, |record| {
+            record.last_backup = Some(archive);
+            record.last_backup_at = Some(inventory::now());
+        }) {
+            ConsoleUi::warn(&format!("资产清单更新失败: {}", e));
+        }
         Ok(backup_file)
     }
     async fn get_device_info(&self, serial: &str) -> Result<(String, String)> {
-        let props = self.device_mgr.get_properties(serial).await?;
-        let model = props
-            .get("ro.product.model")
-            .or(props.get("ro.product.device"))
-            .cloned()
-            .unwrap_or_else(|| "Unknown".to_string());
-        let android = props
-            .get("ro.build.version.release")
-            .cloned()
-            .unwrap_or_else(|| "Unknown".to_string());
-        Ok((model, android))
+        let props = self.device_mgr.properties(serial).await?;
+        if props.is_empty() {
+            // 完整的 getprop 没有输出时逐个读取
+            let model = self.device_mgr.get_property(serial, "ro.product.model").await?;
+            let android = self.device_mgr.get_property(serial, "ro.build.version.release").await?;
+            return Ok((
+                model.unwrap_or_else(|| "Unknown".to_string()),
+                android.unwrap_or_else(|| "Unknown".to_string()),
+            ));
+        }
+        let model = props.first(&["ro.product.model", "ro.product.device"]).unwrap_or("Unknown");
+        let android = match (props.get("ro.build.version.release"), props.sdk()) {
+            (Some(release), Some(sdk)) => format!("{} (SDK {})", release, sdk),
+            (Some(release), None) => release.to_string(),
+            (None, _) => "Unknown".to_string(),
+        };
+        Ok((model.to_string(), android))
     }
     fn create_backup_dir(&self) -> Result<PathBuf> {
+        if let Some(dir) = &self.output_dir {
+            fs::create_dir_all(dir)?;
+            return Ok(dir.clone());
+        }
         let base_dir = std::env::current_exe()?
             .parent()
             .ok_or_else(|| {
@@ -111,22 +151,34 @@ impl BackupEngine {
             BackupItem::UserFiles => self.backup_user_files(serial, temp_dir).await,
             BackupItem::AppList => self.backup_app_list(serial, temp_dir).await,
             BackupItem::AppData => self.backup_app_data(serial, temp_dir, has_root).await,
-            BackupItem::SystemSettings => self.backup_system_settings(serial, temp_dir).await,
+            BackupItem::SystemSettings => self.backup_system_settings(serial, temp_dir, has_root).await,
         }
     }
     async fn backup_user_files(&self, serial: &str, temp_dir: &Path) -> Result<()> {
         let target = temp_dir.join("sdcard");
         fs::create_dir_all(&target)?;
         ConsoleUi::info("  拉取 /sdcard/ 目录...");
-        let target_str = target.to_str().unwrap();
-        let (_stdout, stderr) = self
-            .client
-            .execute(&["-s", serial, "pull", "/sdcard/", target_str])
-            .await?;
-        if !stderr.is_empty() && !stderr.contains("pulled") {
-            ConsoleUi::warn(&format!("  警告: {}", stderr));
+        let device_progress = DeviceProgress::new(serial);
+        let per_device = |p: &TransferProgress| device_progress.report(p);
+        let progress: ProgressFn<'_> = if self.device_progress { &per_device } else { &show_progress };
+        let result = self.client.pull(serial, "/sdcard", &target, &self.sync, progress).await;
+        ConsoleUi::progress_done();
+        match result {
+            Ok(report) => {
+                for failure in &report.failures {
+                    ConsoleUi::warn(&format!("  跳过 {}: {}", failure.path, failure.error));
+                }
+                ConsoleUi::success(&format!("  用户文件备份完成: {}", report.summary()));
+            }
+            Err(AdbError::PermissionDenied(msg)) => {
+                ConsoleUi::warn(&format!("  部分文件无读取权限，已跳过: {}", msg));
+                ConsoleUi::success("  用户文件备份完成");
+            }
+            Err(e) => {
+                ConsoleUi::warn("  传输中断，重新执行备份可从断点继续");
+                return Err(e);
+            }
         }
-        ConsoleUi::success("  用户文件备份完成");
         Ok(())
     }
     async fn backup_app_list(&self, serial: &str, temp_dir: &Path) -> Result<()> {
@@ -148,28 +200,39 @@ impl BackupEngine {
         let backup_file_str = backup_file.to_str().unwrap();
         let result = self
             .client
-            .try_execute(&[
-                "-s",
-                serial,
-                "backup",
-                "-apk",
-                "-shared",
-                "-all",
-                "-f",
-                backup_file_str,
-            ])
+            .try_execute_with_timeout(
+                &[
+                    "-s",
+                    serial,
+                    "backup",
+                    "-apk",
+                    "-shared",
+                    "-all",
+                    "-f",
+                    backup_file_str,
+                ],
+                TRANSFER_TIMEOUT,
+            )
             .await;
         match result {
             Ok((true, _, _)) => {
                 ConsoleUi::success("  应用数据备份完成");
             }
-            _ => {
+            Ok((false, _, stderr)) => {
+                let err = AdbError::classify(&stderr);
+                if err.is_device_unavailable() {
+                    return Err(err);
+                }
+                ConsoleUi::warn("  应用数据备份失败或被取消");
+            }
+            Err(e) if e.is_device_unavailable() => return Err(e),
+            Err(_) => {
                 ConsoleUi::warn("  应用数据备份失败或被取消");
             }
         }
         Ok(())
     }
-    async fn backup_system_settings(&self, serial: &str, temp_dir: &Path) -> Result<()> {
+    async fn backup_system_settings(&self, serial: &str, temp_dir: &Path, has_root: bool) -> Result<()> {
         ConsoleUi::info("  备份系统设置数据库...");
         let target = temp_dir.join("system_settings");
         fs::create_dir_all(&target)?;
@@ -189,10 +252,29 @@ impl BackupEngine {
             let target_str = target_file.to_str().unwrap();
             let result = self
                 .client
-                .try_execute(&["-s", serial, "pull", db_file, target_str])
+                .try_execute_with_timeout(&["-s", serial, "pull", db_file, target_str], TRANSFER_TIMEOUT)
                 .await;
-            if result.is_ok_and(|(success, _, _)| success) {
-                success_count += 1;
+            match result {
+                Ok((true, _, _)) => success_count += 1,
+                Ok((false, _, stderr)) => match AdbError::classify(&stderr) {
+                    AdbError::PermissionDenied(_) if has_root => {
+                        match self.device_mgr.read_file_as_root(serial, db_file).await {
+                            Ok(bytes) if !bytes.is_empty() => {
+                                fs::write(&target_file, bytes)?;
+                                success_count += 1;
+                            }
+                            Err(e) if e.is_device_unavailable() => return Err(e),
+                            _ => ConsoleUi::warn(&format!("  {} 读取失败", filename)),
+                        }
+                    }
+                    AdbError::PermissionDenied(_) => {
+                        ConsoleUi::warn(&format!("  {} 无读取权限", filename));
+                    }
+                    err if err.is_device_unavailable() => return Err(err),
+                    _ => {}
+                },
+                Err(e) if e.is_device_unavailable() => return Err(e),
+                Err(_) => {}
             }
         }
         ConsoleUi::success(&format!("  系统设置备份完成 ({}/{} 个文件)", success_count, total_files));
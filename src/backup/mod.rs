@@ -5,7 +5,7 @@ mod root_checker;
 pub use backup_engine::BackupEngine;
 pub use models::{BackupItem, RestoreMode};
 pub use restore_engine::RestoreEngine;
-use crate::adb::AdbClient;
+use crate::adb::DeviceManager;
 use crate::core::Result;
 use crate::ui::ConsoleUi;
 pub struct BackupRunner {
@@ -13,14 +13,25 @@ pub struct BackupRunner {
     rst_eng: RestoreEngine,
 }
 impl BackupRunner {
-    pub fn new(c: AdbClient) -> Self {
-        let be = BackupEngine::new(c.clone());
-        let re = RestoreEngine::new(c);
+    pub fn new(dm: DeviceManager) -> Self {
+        let re = RestoreEngine::new(dm.client().clone());
+        let be = BackupEngine::new(dm);
         Self {
             bak_eng: be,
             rst_eng: re,
         }
     }
+    pub fn with_output_dir(mut self, dir: Option<std::path::PathBuf>) -> Self {
+        if let Some(dir) = dir {
+            self.bak_eng = self.bak_eng.with_output_dir(dir);
+        }
+        self
+    }
+    pub fn with_verify(mut self, verify: bool) -> Self {
+        self.bak_eng = self.bak_eng.with_verify(verify);
+        self.rst_eng = self.rst_eng.with_verify(verify);
+        self
+    }
     pub async fn show_menu(&self, serial: &str) -> Result<()> {
         loop {
             println!("\n=== 备份与恢复 ===");
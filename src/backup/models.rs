@@ -1,4 +1,46 @@
-﻿use serde::{Deserialize, Serialize};
+﻿use crate::adb::sync::{SyncOptions, TransferProgress};
+use crate::ui::ConsoleUi;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+pub const TRANSFER_TIMEOUT: Duration = Duration::from_secs(6 * 60 * 60);
+pub fn sync_options() -> SyncOptions {
+    SyncOptions {
+        resume: true,
+        verify: false,
+        timeout: TRANSFER_TIMEOUT,
+    }
+}
+pub fn show_progress(p: &TransferProgress) {
+    let percent = (p.bytes_done * 100).checked_div(p.bytes_total).unwrap_or(100);
+    ConsoleUi::progress(&format!(
+        "  [{}/{}] {:>3}% {}",
+        p.files_done, p.files_total, percent, p.file
+    ));
+}
+// 批量模式下多台设备并行传输，单行刷新的进度会互相覆盖，改为按设备逐行输出，每前进 10% 输出一次
+pub struct DeviceProgress {
+    serial: String,
+    reported: AtomicU64,
+}
+impl DeviceProgress {
+    pub fn new(serial: &str) -> Self {
+        Self {
+            serial: serial.to_string(),
+            reported: AtomicU64::new(0),
+        }
+    }
+    pub fn report(&self, p: &TransferProgress) {
+        let percent = (p.bytes_done * 100).checked_div(p.bytes_total).unwrap_or(100);
+        let step = percent / 10 * 10;
+        if step > 0 && self.reported.fetch_max(step, Ordering::Relaxed) < step {
+            ConsoleUi::info(&format!(
+                "[{}] 传输 {:>3}% [{}/{}] {}",
+                self.serial, step, p.files_done, p.files_total, p.file
+            ));
+        }
+    }
+}
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum BackupItem {
     UserFiles,
@@ -15,6 +57,17 @@ impl BackupItem {
             Self::SystemSettings => "系统设置数据库 (需Root)",
         }
     }
+    pub fn key(&self) -> &str {
+        match self {
+            Self::UserFiles => "user-files",
+            Self::AppList => "app-list",
+            Self::AppData => "app-data",
+            Self::SystemSettings => "settings",
+        }
+    }
+    pub fn from_key(key: &str) -> Option<Self> {
+        Self::all_items().into_iter().find(|item| item.key() == key)
+    }
     pub fn requires_root(&self) -> bool {
         matches!(self, Self::SystemSettings)
     }
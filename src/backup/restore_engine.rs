@@ -1,18 +1,27 @@
-﻿use crate::adb::AdbClient;
+﻿use crate::adb::SharedTransport;
 use crate::core::Result;
 use crate::ui::ConsoleUi;
-use super::models::{BackupItem, BackupMetadata, RestoreMode};
+use super::models::{show_progress, sync_options, BackupItem, BackupMetadata, RestoreMode, TRANSFER_TIMEOUT};
+use crate::adb::sync::SyncOptions;
 use flate2::read::GzDecoder;
 use std::fs::{self, File};
 use std::io::Read;
 use std::path::{Path, PathBuf};
 use tar::Archive;
 pub struct RestoreEngine {
-    client: AdbClient,
+    client: SharedTransport,
+    sync: SyncOptions,
 }
 impl RestoreEngine {
-    pub fn new(client: AdbClient) -> Self {
-        Self { client }
+    pub fn new(client: SharedTransport) -> Self {
+        Self {
+            client,
+            sync: sync_options(),
+        }
+    }
+    pub fn with_verify(mut self, verify: bool) -> Self {
+        self.sync.verify = verify;
+        self
     }
     pub async fn start_restore(
         &self,
@@ -23,8 +32,7 @@ impl RestoreEngine {
         if !backup_file.exists() {
             return Err(crate::core::AdbError::CommandFailed(
                 "备份文件不存在".to_string(),
-            )
-            .into());
+            ));
         }
         if backup_file.extension().and_then(|e| e.to_str()) != Some("adbbackup") {
             ConsoleUi::warn("文件扩展名不是 .adbbackup，将尝试作为 tar.gz 处理");
@@ -90,15 +98,16 @@ impl RestoreEngine {
             return Ok(());
         }
         ConsoleUi::info("  推送文件到 /sdcard/ ...");
-        let source_str = source.to_str().unwrap();
-        let (_, stderr) = self
+        let result = self
             .client
-            .execute(&["-s", serial, "push", source_str, "/sdcard/"])
-            .await?;
-        if !stderr.is_empty() && !stderr.contains("pushed") {
-            ConsoleUi::warn(&format!("  警告: {}", stderr));
+            .push(serial, &source, "/sdcard", &self.sync, &show_progress)
+            .await;
+        ConsoleUi::progress_done();
+        let report = result?;
+        for failure in &report.failures {
+            ConsoleUi::warn(&format!("  失败 {}: {}", failure.path, failure.error));
         }
-        ConsoleUi::success("  用户文件恢复完成");
+        ConsoleUi::success(&format!("  用户文件恢复完成: {}", report.summary()));
         Ok(())
     }
     async fn restore_app_list(&self, _serial: &str, temp_dir: &Path) -> Result<()> {
@@ -124,7 +133,7 @@ impl RestoreEngine {
         let backup_file_str = backup_file.to_str().unwrap();
         let result = self
             .client
-            .try_execute(&["-s", serial, "restore", backup_file_str])
+            .try_execute_with_timeout(&["-s", serial, "restore", backup_file_str], TRANSFER_TIMEOUT)
             .await;
         match result {
             Ok((true, _, _)) => {
@@ -150,8 +159,7 @@ impl RestoreEngine {
         if !backup_file.exists() {
             return Err(crate::core::AdbError::CommandFailed(
                 "备份文件不存在".to_string(),
-            )
-            .into());
+            ));
         }
         let temp_dir = self.extract_backup(backup_file)?;
         let metadata = self.read_metadata(&temp_dir)?;
@@ -1,10 +1,10 @@
-﻿use crate::adb::AdbClient;
+﻿use crate::adb::SharedTransport;
 use crate::core::Result;
 pub struct RootChecker {
-    client: AdbClient,
+    client: SharedTransport,
 }
 impl RootChecker {
-    pub fn new(client: AdbClient) -> Self {
+    pub fn new(client: SharedTransport) -> Self {
         Self { client }
     }
     pub async fn has_root(&self, serial: &str) -> Result<bool> {
@@ -28,6 +28,8 @@ impl RootChecker {
             .await?;
         Ok(success3 && output3.contains("uid=0"))
     }
+    // 保留的公开接口，命令行暂未使用
+    #[allow(dead_code)]
     pub async fn request_root(&self, serial: &str) -> Result<bool> {
         let (success, _, _) = self
             .client
@@ -0,0 +1,264 @@
+﻿use crate::adb::{Device, DeviceManager, SharedTransport};
+use crate::audit::AuditRunner;
+use crate::backup::{BackupEngine, BackupItem};
+use crate::checks::{BootloaderChecker, EmulatorChecker, HookChecker, RootChecker};
+use crate::core::config::Config;
+use crate::core::Result;
+use crate::stress::StressRunner;
+use crate::ui::ConsoleUi;
+use futures::StreamExt;
+use serde::Serialize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum BatchOperation {
+    RootCheck,
+    Bootloader,
+    HookCheck,
+    EmulatorCheck,
+    Audit,
+    Backup,
+    Stress,
+}
+impl BatchOperation {
+    pub fn all() -> [Self; 7] {
+        [
+            Self::RootCheck,
+            Self::Bootloader,
+            Self::HookCheck,
+            Self::EmulatorCheck,
+            Self::Audit,
+            Self::Backup,
+            Self::Stress,
+        ]
+    }
+    pub fn from_key(key: &str) -> Option<Self> {
+        match key {
+            "root-check" => Some(Self::RootCheck),
+            "bootloader" => Some(Self::Bootloader),
+            "hook-check" => Some(Self::HookCheck),
+            "emulator-check" => Some(Self::EmulatorCheck),
+            "audit" => Some(Self::Audit),
+            "backup" => Some(Self::Backup),
+            "stress" => Some(Self::Stress),
+            _ => None,
+        }
+    }
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::RootCheck => "Root 环境检测",
+            Self::Bootloader => "引导/BL 锁状态",
+            Self::HookCheck => "Hook/注入框架检测",
+            Self::EmulatorCheck => "模拟器/虚拟设备检测",
+            Self::Audit => "系统与硬件安全检查",
+            Self::Backup => "完整备份",
+            Self::Stress => "ADB 稳定性测试",
+        }
+    }
+}
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchResult {
+    pub serial: String,
+    pub device: String,
+    pub ok: bool,
+    pub findings: bool,
+    pub summary: String,
+    pub elapsed_secs: f64,
+}
+pub struct BatchRunner {
+    client: SharedTransport,
+    device_mgr: DeviceManager,
+    config: Config,
+    concurrency: usize,
+}
+impl BatchRunner {
+    pub fn new(device_mgr: DeviceManager, config: Config) -> Self {
+        let concurrency = config.batch_concurrency;
+        Self {
+            client: device_mgr.client().clone(),
+            device_mgr,
+            config,
+            concurrency,
+        }
+    }
+    pub fn with_concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+    pub async fn run(&self, devices: &[Device], op: BatchOperation) -> Vec<BatchResult> {
+        let total = devices.len();
+        let finished = AtomicUsize::new(0);
+        ConsoleUi::info(&format!(
+            "批量执行 {}: {} 台设备, 并发上限 {}",
+            op.label(),
+            total,
+            self.concurrency
+        ));
+        ConsoleUi::suspend_progress(true);
+        let tasks = devices.iter().enumerate().map(|(idx, device)| {
+            let finished = &finished;
+            async move {
+                ConsoleUi::info(&format!("[{}] 开始 {}", device.serial, op.label()));
+                let t0 = Instant::now();
+                let outcome = self.run_one(&device.serial, op).await;
+                let done = finished.fetch_add(1, Ordering::Relaxed) + 1;
+                let result = BatchResult {
+                    serial: device.serial.clone(),
+                    device: device.display_name(),
+                    ok: outcome.is_ok(),
+                    findings: matches!(outcome, Ok((true, _))),
+                    summary: match outcome {
+                        Ok((_, summary)) => summary,
+                        Err(e) => e.to_string(),
+                    },
+                    elapsed_secs: t0.elapsed().as_secs_f64(),
+                };
+                let line = format!(
+                    "[{}/{}] {} {}: {} ({:.1}s)",
+                    done,
+                    total,
+                    result.serial,
+                    if result.ok { "完成" } else { "失败" },
+                    result.summary,
+                    result.elapsed_secs
+                );
+                if result.ok {
+                    ConsoleUi::success(&line);
+                } else {
+                    ConsoleUi::warn(&line);
+                }
+                (idx, result)
+            }
+        });
+        let mut results: Vec<(usize, BatchResult)> = futures::stream::iter(tasks)
+            .buffer_unordered(self.concurrency.max(1))
+            .collect()
+            .await;
+        ConsoleUi::suspend_progress(false);
+        results.sort_by_key(|(idx, _)| *idx);
+        results.into_iter().map(|(_, r)| r).collect()
+    }
+    async fn run_one(&self, serial: &str, op: BatchOperation) -> Result<(bool, String)> {
+        let dm = self.device_mgr.scoped();
+        match op {
+            BatchOperation::RootCheck => {
+                let checker = RootChecker::from_config(dm, &self.config)?;
+                let report = checker.detect(serial).await?;
+                let hits: Vec<&str> = report.hits().map(|f| f.title.as_str()).collect();
+                let summary = if hits.is_empty() {
+                    report.verdict.label().to_string()
+                } else {
+                    format!("{} ({})", report.verdict.label(), hits.join("; "))
+                };
+                Ok((report.detected, summary))
+            }
+            BatchOperation::Bootloader => {
+                let checker = BootloaderChecker::new(dm);
+                let report = checker.inspect(serial).await?;
+                Ok((report.detected, report.analysis))
+            }
+            BatchOperation::HookCheck => {
+                let checker = HookChecker::from_config(dm, &self.config)?;
+                let report = checker.detect(serial).await?;
+                let hits: Vec<&str> = report.hits().map(|f| f.title.as_str()).collect();
+                let summary = if hits.is_empty() {
+                    report.verdict.label().to_string()
+                } else {
+                    format!("{} ({})", report.verdict.label(), hits.join("; "))
+                };
+                Ok((report.detected, summary))
+            }
+            BatchOperation::EmulatorCheck => {
+                let checker = EmulatorChecker::from_config(dm, &self.config)?;
+                let report = checker.detect(serial).await?;
+                let signals: Vec<&str> = report.signals.iter().map(|s| s.title.as_str()).collect();
+                let summary = if signals.is_empty() {
+                    report.verdict.label().to_string()
+                } else {
+                    format!("{} (得分 {}: {})", report.verdict.label(), report.score, signals.join("; "))
+                };
+                Ok((report.detected, summary))
+            }
+            BatchOperation::Audit => {
+                let report = AuditRunner::new(dm.clone())
+                    .with_root_checker(RootChecker::from_config(dm.clone(), &self.config)?)
+                    .with_hook_checker(HookChecker::from_config(dm.clone(), &self.config)?)
+                    .with_emulator_checker(EmulatorChecker::from_config(dm, &self.config)?)
+                    .run(serial, None)
+                    .await?;
+                let summary = format!("{}, 补丁 {}", report.brief(), report.integrity.security_patch);
+                Ok((report.detected(), summary))
+            }
+            BatchOperation::Backup => {
+                let mut engine = BackupEngine::new(dm)
+                    .with_verify(self.config.verify_transfers)
+                    .with_device_progress(true);
+                if let Some(dir) = &self.config.backup_dir {
+                    engine = engine.with_output_dir(dir.clone());
+                }
+                let archive = engine.start_backup(serial, BackupItem::all_items()).await?;
+                Ok((false, archive.display().to_string()))
+            }
+            BatchOperation::Stress => {
+                let secs = self.config.stress_duration_secs;
+                let (ok, fail) = StressRunner::new(self.client.clone())
+                    .with_device_progress(true)
+                    .adb_stability_test(serial, secs)
+                    .await?;
+                Ok((fail > 0, format!("成功 {} 次, 失败 {} 次", ok, fail)))
+            }
+        }
+    }
+    pub fn render(results: &[BatchResult]) -> String {
+        let mut out = String::new();
+        let sep = "-".repeat(100);
+        out.push_str(&format!("{}\n", sep));
+        out.push_str(&format!("{:<20} {:<24} {:<6} {:>8}  {}\n", "Serial", "Device", "结果", "耗时", "摘要"));
+        out.push_str(&format!("{}\n", sep));
+        for r in results {
+            let status = match (r.ok, r.findings) {
+                (false, _) => "失败",
+                (true, true) => "发现",
+                (true, false) => "正常",
+            };
+            out.push_str(&format!(
+                "{:<20} {:<24} {:<6} {:>7.1}s  {}\n",
+                r.serial, r.device, status, r.elapsed_secs, r.summary
+            ));
+        }
+        out.push_str(&format!("{}\n", sep));
+        let failed = results.iter().filter(|r| !r.ok).count();
+        let findings = results.iter().filter(|r| r.findings).count();
+        out.push_str(&format!(
+            "共 {} 台: 正常 {}, 发现问题 {}, 失败 {}\n",
+            results.len(),
+            results.len() - failed - findings,
+            findings,
+            failed
+        ));
+        out
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adb::fake::{fixture, FakeFixture};
+    use crate::adb::FakeTransport;
+    use std::sync::Arc;
+    #[tokio::test]
+    async fn unlocked_bootloader_is_reported_as_a_finding() {
+        let fake = FakeTransport::new(FakeFixture::default()).with_shell(
+            "AAA",
+            &["getprop"],
+            "[ro.boot.verifiedbootstate]: [orange]\n[ro.boot.flash.locked]: [0]\n",
+        );
+        let runner = BatchRunner::new(DeviceManager::new(Arc::new(fake)), Config::default());
+        let (findings, summary) = runner.run_one("AAA", BatchOperation::Bootloader).await.unwrap();
+        assert!(findings);
+        assert!(summary.contains("已解锁"));
+        let runner = BatchRunner::new(DeviceManager::new(fixture("tablet_wifi.json")), Config::default());
+        let (findings, _) = runner.run_one("TAB", BatchOperation::Bootloader).await.unwrap();
+        assert!(!findings);
+    }
+}
@@ -1,75 +1,225 @@
-﻿use crate::adb::DeviceManager;
+﻿use crate::adb::props::{Properties, VerifiedBootState};
+use crate::adb::DeviceManager;
+use crate::checks::finding::{Confidence, Finding, Severity};
 use crate::core::Result;
-use std::collections::HashMap;
-pub struct BootloaderChecker {
-    device_mgr: DeviceManager,
+use serde::{Deserialize, Serialize};
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BootloaderVerdict {
+    Locked,
+    ProbablyLocked,
+    Unlocked,
+    VerificationFailed,
+    Debuggable,
+    Emulator,
+    EmulatorDebuggable,
+    Unknown,
 }
-impl BootloaderChecker {
-    pub fn new(dm: DeviceManager) -> Self {
-        Self { device_mgr: dm }
+impl BootloaderVerdict {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Locked => "Bootloader 已锁定，系统完整性良好",
+            Self::ProbablyLocked => "Bootloader 可能已锁定（基于 veritymode）",
+            Self::Unlocked => "Bootloader 已解锁，系统可能被修改",
+            Self::VerificationFailed => "系统验证失败，存在严重安全风险",
+            Self::Debuggable => "设备处于调试模式，可能已解锁",
+            Self::Emulator => "模拟器环境",
+            Self::EmulatorDebuggable => "模拟器环境，默认可调试模式（相当于已解锁）",
+            Self::Unknown => "无法确定 Bootloader 状态（设备不提供标准属性）",
+        }
     }
-    fn analyze(&self, props: &HashMap<String, String>) -> String {
-        let vb = props.get("ro.boot.verifiedbootstate").map(|s| s.as_str());
-        let locked = props.get("ro.boot.flash.locked").map(|s| s.as_str());
-        let state = props.get("ro.boot.vbmeta.device_state").map(|s| s.as_str());
-        let verify = props.get("ro.boot.veritymode").map(|s| s.as_str());
-        let dbg = props.get("ro.debuggable").map(|s| s.as_str());
-        let qemu = props.get("ro.boot.qemu").map(|s| s.as_str());
-        if qemu == Some("1") {
-            if dbg == Some("1") {
-                return "模拟器环境，默认可调试模式（相当于已解锁）".to_string();
-            } else {
-                return "模拟器环境".to_string();
-            }
+    pub fn is_locked(&self) -> Option<bool> {
+        match self {
+            Self::Locked | Self::ProbablyLocked => Some(true),
+            Self::Unlocked | Self::VerificationFailed | Self::EmulatorDebuggable => Some(false),
+            Self::Debuggable | Self::Emulator | Self::Unknown => None,
+        }
+    }
+    pub fn evaluate(props: &Properties) -> Self {
+        let vb = props.verified_boot_state();
+        let locked = props.bool("ro.boot.flash.locked");
+        let state = props.get("ro.boot.vbmeta.device_state");
+        let verify = props.get("ro.boot.veritymode");
+        let dbg = props.bool("ro.debuggable");
+        if props.bool("ro.boot.qemu") == Some(true) {
+            return if dbg == Some(true) { Self::EmulatorDebuggable } else { Self::Emulator };
         }
         match (vb, locked, state) {
-            (Some("green"), Some("1"), Some("locked")) => {
-                "Bootloader 已锁定，系统完整性良好".to_string()
-            }
-            (Some("orange"), _, _) | (_, Some("0"), _) | (_, _, Some("unlocked")) => {
-                "Bootloader 已解锁，系统可能被修改".to_string()
-            }
-            (Some("red"), _, _) => "系统验证失败，存在严重安全风险".to_string(),
+            (Some(VerifiedBootState::Green), Some(true), Some("locked")) => Self::Locked,
+            (Some(VerifiedBootState::Orange), _, _) | (_, Some(false), _) | (_, _, Some("unlocked")) => Self::Unlocked,
+            (Some(VerifiedBootState::Red), _, _) => Self::VerificationFailed,
             _ => {
-                if verify == Some("enforcing") && dbg == Some("0") {
-                    "Bootloader 可能已锁定（基于 veritymode）".to_string()
-                } else if dbg == Some("1") {
-                    "设备处于调试模式，可能已解锁".to_string()
+                if verify == Some("enforcing") && dbg == Some(false) {
+                    Self::ProbablyLocked
+                } else if dbg == Some(true) {
+                    Self::Debuggable
                 } else {
-                    "无法确定 Bootloader 状态（设备不提供标准属性）".to_string()
+                    Self::Unknown
                 }
             }
         }
     }
-    fn extract_info(&self, props: &HashMap<String, String>) -> Vec<(String, String)> {
-        let items = vec![
-            ("ro.boot.verifiedbootstate", "verifiedbootstate"),
-            ("ro.boot.vbmeta.device_state", "vbmeta.device_state"),
-            ("ro.boot.flash.locked", "flash.locked"),
-            ("ro.boot.veritymode", "veritymode"),
-            ("ro.boot.warranty_bit", "warranty_bit"),
-            ("ro.boot.bootloader", "bootloader"),
-            ("ro.debuggable", "debuggable"),
-            ("ro.boot.qemu", "qemu"),
-        ];
-        items.into_iter()
-            .filter_map(|(k, label)| {
-                props.get(k).map(|v| {
-                    (label.to_string(), if v.is_empty() { "-".to_string() } else { v.clone() })
-                })
-            })
-            .collect()
+}
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BootProperty {
+    pub name: String,
+    pub value: String,
+}
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BootloaderReport {
+    pub serial: String,
+    pub verdict: BootloaderVerdict,
+    pub detected: bool,
+    pub analysis: String,
+    pub properties: Vec<BootProperty>,
+    pub findings: Vec<Finding>,
+}
+impl BootloaderReport {
+    pub fn from_properties(serial: &str, props: &Properties) -> Self {
+        let verdict = BootloaderVerdict::evaluate(props);
+        Self {
+            serial: serial.to_string(),
+            verdict,
+            detected: verdict.is_locked() == Some(false),
+            analysis: verdict.label().to_string(),
+            properties: extract_info(props),
+            findings: findings(props),
+        }
     }
-    pub async fn check(&self, serial: &str) -> Result<String> {
-        let props = self.device_mgr.get_properties(serial).await?;
-        let info = self.extract_info(&props);
+    pub fn render(&self) -> String {
         let mut out = String::new();
         out.push_str("\n[BL 锁 / Verified Boot]\n");
-        for (k, v) in info {
-            out.push_str(&format!("  {}={}\n", k, v));
+        for p in &self.properties {
+            out.push_str(&format!("  {}={}\n", p.name, p.value));
         }
-        let analysis = self.analyze(&props);
-        out.push_str(&format!("\n分析: {}\n", analysis));
-        Ok(out)
+        let hits: Vec<&Finding> = self.findings.iter().filter(|f| f.detected).collect();
+        if !hits.is_empty() {
+            out.push('\n');
+            for f in hits {
+                out.push_str(&format!("  {}\n", f.render()));
+            }
+        }
+        out.push_str(&format!("\n分析: {}\n", self.analysis));
+        out
     }
-}
\ No newline at end of file
+}
+pub struct BootloaderChecker {
+    device_mgr: DeviceManager,
+}
+impl BootloaderChecker {
+    pub fn new(dm: DeviceManager) -> Self {
+        Self { device_mgr: dm }
+    }
+    pub async fn inspect(&self, serial: &str) -> Result<BootloaderReport> {
+        let props = self.device_mgr.properties(serial).await?;
+        Ok(BootloaderReport::from_properties(serial, &props))
+    }
+    pub async fn check(&self, serial: &str) -> Result<String> {
+        Ok(self.inspect(serial).await?.render())
+    }
+}
+fn extract_info(props: &Properties) -> Vec<BootProperty> {
+    let items = vec![
+        ("ro.boot.verifiedbootstate", "verifiedbootstate"),
+        ("ro.boot.vbmeta.device_state", "vbmeta.device_state"),
+        ("ro.boot.flash.locked", "flash.locked"),
+        ("ro.boot.veritymode", "veritymode"),
+        ("ro.boot.warranty_bit", "warranty_bit"),
+        ("ro.boot.bootloader", "bootloader"),
+        ("ro.debuggable", "debuggable"),
+        ("ro.boot.qemu", "qemu"),
+    ];
+    items.into_iter()
+        .filter_map(|(k, label)| {
+            props.raw(k).map(|v| BootProperty {
+                name: label.to_string(),
+                value: if v.is_empty() { "-".to_string() } else { v.to_string() },
+            })
+        })
+        .collect()
+}
+fn findings(props: &Properties) -> Vec<Finding> {
+    let evidence = |key: &str| format!("{}={}", key, props.get(key).unwrap_or("-"));
+    let mut findings = Vec::new();
+    match props.verified_boot_state() {
+        Some(VerifiedBootState::Orange) => findings.push(
+            Finding::detected("verified-boot-orange", "Verified Boot 为 orange (已解锁)", Severity::High, Confidence::High)
+                .with_evidence([evidence("ro.boot.verifiedbootstate")]),
+        ),
+        Some(VerifiedBootState::Yellow) => findings.push(
+            Finding::detected("verified-boot-yellow", "Verified Boot 为 yellow (自定义签名)", Severity::Medium, Confidence::High)
+                .with_evidence([evidence("ro.boot.verifiedbootstate")]),
+        ),
+        Some(VerifiedBootState::Red) => findings.push(
+            Finding::detected("verified-boot-red", "Verified Boot 验证失败", Severity::Critical, Confidence::High)
+                .with_evidence([evidence("ro.boot.verifiedbootstate")]),
+        ),
+        Some(VerifiedBootState::Green) => findings.push(Finding::clear("verified-boot", "Verified Boot 为 green")),
+        _ => {}
+    }
+    match props.bool("ro.boot.flash.locked") {
+        Some(false) => findings.push(
+            Finding::detected("flash-unlocked", "flash.locked 显示已解锁", Severity::High, Confidence::High)
+                .with_evidence([evidence("ro.boot.flash.locked")]),
+        ),
+        Some(true) => findings.push(Finding::clear("flash-unlocked", "flash.locked 显示已锁定")),
+        None => {}
+    }
+    if props.get("ro.boot.vbmeta.device_state") == Some("unlocked") {
+        findings.push(
+            Finding::detected("vbmeta-unlocked", "vbmeta 设备状态为 unlocked", Severity::High, Confidence::High)
+                .with_evidence([evidence("ro.boot.vbmeta.device_state")]),
+        );
+    }
+    if props.bool("ro.debuggable") == Some(true) {
+        findings.push(
+            Finding::detected("debuggable", "系统为可调试构建", Severity::Medium, Confidence::Medium)
+                .with_evidence([evidence("ro.debuggable")]),
+        );
+    }
+    findings
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use BootloaderVerdict::*;
+    type Case<'a> = (&'a str, &'a [(&'a str, &'a str)], BootloaderVerdict);
+    fn props(pairs: &[(&str, &str)]) -> Properties {
+        let output: String = pairs.iter().map(|(k, v)| format!("[{}]: [{}]\n", k, v)).collect();
+        Properties::parse(&output)
+    }
+    #[test]
+    fn verdict_table() {
+        let locked = [
+            ("ro.boot.verifiedbootstate", "green"),
+            ("ro.boot.flash.locked", "1"),
+            ("ro.boot.vbmeta.device_state", "locked"),
+        ];
+        let cases: &[Case] = &[
+            ("locked", &locked, Locked),
+            ("orange", &[("ro.boot.verifiedbootstate", "orange")], Unlocked),
+            ("flash.locked=0", &[("ro.boot.verifiedbootstate", "green"), ("ro.boot.flash.locked", "0")], Unlocked),
+            ("device_state unlocked", &[("ro.boot.vbmeta.device_state", "unlocked")], Unlocked),
+            ("red", &[("ro.boot.verifiedbootstate", "red"), ("ro.boot.flash.locked", "1")], VerificationFailed),
+            ("veritymode only", &[("ro.boot.veritymode", "enforcing"), ("ro.debuggable", "0")], ProbablyLocked),
+            ("veritymode on a debuggable build", &[("ro.boot.veritymode", "enforcing"), ("ro.debuggable", "1")], Debuggable),
+            ("debuggable", &[("ro.debuggable", "1")], Debuggable),
+            ("qemu", &[("ro.boot.qemu", "1"), ("ro.boot.verifiedbootstate", "orange")], Emulator),
+            ("qemu debuggable", &[("ro.boot.qemu", "1"), ("ro.debuggable", "1")], EmulatorDebuggable),
+            ("green without lock properties", &[("ro.boot.verifiedbootstate", "green")], Unknown),
+            ("unknown", &[("ro.product.model", "Pixel 7")], Unknown),
+        ];
+        for (name, pairs, expected) in cases {
+            assert_eq!(BootloaderVerdict::evaluate(&props(pairs)), *expected, "{}", name);
+        }
+    }
+    #[test]
+    fn only_unlocked_verdicts_are_detected() {
+        assert!(!BootloaderReport::from_properties("AAA", &props(&[("ro.boot.verifiedbootstate", "green"), ("ro.boot.flash.locked", "1"), ("ro.boot.vbmeta.device_state", "locked")])).detected);
+        assert!(BootloaderReport::from_properties("AAA", &props(&[("ro.boot.verifiedbootstate", "orange")])).detected);
+        assert!(BootloaderReport::from_properties("AAA", &props(&[("ro.boot.qemu", "1"), ("ro.debuggable", "1")])).detected);
+        for verdict in [Debuggable, Emulator, Unknown] {
+            assert_eq!(verdict.is_locked(), None);
+        }
+    }
+}
@@ -0,0 +1,195 @@
+﻿use crate::adb::props::Properties;
+use crate::adb::{DeviceManager, SharedTransport};
+use crate::checks::facts::FactNeeds;
+use crate::checks::finding::{self, Confidence, Finding, Severity};
+use crate::checks::rules::{DetectionRules, EMULATOR_RULES};
+use crate::core::config::Config;
+use crate::core::Result;
+use serde::{Deserialize, Serialize};
+pub const EMULATOR_SCORE: u32 = 9;
+pub const SUSPICIOUS_SCORE: u32 = 3;
+const MIN_SENSORS: usize = 3;
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EmulatorVerdict {
+    PhysicalDevice,
+    Suspicious,
+    Emulator,
+}
+impl EmulatorVerdict {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::PhysicalDevice => "未发现模拟器特征，判断为真机",
+            Self::Suspicious => "存在虚拟设备特征",
+            Self::Emulator => "判断为模拟器/虚拟设备",
+        }
+    }
+    pub fn from_score(score: u32) -> Self {
+        if score >= EMULATOR_SCORE {
+            Self::Emulator
+        } else if score >= SUSPICIOUS_SCORE {
+            Self::Suspicious
+        } else {
+            Self::PhysicalDevice
+        }
+    }
+}
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmulatorSignal {
+    pub id: String,
+    pub title: String,
+    pub score: u32,
+}
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmulatorReport {
+    pub serial: String,
+    pub verdict: EmulatorVerdict,
+    pub detected: bool,
+    pub score: u32,
+    pub signals: Vec<EmulatorSignal>,
+    pub findings: Vec<Finding>,
+}
+impl EmulatorReport {
+    pub fn new(serial: &str, findings: Vec<Finding>) -> Self {
+        let mut signals: Vec<EmulatorSignal> = findings
+            .iter()
+            .filter(|f| f.detected)
+            .map(|f| EmulatorSignal {
+                id: f.id.clone(),
+                title: f.title.clone(),
+                score: f.weight(),
+            })
+            .collect();
+        signals.sort_by_key(|s| std::cmp::Reverse(s.score));
+        let score = signals.iter().map(|s| s.score).sum();
+        let verdict = EmulatorVerdict::from_score(score);
+        Self {
+            serial: serial.to_string(),
+            verdict,
+            detected: verdict == EmulatorVerdict::Emulator,
+            score,
+            signals,
+            findings,
+        }
+    }
+    pub fn hits(&self) -> impl Iterator<Item = &Finding> {
+        finding::hits(&self.findings)
+    }
+    pub fn render(&self) -> String {
+        let summary = format!("{} (得分 {}，≥{} 判定为模拟器)", self.verdict.label(), self.score, EMULATOR_SCORE);
+        finding::render_report("模拟器/虚拟设备检测", &summary, &self.findings, |f| match f.weight() {
+            0 => "    ".to_string(),
+            score => format!("+{:<2} ", score),
+        })
+    }
+}
+pub struct EmulatorChecker {
+    client: SharedTransport,
+    device_mgr: DeviceManager,
+    rules: DetectionRules,
+}
+impl EmulatorChecker {
+    pub fn new(dm: DeviceManager) -> Self {
+        Self {
+            client: dm.client().clone(),
+            device_mgr: dm,
+            rules: DetectionRules::bundled(EMULATOR_RULES),
+        }
+    }
+    pub fn from_config(dm: DeviceManager, config: &Config) -> Result<Self> {
+        Ok(Self::new(dm).with_rules(DetectionRules::emulators_from_config(config)?))
+    }
+    pub fn with_rules(mut self, rules: DetectionRules) -> Self {
+        self.rules = rules;
+        self
+    }
+    pub async fn check(&self, serial: &str) -> Result<String> {
+        Ok(self.detect(serial).await?.render())
+    }
+    pub async fn detect(&self, serial: &str) -> Result<EmulatorReport> {
+        let needs = FactNeeds {
+            props: true,
+            ..FactNeeds::default()
+        };
+        let (mut findings, facts) = self.rules.evaluate_with(&self.client, &self.device_mgr, serial, needs).await?;
+        let sensors = self.client.shell_output(serial, &["dumpsys", "sensorservice"]).await?;
+        if sensors.success() {
+            findings.extend(sensor_findings(&sensors.stdout));
+        }
+        findings.push(telephony_finding(&facts.props));
+        Ok(EmulatorReport::new(serial, findings))
+    }
+}
+// dumpsys sensorservice: 新版本有 "Total N h/w sensors" 汇总行，旧版本逐行列出 "0x0000000b) 名称 | 厂商 | ..."
+fn sensor_findings(output: &str) -> Vec<Finding> {
+    let listed: Vec<&str> = output
+        .lines()
+        .map(str::trim)
+        .filter(|line| line.starts_with("0x") && line.contains(") "))
+        .collect();
+    let total = output
+        .lines()
+        .find_map(|line| line.trim().strip_prefix("Total ")?.split_whitespace().next()?.parse::<usize>().ok())
+        .or((!listed.is_empty()).then_some(listed.len()));
+    let virtual_sensors: Vec<String> = listed
+        .iter()
+        .filter(|line| ["Goldfish", "goldfish", "Ranchu"].iter().any(|p| line.contains(p)))
+        .filter_map(|line| line.split_once(") ").map(|(_, rest)| rest.split('|').next().unwrap_or(rest).trim().to_string()))
+        .collect();
+    let mut findings = Vec::new();
+    findings.push(if virtual_sensors.is_empty() {
+        Finding::clear("emulator-sensors", "未发现: goldfish 虚拟传感器")
+    } else {
+        Finding::detected("emulator-sensors", "goldfish 虚拟传感器", Severity::High, Confidence::High)
+            .with_evidence(virtual_sensors)
+    });
+    // 无法识别的输出格式不计分，避免把真机误判为可疑
+    findings.push(match total {
+        None => Finding::clear("few-sensors", "无法解析传感器列表，未计分"),
+        Some(total) if total < MIN_SENSORS => {
+            Finding::detected("few-sensors", "硬件传感器缺失或过少", Severity::Medium, Confidence::Medium)
+                .with_evidence([format!("{} 个传感器", total)])
+        }
+        Some(total) => Finding::clear("few-sensors", format!("传感器数量正常 ({} 个)", total)),
+    });
+    findings
+}
+// 平板等无蜂窝模块的真机同样没有基带，只作为弱信号
+fn telephony_finding(props: &Properties) -> Finding {
+    match props.get("gsm.version.baseband") {
+        Some(baseband) => Finding::clear("no-baseband", format!("基带版本 {}", baseband)),
+        None => Finding::detected("no-baseband", "未报告基带版本 (无电话功能)", Severity::Low, Confidence::Low)
+            .with_evidence(["gsm.version.baseband=-".to_string()]),
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adb::fake::fixture;
+    #[tokio::test]
+    async fn android_emulator_is_detected() {
+        let report = EmulatorChecker::new(DeviceManager::new(fixture("emulator_x86_64.json"))).detect("emulator-5554").await.unwrap();
+        assert_eq!(report.verdict, EmulatorVerdict::Emulator);
+        assert!(report.score >= EMULATOR_SCORE);
+        assert!(report.signals.windows(2).all(|w| w[0].score >= w[1].score));
+        assert!(report.hits().any(|f| f.id == "emulator-sensors"));
+        assert!(report.hits().any(|f| f.id == "few-sensors"));
+    }
+    #[tokio::test]
+    async fn wifi_tablet_is_a_physical_device() {
+        let report = EmulatorChecker::new(DeviceManager::new(fixture("tablet_wifi.json"))).detect("TAB").await.unwrap();
+        assert_eq!(report.verdict, EmulatorVerdict::PhysicalDevice);
+        // 无基带只是弱信号，单独出现不足以判为可疑
+        assert_eq!(report.score, 1);
+        assert!(report.hits().all(|f| f.id == "no-baseband"));
+    }
+    #[test]
+    fn unrecognized_sensor_dump_is_not_scored() {
+        let findings = sensor_findings("Sensor Device:\nSensor List:\n  (format changed by vendor)\n");
+        let few = findings.iter().find(|f| f.id == "few-sensors").unwrap();
+        assert!(!few.detected);
+        assert_eq!(few.weight(), 0);
+        let findings = sensor_findings("Total 2 h/w sensors, 2 running:\n");
+        assert!(findings.iter().any(|f| f.id == "few-sensors" && f.detected));
+    }
+}
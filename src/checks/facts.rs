@@ -0,0 +1,358 @@
+﻿use crate::adb::props::Properties;
+use crate::adb::protocol::shell_quote;
+use crate::adb::{DeviceManager, SharedTransport};
+use crate::core::Result;
+use std::collections::HashMap;
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MountEntry {
+    pub source: String,
+    pub mount_point: String,
+    pub fs_type: String,
+    pub raw: String,
+}
+impl MountEntry {
+    pub fn describe(&self) -> String {
+        format!("{} on {} ({})", self.source, self.mount_point, self.fs_type)
+    }
+}
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcessEntry {
+    pub context: String,
+    pub pid: String,
+    pub name: String,
+}
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ListeningPort {
+    pub proto: String,
+    pub address: String,
+    pub port: u16,
+}
+impl ListeningPort {
+    pub fn describe(&self) -> String {
+        format!("{} {}:{}", self.proto, self.address, self.port)
+    }
+}
+// 规则评估需要的设备信息，只按启用规则的需要读取一次
+#[derive(Debug, Clone, Default)]
+pub struct DeviceFacts {
+    pub packages: Vec<String>,
+    pub props: Properties,
+    pub mounts: Vec<MountEntry>,
+    pub processes: Vec<ProcessEntry>,
+    pub bootconfig: HashMap<String, String>,
+    pub ports: Vec<ListeningPort>,
+}
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FactNeeds {
+    pub packages: bool,
+    pub props: bool,
+    pub mounts: bool,
+    pub processes: bool,
+    pub bootconfig: bool,
+    pub ports: bool,
+}
+impl DeviceFacts {
+    pub async fn collect(client: &SharedTransport, device_mgr: &DeviceManager, serial: &str, needs: FactNeeds) -> Result<Self> {
+        let mut facts = Self::default();
+        if needs.packages {
+            facts.packages = device_mgr.get_packages(serial).await?;
+        }
+        if needs.props {
+            facts.props = device_mgr.properties(serial).await?;
+        }
+        if needs.mounts {
+            let mounts = client.shell_output(serial, &["cat", "/proc/mounts"]).await?;
+            facts.mounts = parse_mounts(&mounts.stdout);
+            let mountinfo = client.shell_output(serial, &["cat", "/proc/self/mountinfo"]).await?;
+            facts.mounts.extend(parse_mountinfo(&mountinfo.stdout));
+        }
+        if needs.processes {
+            let mut ps = client.shell_output(serial, &["ps", "-A", "-Z"]).await?;
+            // Android 8 之前的 toolbox ps 不支持 -A，默认即列出全部进程
+            if !ps.success() || ps.stdout.trim().is_empty() {
+                ps = client.shell_output(serial, &["ps", "-Z"]).await?;
+            }
+            facts.processes = parse_processes(&ps.stdout);
+        }
+        if needs.bootconfig {
+            let bootconfig = client.shell_output(serial, &["cat", "/proc/bootconfig"]).await?;
+            facts.bootconfig = if bootconfig.success() {
+                parse_bootconfig(&bootconfig.stdout)
+            } else {
+                let cmdline = client.shell_output(serial, &["cat", "/proc/cmdline"]).await?;
+                if cmdline.success() { parse_cmdline(&cmdline.stdout) } else { HashMap::new() }
+            };
+        }
+        if needs.ports {
+            for (proto, path) in [("tcp", "/proc/net/tcp"), ("tcp6", "/proc/net/tcp6")] {
+                let out = client.shell_output(serial, &["cat", path]).await?;
+                if out.success() {
+                    facts.ports.extend(parse_net_tcp(&out.stdout, proto));
+                }
+            }
+        }
+        Ok(facts)
+    }
+    pub fn services(&self) -> Vec<(&str, &str)> {
+        self.props
+            .keys_sorted()
+            .into_iter()
+            .filter_map(|key| {
+                let name = key.strip_prefix("init.svc.")?;
+                Some((name, self.props.get(key).unwrap_or_default()))
+            })
+            .collect()
+    }
+}
+pub fn parse_mounts(output: &str) -> Vec<MountEntry> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.split_whitespace();
+            Some(MountEntry {
+                source: parts.next()?.to_string(),
+                mount_point: parts.next()?.to_string(),
+                fs_type: parts.next()?.to_string(),
+                raw: line.trim().to_string(),
+            })
+        })
+        .collect()
+}
+// mountinfo: id parent major:minor root mount_point options [optional...] - fs_type source super_options
+pub fn parse_mountinfo(output: &str) -> Vec<MountEntry> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (head, tail) = line.split_once(" - ")?;
+            let mount_point = head.split_whitespace().nth(4)?;
+            let mut tail = tail.split_whitespace();
+            let fs_type = tail.next()?;
+            let source = tail.next().unwrap_or("none");
+            Some(MountEntry {
+                source: source.to_string(),
+                mount_point: mount_point.to_string(),
+                fs_type: fs_type.to_string(),
+                raw: line.trim().to_string(),
+            })
+        })
+        .collect()
+}
+pub fn parse_processes(output: &str) -> Vec<ProcessEntry> {
+    output
+        .lines()
+        .filter(|line| !line.trim_start().starts_with("LABEL"))
+        .filter_map(|line| {
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            if parts.len() < 4 || !parts[0].contains(':') {
+                return None;
+            }
+            Some(ProcessEntry {
+                context: parts[0].to_string(),
+                pid: parts[2].to_string(),
+                name: parts[parts.len() - 1].to_string(),
+            })
+        })
+        .collect()
+}
+pub fn parse_bootconfig(output: &str) -> HashMap<String, String> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (key, value) = line.split_once('=')?;
+            Some((key.trim().to_string(), value.trim().trim_matches('"').to_string()))
+        })
+        .collect()
+}
+pub fn parse_cmdline(output: &str) -> HashMap<String, String> {
+    output
+        .split_whitespace()
+        .filter_map(|token| {
+            let (key, value) = token.split_once('=')?;
+            Some((key.to_string(), value.to_string()))
+        })
+        .collect()
+}
+// /proc/net/tcp: sl local_address rem_address st ...，地址为小端十六进制，st=0A 表示 LISTEN
+pub fn parse_net_tcp(output: &str, proto: &str) -> Vec<ListeningPort> {
+    output
+        .lines()
+        .skip(1)
+        .filter_map(|line| {
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            if parts.len() < 4 || parts[3] != "0A" {
+                return None;
+            }
+            let (addr, port) = parts[1].split_once(':')?;
+            Some(ListeningPort {
+                proto: proto.to_string(),
+                address: decode_address(addr),
+                port: u16::from_str_radix(port, 16).ok()?,
+            })
+        })
+        .collect()
+}
+// tcp6 的地址是 4 个按主机字节序（小端）存放的 32 位字
+fn decode_address(hex: &str) -> String {
+    let word = |i: usize| hex.get(i * 8..i * 8 + 8).and_then(|w| u32::from_str_radix(w, 16).ok());
+    match hex.len() {
+        8 => match word(0) {
+            Some(v) => std::net::Ipv4Addr::from(v.swap_bytes()).to_string(),
+            None => hex.to_string(),
+        },
+        32 => match (0..4).map(word).collect::<Option<Vec<u32>>>() {
+            Some(words) => {
+                let mut bytes = [0u8; 16];
+                for (chunk, w) in bytes.chunks_mut(4).zip(words) {
+                    chunk.copy_from_slice(&w.swap_bytes().to_be_bytes());
+                }
+                format!("[{}]", std::net::Ipv6Addr::from(bytes))
+            }
+            None => format!("[{}]", hex),
+        },
+        _ => hex.to_string(),
+    }
+}
+// 返回映射的文件路径（去重）；无权读取时返回 None
+pub async fn read_maps(client: &SharedTransport, serial: &str, pid: &str) -> Result<Option<Vec<String>>> {
+    let path = format!("/proc/{}/maps", pid);
+    let mut out = client.shell_output(serial, &["cat", &path]).await?;
+    if !out.success() || out.stdout.trim().is_empty() {
+        let cmd = shell_quote(&format!("cat {}", shell_quote(&path)));
+        out = client.shell_output(serial, &["su", "-c", &cmd]).await?;
+    }
+    Ok(out.success().then(|| parse_maps(&out.stdout)))
+}
+// maps: address perms offset dev inode pathname
+pub fn parse_maps(output: &str) -> Vec<String> {
+    let mut paths: Vec<String> = Vec::new();
+    for line in output.lines() {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        if parts.len() < 6 || !parts[0].contains('-') {
+            continue;
+        }
+        let path = parts[5..].join(" ");
+        if !paths.contains(&path) {
+            paths.push(path);
+        }
+    }
+    paths
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adb::FakeTransport;
+    use crate::adb::fake::FakeFixture;
+    use std::sync::Arc;
+    #[tokio::test]
+    async fn processes_fall_back_to_toolbox_ps() {
+        let fake = FakeTransport::new(FakeFixture::default())
+            .with_response("-s OLD shell ps -A -Z", "", "bad argument: -A", 1)
+            .with_shell(
+                "OLD",
+                &["ps", "-Z"],
+                "LABEL USER PID PPID VSIZE RSS WCHAN PC NAME\n\
+                 u:r:init:s0 root 1 0 1234 567 SyS_epoll_ 00000000 S /init\n\
+                 u:r:magisk:s0 root 455 1 9876 543 poll_sched 00000000 S magiskd\n",
+            );
+        let client: SharedTransport = Arc::new(fake);
+        let dm = DeviceManager::new(client.clone());
+        let needs = FactNeeds {
+            processes: true,
+            ..FactNeeds::default()
+        };
+        let facts = DeviceFacts::collect(&client, &dm, "OLD", needs).await.unwrap();
+        assert_eq!(
+            facts.processes[1],
+            ProcessEntry {
+                context: "u:r:magisk:s0".to_string(),
+                pid: "455".to_string(),
+                name: "magiskd".to_string(),
+            }
+        );
+        assert_eq!(facts.processes.len(), 2);
+    }
+    #[test]
+    fn ps_header_and_short_lines_are_skipped() {
+        let output = "LABEL USER PID PPID VSZ RSS WCHAN ADDR S NAME\n\
+                      u:r:su:s0 root 812 1 0 0 0 0 S ksud\n\
+                      garbage\n";
+        let processes = parse_processes(output);
+        assert_eq!(processes.len(), 1);
+        assert_eq!(processes[0].pid, "812");
+        assert_eq!(processes[0].name, "ksud");
+    }
+    #[test]
+    fn listening_sockets_are_decoded() {
+        let tcp = "  sl  local_address rem_address   st tx_queue rx_queue tr tm->when retrnsmt   uid  timeout inode\n\
+                   0: 0100007F:69A2 00000000:0000 0A 00000000:00000000 00:00000000 00000000     0        0 4242\n\
+                   1: 00000000:1389 0100007F:9C40 01 00000000:00000000 00:00000000 00000000  2000        0 4243\n";
+        let ports = parse_net_tcp(tcp, "tcp");
+        assert_eq!(ports.len(), 1);
+        assert_eq!(ports[0].describe(), "tcp 127.0.0.1:27042");
+        let tcp6 = "  sl  local_address                         remote_address                        st\n\
+                    0: 00000000000000000000000000000000:69A3 00000000000000000000000000000000:0000 0A\n\
+                    1: 0000000000000000FFFF00000100007F:1F90 00000000000000000000000000000000:0000 0A\n\
+                    2: 00000000000000000000000001000000:0035 00000000000000000000000000000000:0000 0A\n";
+        let ports = parse_net_tcp(tcp6, "tcp6");
+        let described: Vec<String> = ports.iter().map(ListeningPort::describe).collect();
+        assert_eq!(described, vec!["tcp6 [::]:27043", "tcp6 [::ffff:127.0.0.1]:8080", "tcp6 [::1]:53"]);
+    }
+    #[test]
+    fn address_decoding_handles_odd_input() {
+        assert_eq!(decode_address("00000000"), "0.0.0.0");
+        assert_eq!(decode_address("0101A8C0"), "192.168.1.1");
+        assert_eq!(decode_address("XYZ"), "XYZ");
+    }
+    #[test]
+    fn maps_paths_are_deduplicated() {
+        let output = "7f0000000-7f0001000 r-xp 00000000 fd:05 1234 /system/lib64/libc.so\n\
+                      7f0001000-7f0002000 r--p 00001000 fd:05 1234 /system/lib64/libc.so\n\
+                      7f0003000-7f0004000 rw-p 00000000 00:00 0\n\
+                      7f0005000-7f0006000 r-xp 00000000 fd:21 99 /data/adb/modules/zygisk_lsposed/lib/liblspd.so\n\
+                      7f0007000-7f0008000 r-xp 00000000 00:05 7 /dev/ashmem/dalvik main space (deleted)\n";
+        assert_eq!(
+            parse_maps(output),
+            vec![
+                "/system/lib64/libc.so",
+                "/data/adb/modules/zygisk_lsposed/lib/liblspd.so",
+                "/dev/ashmem/dalvik main space (deleted)",
+            ]
+        );
+    }
+    #[test]
+    fn mounts_keep_source_point_and_type() {
+        let mounts = parse_mounts("magisk /debug_ramdisk tmpfs rw,relatime 0 0\n\ntoo short\n/dev/root / ext4 ro 0 0\n");
+        assert_eq!(mounts.len(), 2);
+        assert_eq!(mounts[0].describe(), "magisk on /debug_ramdisk (tmpfs)");
+        assert_eq!(mounts[0].raw, "magisk /debug_ramdisk tmpfs rw,relatime 0 0");
+        assert_eq!(mounts[1].mount_point, "/");
+    }
+    #[test]
+    fn mountinfo_skips_optional_fields() {
+        let output = "30 1 0:20 / /system/bin rw,relatime shared:5 master:1 - overlay KSU rw\n\
+                      41 1 253:5 /adb/modules/foo/system/lib/libx.so /system/lib/libx.so ro - ext4 /dev/block/dm-5 rw\n\
+                      52 1 0:30 / /mnt/odd rw - fuse\n\
+                      no separator here\n";
+        let mounts = parse_mountinfo(output);
+        assert_eq!(mounts.len(), 3);
+        assert_eq!(mounts[0].describe(), "KSU on /system/bin (overlay)");
+        assert_eq!(mounts[1].mount_point, "/system/lib/libx.so");
+        assert!(mounts[1].raw.contains("/adb/modules/foo"));
+        assert_eq!(mounts[2].source, "none");
+    }
+    #[test]
+    fn bootconfig_values_are_unquoted() {
+        let config = parse_bootconfig("androidboot.verifiedbootstate = \"orange\"\nandroidboot.serialno=ABC123\nnot a pair\n");
+        assert_eq!(config.len(), 2);
+        assert_eq!(config["androidboot.verifiedbootstate"], "orange");
+        assert_eq!(config["androidboot.serialno"], "ABC123");
+    }
+    #[test]
+    fn cmdline_keeps_only_key_value_tokens() {
+        let config = parse_cmdline("console=ttyMSM0 quiet androidboot.hardware=qcom androidboot.vbmeta.avb_version=1.0\n");
+        assert_eq!(config.len(), 3);
+        assert_eq!(config["androidboot.hardware"], "qcom");
+        assert_eq!(config["androidboot.vbmeta.avb_version"], "1.0");
+        assert!(!config.contains_key("quiet"));
+    }
+}
@@ -0,0 +1,148 @@
+﻿use serde::{Deserialize, Serialize};
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Info,
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+impl Severity {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Info => "信息",
+            Self::Low => "低",
+            Self::Medium => "中",
+            Self::High => "高",
+            Self::Critical => "严重",
+        }
+    }
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Confidence {
+    Low,
+    Medium,
+    High,
+}
+impl Confidence {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Low => "低",
+            Self::Medium => "中",
+            Self::High => "高",
+        }
+    }
+}
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Finding {
+    pub id: String,
+    pub title: String,
+    pub detected: bool,
+    pub severity: Severity,
+    pub confidence: Confidence,
+    #[serde(default)]
+    pub evidence: Vec<String>,
+    #[serde(default)]
+    pub unreadable: bool,
+}
+impl Finding {
+    pub fn detected(id: &str, title: impl Into<String>, severity: Severity, confidence: Confidence) -> Self {
+        Self {
+            id: id.to_string(),
+            title: title.into(),
+            detected: true,
+            severity,
+            confidence,
+            evidence: Vec::new(),
+            unreadable: false,
+        }
+    }
+    // 未命中的检查项同样保留，便于输出完整的检查清单
+    pub fn clear(id: &str, title: impl Into<String>) -> Self {
+        Self {
+            id: id.to_string(),
+            title: title.into(),
+            detected: false,
+            severity: Severity::Info,
+            confidence: Confidence::High,
+            evidence: Vec::new(),
+            unreadable: false,
+        }
+    }
+    // 无权读取、无法判断的检查项不计分，但不能当作通过
+    pub fn unreadable(id: &str, title: impl Into<String>) -> Self {
+        Self {
+            unreadable: true,
+            confidence: Confidence::Low,
+            ..Self::clear(id, title)
+        }
+    }
+    pub fn with_evidence(mut self, evidence: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.evidence.extend(evidence.into_iter().map(Into::into));
+        self
+    }
+    // 单条命中的分值：严重程度 (info=0 … critical=4) × 置信度 (low=1 … high=3)
+    pub fn weight(&self) -> u32 {
+        if !self.detected {
+            return 0;
+        }
+        let severity = match self.severity {
+            Severity::Info => 0,
+            Severity::Low => 1,
+            Severity::Medium => 2,
+            Severity::High => 3,
+            Severity::Critical => 4,
+        };
+        let confidence = match self.confidence {
+            Confidence::Low => 1,
+            Confidence::Medium => 2,
+            Confidence::High => 3,
+        };
+        severity * confidence
+    }
+    pub fn render(&self) -> String {
+        let mut line = if self.detected {
+            format!("[{}/置信度{}] {}", self.severity.label(), self.confidence.label(), self.title)
+        } else if self.unreadable {
+            format!("[无法读取] {}", self.title)
+        } else {
+            format!("[通过] {}", self.title)
+        };
+        if !self.evidence.is_empty() {
+            line.push_str(&format!(" ({})", self.evidence.join(", ")));
+        }
+        line
+    }
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Tier {
+    Clear,
+    Suspicious,
+    Confirmed,
+}
+impl Tier {
+    // 高置信度的高危命中才算确认，其余命中只作为可疑
+    pub fn of(findings: &[Finding]) -> Self {
+        let mut tier = Self::Clear;
+        for f in hits(findings) {
+            if f.severity >= Severity::High && f.confidence == Confidence::High {
+                return Self::Confirmed;
+            }
+            tier = Self::Suspicious;
+        }
+        tier
+    }
+}
+pub fn hits(findings: &[Finding]) -> impl Iterator<Item = &Finding> {
+    findings.iter().filter(|f| f.detected)
+}
+// 各检测报告的统一格式；marker 为每条检查项前的附加标注（如模拟器得分）
+pub fn render_report(heading: &str, summary: &str, findings: &[Finding], marker: impl Fn(&Finding) -> String) -> String {
+    let mut out = format!("\n[{}]\n总体判断: {}\n\n", heading, summary);
+    for f in findings {
+        out.push_str(&format!("  {}{}\n", marker(f), f.render()));
+    }
+    out
+}
@@ -0,0 +1,112 @@
+﻿use crate::adb::{DeviceManager, SharedTransport};
+use crate::checks::finding::{self, Finding, Tier};
+use crate::checks::rules::{DetectionRules, HOOK_RULES};
+use crate::core::config::Config;
+use crate::core::Result;
+use serde::{Deserialize, Serialize};
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HookVerdict {
+    NotDetected,
+    Suspicious,
+    Instrumented,
+}
+impl HookVerdict {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::NotDetected => "未检测到 Hook/注入框架",
+            Self::Suspicious => "存在可疑 Hook/注入痕迹",
+            Self::Instrumented => "检测到 Hook/注入框架",
+        }
+    }
+    pub fn evaluate(findings: &[Finding]) -> Self {
+        match Tier::of(findings) {
+            Tier::Clear => Self::NotDetected,
+            Tier::Suspicious => Self::Suspicious,
+            Tier::Confirmed => Self::Instrumented,
+        }
+    }
+}
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HookReport {
+    pub serial: String,
+    pub verdict: HookVerdict,
+    pub detected: bool,
+    pub findings: Vec<Finding>,
+}
+impl HookReport {
+    pub fn new(serial: &str, findings: Vec<Finding>) -> Self {
+        let verdict = HookVerdict::evaluate(&findings);
+        Self {
+            serial: serial.to_string(),
+            verdict,
+            detected: verdict == HookVerdict::Instrumented,
+            findings,
+        }
+    }
+    pub fn hits(&self) -> impl Iterator<Item = &Finding> {
+        finding::hits(&self.findings)
+    }
+    pub fn render(&self) -> String {
+        finding::render_report("Hook/注入框架检测", self.verdict.label(), &self.findings, |_| String::new())
+    }
+}
+pub struct HookChecker {
+    client: SharedTransport,
+    device_mgr: DeviceManager,
+    rules: DetectionRules,
+}
+impl HookChecker {
+    pub fn new(dm: DeviceManager) -> Self {
+        Self {
+            client: dm.client().clone(),
+            device_mgr: dm,
+            rules: DetectionRules::bundled(HOOK_RULES),
+        }
+    }
+    pub fn from_config(dm: DeviceManager, config: &Config) -> Result<Self> {
+        Ok(Self::new(dm).with_rules(DetectionRules::hooks_from_config(config)?))
+    }
+    pub fn with_rules(mut self, rules: DetectionRules) -> Self {
+        self.rules = rules;
+        self
+    }
+    pub async fn check(&self, serial: &str) -> Result<String> {
+        Ok(self.detect(serial).await?.render())
+    }
+    pub async fn detect(&self, serial: &str) -> Result<HookReport> {
+        let findings = self.rules.evaluate(&self.client, &self.device_mgr, serial).await?;
+        Ok(HookReport::new(serial, findings))
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adb::fake::fixture;
+    fn finding<'a>(report: &'a HookReport, id: &str) -> &'a Finding {
+        report.findings.iter().find(|f| f.id == id).unwrap()
+    }
+    #[tokio::test]
+    async fn lsposed_and_frida_device_is_instrumented() {
+        let report = HookChecker::new(DeviceManager::new(fixture("pixel5_lsposed.json"))).detect("HK").await.unwrap();
+        assert_eq!(report.verdict, HookVerdict::Instrumented);
+        assert!(report.detected);
+        assert_eq!(finding(&report, "xposed-files").evidence, vec!["/data/adb/lspd", "/data/adb/modules/zygisk_lsposed"]);
+        let modules = finding(&report, "riru-zygisk-modules");
+        assert!(!modules.detected && modules.unreadable);
+        assert_eq!(modules.evidence.len(), 6);
+        assert!(finding(&report, "frida-binaries").evidence[0].contains("frida-server"));
+        assert_eq!(finding(&report, "frida-ports").evidence, vec!["tcp 0.0.0.0:27042"]);
+        assert_eq!(
+            finding(&report, "zygote-injection").evidence,
+            vec!["zygote64 (pid 700): /data/adb/modules/zygisk_lsposed/lib/liblspd.so"]
+        );
+        assert!(!finding(&report, "xposed-managers").detected);
+    }
+    #[tokio::test]
+    async fn stock_tablet_has_no_hooks() {
+        let report = HookChecker::new(DeviceManager::new(fixture("tablet_wifi.json"))).detect("TAB").await.unwrap();
+        assert_eq!(report.verdict, HookVerdict::NotDetected);
+        assert_eq!(report.hits().count(), 0);
+    }
+}
@@ -1,4 +1,11 @@
 ﻿pub mod root;
 pub mod bootloader;
-pub use root::RootChecker;
-pub use bootloader::BootloaderChecker;
\ No newline at end of file
+pub mod emulator;
+pub mod hooks;
+pub mod facts;
+pub mod finding;
+pub mod rules;
+pub use root::{RootChecker, RootReport};
+pub use bootloader::{BootloaderChecker, BootloaderReport};
+pub use emulator::{EmulatorChecker, EmulatorReport};
+pub use hooks::{HookChecker, HookReport};
\ No newline at end of file
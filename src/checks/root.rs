@@ -1,81 +1,134 @@
-﻿use crate::adb::{AdbClient, DeviceManager};
+﻿use crate::adb::{DeviceManager, SharedTransport};
+use crate::checks::finding::{self, Finding, Tier};
+use crate::checks::rules::{DetectionRules, ROOT_RULES};
+use crate::core::config::{Config, DEFAULT_SUSPICIOUS_PACKAGES};
 use crate::core::Result;
+use serde::{Deserialize, Serialize};
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RootVerdict {
+    NotDetected,
+    Suspicious,
+    Rooted,
+}
+impl RootVerdict {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::NotDetected => "未检测到 Root",
+            Self::Suspicious => "存在可疑 Root 痕迹",
+            Self::Rooted => "检测到 Root 痕迹",
+        }
+    }
+    pub fn evaluate(findings: &[Finding]) -> Self {
+        match Tier::of(findings) {
+            Tier::Clear => Self::NotDetected,
+            Tier::Suspicious => Self::Suspicious,
+            Tier::Confirmed => Self::Rooted,
+        }
+    }
+}
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RootReport {
+    pub serial: String,
+    pub verdict: RootVerdict,
+    pub detected: bool,
+    pub findings: Vec<Finding>,
+}
+impl RootReport {
+    pub fn new(serial: &str, findings: Vec<Finding>) -> Self {
+        let verdict = RootVerdict::evaluate(&findings);
+        Self {
+            serial: serial.to_string(),
+            verdict,
+            detected: verdict == RootVerdict::Rooted,
+            findings,
+        }
+    }
+    pub fn hits(&self) -> impl Iterator<Item = &Finding> {
+        finding::hits(&self.findings)
+    }
+    pub fn evidence_of(&self, id: &str) -> Vec<String> {
+        self.hits()
+            .filter(|f| f.id == id)
+            .flat_map(|f| f.evidence.iter().cloned())
+            .collect()
+    }
+    pub fn render(&self) -> String {
+        finding::render_report("Root 环境检测", self.verdict.label(), &self.findings, |_| String::new())
+    }
+}
 pub struct RootChecker {
-    client: AdbClient,
+    client: SharedTransport,
     device_mgr: DeviceManager,
+    rules: DetectionRules,
 }
 impl RootChecker {
-    pub fn new(client: AdbClient) -> Self {
-        let dm = DeviceManager::new(client.clone());
+    pub fn new(dm: DeviceManager) -> Self {
         Self {
-            client,
+            client: dm.client().clone(),
             device_mgr: dm,
+            rules: DetectionRules::bundled(ROOT_RULES),
         }
+        .with_suspicious_packages(DEFAULT_SUSPICIOUS_PACKAGES.iter().map(|s| s.to_string()).collect())
+    }
+    pub fn from_config(dm: DeviceManager, config: &Config) -> Result<Self> {
+        Ok(Self::new(dm).with_rules(DetectionRules::root_from_config(config)?))
+    }
+    pub fn with_rules(mut self, rules: DetectionRules) -> Self {
+        self.rules = rules;
+        self
+    }
+    pub fn with_suspicious_packages(mut self, packages: Vec<String>) -> Self {
+        self.rules.set_packages(&packages);
+        self
     }
     pub async fn check(&self, serial: &str) -> Result<String> {
-        let mut results = Vec::new();
-        let packages = self.device_mgr.get_packages(serial).await?;
-        let suspect_list = vec![
-            "com.topjohnwu.magisk",
-            "org.lsposed.manager",
-            "eu.chainfire.supersu",
-            "stericson.busybox",
-            "com.koushikdutta.superuser",
-            "com.noshufou.android.su",
-        ];
-        let found_apps: Vec<_> = packages
-            .iter()
-            .filter(|pkg| suspect_list.iter().any(|s| pkg.contains(s)))
-            .collect();
-        if !found_apps.is_empty() {
-            results.push(format!("发现可疑包: {:?}", found_apps));
-        } else {
-            results.push("未发现可疑 Root 管理包".to_string());
-        }
-        let (ok, path, _) = self.client.try_shell(serial, &["which", "su"]).await?;
-        if ok && !path.trim().is_empty() {
-            results.push(format!("发现 su 命令: {}", path.trim()));
-        } else {
-            results.push("未发现 su 命令".to_string());
-        }
-        let paths_to_check = vec![
-            "/system/bin/su",
-            "/system/xbin/su",
-            "/sbin/su",
-            "/system/app/Superuser.apk",
-            "/system/app/SuperSU.apk",
-        ];
-        let mut files_found = Vec::new();
-        for p in paths_to_check {
-            if self.device_mgr.file_exists(serial, p).await {
-                files_found.push(p);
-            }
-        }
-        if !files_found.is_empty() {
-            results.push(format!("发现 Root 相关文件: {:?}", files_found));
-        } else {
-            results.push("未发现 Root 相关文件".to_string());
-        }
-        let (works1, out1, _) = self.client.try_shell(serial, &["su", "-c", "id"]).await?;
-        let (works2, out2, _) = self.client.try_shell(serial, &["su", "0", "id"]).await?;
-        let (works3, out3, _) = self.client.try_shell(serial, &["id"]).await?;
-        let root_works = (works1 && out1.contains("uid=0"))
-            || (works2 && out2.contains("uid=0"))
-            || (works3 && out3.contains("uid=0"));
-        if root_works {
-            results.push("su 命令可执行 (已获取 Root)".to_string());
-        } else {
-            results.push("su 命令不可执行".to_string());
-        }
-        let detected = results.iter().any(|r| r.contains("发现 su") || r.contains("可执行"));
-        let mut out = String::new();
-        out.push_str("\n[Root 环境检测]\n");
-        out.push_str(&format!("总体判断: {}\n\n",
-            if detected { "检测到 Root 痕迹" } else { "未检测到 Root" }
-        ));
-        for r in results {
-            out.push_str(&format!("  {}\n", r));
-        }
-        Ok(out)
+        Ok(self.detect(serial).await?.render())
     }
-}
\ No newline at end of file
+    pub async fn detect(&self, serial: &str) -> Result<RootReport> {
+        let findings = self.rules.evaluate(&self.client, &self.device_mgr, serial).await?;
+        Ok(RootReport::new(serial, findings))
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adb::fake::fixture;
+    #[tokio::test]
+    async fn kernelsu_device_is_rooted() {
+        let report = RootChecker::new(DeviceManager::new(fixture("pixel7_kernelsu.json"))).detect("AAA").await.unwrap();
+        assert_eq!(report.verdict, RootVerdict::Rooted);
+        assert!(report.detected);
+        assert!(report.hits().any(|f| f.evidence.iter().any(|e| e.contains("ksud (pid 812)"))));
+        assert!(report.hits().any(|f| f.evidence.contains(&"init.svc.ksud=running".to_string())));
+        // 原生的 /debug_ramdisk tmpfs 不算命中
+        assert!(!report.hits().any(|f| f.id == "system-tmpfs" || f.id == "magisk-ramdisk"));
+    }
+    #[tokio::test]
+    async fn denied_data_adb_without_su_is_unreadable() {
+        let report = RootChecker::new(DeviceManager::new(fixture("pixel7_kernelsu.json"))).detect("AAA").await.unwrap();
+        let ksu = report.findings.iter().find(|f| f.id == "kernelsu-data").unwrap();
+        assert!(!ksu.detected);
+        assert!(ksu.unreadable);
+        assert!(ksu.evidence.iter().any(|e| e.starts_with("/data/adb/ksu")));
+        assert!(ksu.render().starts_with("[无法读取]"));
+    }
+    #[tokio::test]
+    async fn denied_data_adb_is_retried_through_su() {
+        let report = RootChecker::new(DeviceManager::new(fixture("pixel6_magisk.json"))).detect("P6").await.unwrap();
+        let magisk = report.findings.iter().find(|f| f.id == "magisk-data").unwrap();
+        assert!(magisk.detected);
+        assert_eq!(magisk.evidence, vec!["/data/adb/magisk", "/data/adb/modules"]);
+        let ksu = report.findings.iter().find(|f| f.id == "kernelsu-data").unwrap();
+        assert!(!ksu.detected && !ksu.unreadable);
+        let ramdisk = report.findings.iter().find(|f| f.id == "magisk-ramdisk").unwrap();
+        assert_eq!(ramdisk.evidence.len(), 2);
+        assert_eq!(report.verdict, RootVerdict::Rooted);
+    }
+    #[tokio::test]
+    async fn stock_tablet_is_not_rooted() {
+        let report = RootChecker::new(DeviceManager::new(fixture("tablet_wifi.json"))).detect("TAB").await.unwrap();
+        assert_eq!(report.verdict, RootVerdict::NotDetected);
+        assert_eq!(report.hits().count(), 0);
+    }
+}
@@ -0,0 +1,421 @@
+﻿use crate::adb::props::Properties;
+use crate::adb::protocol::shell_quote;
+use crate::adb::transport::ShellOutput;
+use crate::adb::{DeviceManager, SharedTransport};
+use crate::checks::facts::{self, DeviceFacts, FactNeeds, MountEntry};
+use crate::checks::finding::{Confidence, Finding, Severity};
+use crate::core::config::Config;
+use crate::core::{AdbError, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+pub const ROOT_RULES: &str = include_str!("default_rules.toml");
+pub const HOOK_RULES: &str = include_str!("hook_rules.toml");
+pub const USER_RULES_DIR: &str = "root_rules";
+pub const USER_HOOK_RULES_DIR: &str = "hook_rules";
+pub const EMULATOR_RULES: &str = include_str!("emulator_rules.toml");
+pub const USER_EMULATOR_RULES_DIR: &str = "emulator_rules";
+pub const SUSPICIOUS_PACKAGES_RULE: &str = "suspicious-packages";
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RuleKind {
+    Package,
+    File,
+    Property,
+    Command,
+    Mount,
+    Service,
+    Process,
+    Selinux,
+    Bootconfig,
+    Port,
+    Maps,
+}
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DetectionRule {
+    pub id: String,
+    pub description: String,
+    pub severity: Severity,
+    #[serde(default = "default_confidence")]
+    pub confidence: Confidence,
+    pub kind: RuleKind,
+    #[serde(default)]
+    pub patterns: Vec<String>,
+    #[serde(default)]
+    pub property: Option<String>,
+    #[serde(default)]
+    pub commands: Vec<String>,
+    #[serde(default)]
+    pub fs_types: Vec<String>,
+    #[serde(default)]
+    pub mount_points: Vec<String>,
+    #[serde(default)]
+    pub process: Option<String>,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+fn default_confidence() -> Confidence {
+    Confidence::Medium
+}
+fn default_enabled() -> bool {
+    true
+}
+impl DetectionRule {
+    pub fn suspicious_packages(packages: &[String]) -> Self {
+        Self {
+            id: SUSPICIOUS_PACKAGES_RULE.to_string(),
+            description: "Root 管理类应用".to_string(),
+            severity: Severity::Medium,
+            confidence: Confidence::Medium,
+            kind: RuleKind::Package,
+            patterns: packages.to_vec(),
+            property: None,
+            commands: Vec::new(),
+            fs_types: Vec::new(),
+            mount_points: Vec::new(),
+            process: None,
+            enabled: !packages.is_empty(),
+        }
+    }
+    fn validate(&self) -> std::result::Result<(), String> {
+        if self.id.trim().is_empty() {
+            return Err("id 不能为空".to_string());
+        }
+        if !self.enabled {
+            return Ok(());
+        }
+        if self.patterns.iter().any(|p| p.is_empty()) {
+            return Err(format!("{}: patterns 中不能有空字符串", self.id));
+        }
+        match self.kind {
+            RuleKind::Property | RuleKind::Bootconfig if self.property.as_deref().is_none_or(|p| p.trim().is_empty()) => {
+                Err(format!("{}: 需要 property 字段", self.id))
+            }
+            RuleKind::Command if self.commands.iter().all(|c| c.trim().is_empty()) => {
+                Err(format!("{}: command 规则需要 commands 字段", self.id))
+            }
+            RuleKind::Mount if self.patterns.is_empty() && self.fs_types.is_empty() && self.mount_points.is_empty() => {
+                Err(format!("{}: mount 规则需要 patterns、fs_types 或 mount_points", self.id))
+            }
+            RuleKind::Port if self.patterns.iter().any(|p| p.parse::<u16>().is_err()) => {
+                Err(format!("{}: port 规则的 patterns 必须是端口号", self.id))
+            }
+            RuleKind::Package
+            | RuleKind::File
+            | RuleKind::Command
+            | RuleKind::Service
+            | RuleKind::Process
+            | RuleKind::Selinux
+            | RuleKind::Port
+            | RuleKind::Maps
+                if self.patterns.is_empty() =>
+            {
+                Err(format!("{}: 需要 patterns 字段", self.id))
+            }
+            _ => Ok(()),
+        }
+    }
+    fn matches_mount(&self, entry: &MountEntry) -> bool {
+        let pattern = self.patterns.is_empty() || self.patterns.iter().any(|p| entry.raw.contains(p.as_str()));
+        let fs_type = self.fs_types.is_empty() || self.fs_types.contains(&entry.fs_type);
+        let mount_point = self.mount_points.is_empty()
+            || self.mount_points.iter().any(|mp| {
+                entry.mount_point == *mp || entry.mount_point.starts_with(&format!("{}/", mp.trim_end_matches('/')))
+            });
+        pattern && fs_type && mount_point
+    }
+    // 服务名、进程名按全名或前缀匹配，避免短名称误中其他名称的中间部分
+    fn matches_name(&self, name: &str) -> bool {
+        self.patterns.iter().any(|p| name == p || name.starts_with(p.as_str()))
+    }
+    // property 以 * 结尾时按前缀匹配多个属性
+    fn property_keys<'a>(&self, props: &'a Properties) -> Vec<&'a str> {
+        let wanted = self.property.as_deref().unwrap_or_default();
+        match wanted.strip_suffix('*') {
+            Some(prefix) => props.keys_sorted().into_iter().filter(|k| k.starts_with(prefix)).collect(),
+            None => props.keys_sorted().into_iter().filter(|k| *k == wanted).collect(),
+        }
+    }
+    fn finding(&self, evidence: Vec<String>) -> Finding {
+        if evidence.is_empty() {
+            Finding::clear(&self.id, format!("未发现: {}", self.description))
+        } else {
+            Finding::detected(&self.id, self.description.clone(), self.severity, self.confidence).with_evidence(evidence)
+        }
+    }
+}
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleFile {
+    #[serde(default)]
+    rules: Vec<DetectionRule>,
+}
+#[derive(Debug, Clone, Default)]
+pub struct DetectionRules {
+    rules: Vec<DetectionRule>,
+}
+impl DetectionRules {
+    pub fn bundled(content: &str) -> Self {
+        let mut set = Self::default();
+        set.merge(Self::parse(content, "内置规则").expect("内置检测规则无效"));
+        set
+    }
+    pub fn root_from_config(config: &Config) -> Result<Self> {
+        let mut set = Self::bundled(ROOT_RULES);
+        set.merge(vec![DetectionRule::suspicious_packages(&config.suspicious_packages)]);
+        set.with_user_rules(USER_RULES_DIR, &config.root_rule_files)
+    }
+    pub fn hooks_from_config(config: &Config) -> Result<Self> {
+        Self::bundled(HOOK_RULES).with_user_rules(USER_HOOK_RULES_DIR, &config.hook_rule_files)
+    }
+    pub fn emulators_from_config(config: &Config) -> Result<Self> {
+        Self::bundled(EMULATOR_RULES).with_user_rules(USER_EMULATOR_RULES_DIR, &config.emulator_rule_files)
+    }
+    fn with_user_rules(mut self, dir_name: &str, extra: &[PathBuf]) -> Result<Self> {
+        for path in Self::user_paths(dir_name, extra) {
+            self.merge(Self::load_path(&path)?);
+        }
+        Ok(self)
+    }
+    pub fn user_paths(dir_name: &str, extra: &[PathBuf]) -> Vec<PathBuf> {
+        let mut paths: Vec<PathBuf> = Config::user_dir()
+            .map(|dir| dir.join(dir_name))
+            .filter(|dir| dir.is_dir())
+            .into_iter()
+            .collect();
+        paths.extend(extra.iter().cloned());
+        paths
+    }
+    pub fn parse(content: &str, source: &str) -> Result<Vec<DetectionRule>> {
+        let file: RuleFile = toml::from_str(content).map_err(|e| AdbError::Config(format!("{}: {}", source, e)))?;
+        for rule in &file.rules {
+            rule.validate().map_err(|e| AdbError::Config(format!("{}: {}", source, e)))?;
+        }
+        Ok(file.rules)
+    }
+    // 目录按文件名顺序加载其中的 *.toml，后加载的同 id 规则覆盖先前的
+    pub fn load_path(path: &Path) -> Result<Vec<DetectionRule>> {
+        if !path.is_dir() {
+            let content = std::fs::read_to_string(path)
+                .map_err(|e| AdbError::Config(format!("{}: {}", path.display(), e)))?;
+            return Self::parse(&content, &path.display().to_string());
+        }
+        let mut files: Vec<PathBuf> = std::fs::read_dir(path)?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|p| p.extension().is_some_and(|e| e == "toml"))
+            .collect();
+        files.sort();
+        let mut rules = Vec::new();
+        for file in files {
+            rules.extend(Self::load_path(&file)?);
+        }
+        Ok(rules)
+    }
+    pub fn merge(&mut self, rules: Vec<DetectionRule>) {
+        for rule in rules {
+            match self.rules.iter_mut().find(|r| r.id == rule.id) {
+                Some(existing) => *existing = rule,
+                None => self.rules.push(rule),
+            }
+        }
+    }
+    pub fn set_packages(&mut self, packages: &[String]) {
+        self.merge(vec![DetectionRule::suspicious_packages(packages)]);
+    }
+    pub fn enabled(&self) -> impl Iterator<Item = &DetectionRule> {
+        self.rules.iter().filter(|r| r.enabled)
+    }
+    pub async fn evaluate(&self, client: &SharedTransport, device_mgr: &DeviceManager, serial: &str) -> Result<Vec<Finding>> {
+        Ok(self.evaluate_with(client, device_mgr, serial, FactNeeds::default()).await?.0)
+    }
+    // extra 为调用方规则之外还要用到的设备信息，随检测结果一并返回，避免重复读取
+    pub async fn evaluate_with(
+        &self,
+        client: &SharedTransport,
+        device_mgr: &DeviceManager,
+        serial: &str,
+        extra: FactNeeds,
+    ) -> Result<(Vec<Finding>, DeviceFacts)> {
+        let needs = |kinds: &[RuleKind]| self.enabled().any(|r| kinds.contains(&r.kind));
+        let facts = DeviceFacts::collect(
+            client,
+            device_mgr,
+            serial,
+            FactNeeds {
+                packages: extra.packages || needs(&[RuleKind::Package]),
+                props: extra.props || needs(&[RuleKind::Property, RuleKind::Service, RuleKind::Bootconfig]),
+                mounts: extra.mounts || needs(&[RuleKind::Mount]),
+                processes: extra.processes || needs(&[RuleKind::Process, RuleKind::Selinux, RuleKind::Maps]),
+                bootconfig: extra.bootconfig || needs(&[RuleKind::Bootconfig]),
+                ports: extra.ports || needs(&[RuleKind::Port]),
+            },
+        )
+        .await?;
+        let mut findings = Vec::new();
+        for rule in self.enabled() {
+            let finding = match rule.kind {
+                RuleKind::File => Self::run_file_rule(client, serial, rule).await?,
+                RuleKind::Command => Self::run_command_rule(client, serial, rule).await?,
+                RuleKind::Maps => Self::run_maps_rule(client, serial, rule, &facts).await?,
+                _ => rule.finding(Self::match_facts(rule, &facts)),
+            };
+            findings.push(finding);
+        }
+        Ok((findings, facts))
+    }
+    fn match_facts(rule: &DetectionRule, facts: &DeviceFacts) -> Vec<String> {
+        let any_pattern = |value: &str| rule.patterns.iter().any(|p| value.contains(p.as_str()));
+        match rule.kind {
+            RuleKind::Package => facts.packages.iter().filter(|pkg| any_pattern(pkg)).cloned().collect(),
+            RuleKind::Property => rule
+                .property_keys(&facts.props)
+                .into_iter()
+                .filter_map(|key| {
+                    let value = facts.props.get(key)?;
+                    (rule.patterns.is_empty() || rule.patterns.iter().any(|p| p == value)).then(|| format!("{}={}", key, value))
+                })
+                .collect(),
+            RuleKind::Mount => {
+                let mut evidence: Vec<String> = Vec::new();
+                for entry in facts.mounts.iter().filter(|m| rule.matches_mount(m)) {
+                    let line = entry.describe();
+                    if !evidence.contains(&line) {
+                        evidence.push(line);
+                    }
+                }
+                evidence
+            }
+            RuleKind::Service => facts
+                .services()
+                .into_iter()
+                .filter(|(name, _)| rule.matches_name(name))
+                .map(|(name, state)| format!("init.svc.{}={}", name, state))
+                .collect(),
+            RuleKind::Process => facts
+                .processes
+                .iter()
+                .filter(|p| rule.matches_name(&p.name))
+                .map(|p| format!("{} (pid {})", p.name, p.pid))
+                .collect(),
+            RuleKind::Selinux => facts
+                .processes
+                .iter()
+                .filter(|p| any_pattern(&p.context))
+                .map(|p| format!("{} {}", p.context, p.name))
+                .collect(),
+            // 属性值与内核传入的 androidboot.* 不一致，说明属性被 resetprop 之类的工具改写
+            RuleKind::Bootconfig => rule
+                .property_keys(&facts.props)
+                .into_iter()
+                .filter_map(|key| {
+                    let boot_key = format!("androidboot.{}", key.strip_prefix("ro.boot.")?);
+                    let kernel = facts.bootconfig.get(&boot_key)?;
+                    let value = facts.props.get(key).unwrap_or_default();
+                    (kernel != value).then(|| format!("{}={}, {}={}", key, value, boot_key, kernel))
+                })
+                .collect(),
+            RuleKind::Port => facts
+                .ports
+                .iter()
+                .filter(|p| rule.patterns.iter().any(|want| want.parse::<u16>() == Ok(p.port)))
+                .map(|p| p.describe())
+                .collect(),
+            RuleKind::File | RuleKind::Command | RuleKind::Maps => Vec::new(),
+        }
+    }
+    async fn run_file_rule(client: &SharedTransport, serial: &str, rule: &DetectionRule) -> Result<Finding> {
+        let (found, unreadable) = Self::existing_files(client, serial, &rule.patterns).await?;
+        if found.is_empty() && !unreadable.is_empty() {
+            return Ok(Finding::unreadable(&rule.id, rule.description.clone())
+                .with_evidence(unreadable.iter().map(|p| format!("{}: 拒绝访问", p))));
+        }
+        Ok(rule.finding(found))
+    }
+    // 返回 (存在的路径, 无权判断的路径)。/data/adb 等目录为 0700，Shell 下 ls -d 会被拒绝，
+    // 这些路径改用 su 重试；su 也不可用时既不算存在也不算不存在
+    async fn existing_files(client: &SharedTransport, serial: &str, paths: &[String]) -> Result<(Vec<String>, Vec<String>)> {
+        let mut args = vec!["ls", "-d"];
+        args.extend(paths.iter().map(String::as_str));
+        let out = client.shell_output(serial, &args).await?;
+        let listed = |out: &ShellOutput, path: &str| out.stdout.lines().any(|line| line.trim() == path);
+        let reported = |out: &ShellOutput, path: &str, reason: &str| {
+            let prefix = format!("{}:", path);
+            out.stderr.lines().any(|line| line.contains(&prefix) && line.contains(reason))
+        };
+        let mut found: Vec<String> = paths.iter().filter(|p| listed(&out, p)).cloned().collect();
+        let denied: Vec<&String> = paths
+            .iter()
+            .filter(|p| !listed(&out, p) && reported(&out, p, "Permission denied"))
+            .collect();
+        if denied.is_empty() {
+            return Ok((found, Vec::new()));
+        }
+        let quoted: Vec<String> = denied.iter().map(|p| shell_quote(p)).collect();
+        let cmd = shell_quote(&format!("ls -d {}", quoted.join(" ")));
+        let out = client.shell_output(serial, &["su", "-c", &cmd]).await?;
+        let mut unreadable = Vec::new();
+        for path in denied {
+            if listed(&out, path) {
+                found.push(path.clone());
+            } else if !reported(&out, path, "No such file") {
+                unreadable.push(path.clone());
+            }
+        }
+        Ok((found, unreadable))
+    }
+    async fn run_command_rule(client: &SharedTransport, serial: &str, rule: &DetectionRule) -> Result<Finding> {
+        let mut denied = None;
+        for command in rule.commands.iter().filter(|c| !c.trim().is_empty()) {
+            let args: Vec<&str> = command.split_whitespace().collect();
+            let out = client.shell_output(serial, &args).await?;
+            if out.success() {
+                let matched = out
+                    .stdout
+                    .lines()
+                    .find(|line| rule.patterns.iter().any(|p| line.contains(p.as_str())));
+                if let Some(line) = matched {
+                    return Ok(rule.finding(vec![format!("{}: {}", command, line.trim())]));
+                }
+            } else if denied.is_none() && matches!(out.failure(), Some(AdbError::PermissionDenied(_))) {
+                denied = Some(command.clone());
+            }
+        }
+        // 命令存在但被拒绝执行（如 su 未授权 Shell）同样说明存在痕迹，只是置信度降低
+        Ok(match denied {
+            Some(command) => {
+                let mut finding = rule.finding(vec![format!("{}: 拒绝授权 (请在 Root 管理器中允许 Shell)", command)]);
+                finding.confidence = finding.confidence.min(Confidence::Medium);
+                finding
+            }
+            None => rule.finding(Vec::new()),
+        })
+    }
+    // 读取目标进程（默认 zygote/zygote64）的 /proc/<pid>/maps，普通 Shell 通常无权读取，此时尝试 su
+    async fn run_maps_rule(client: &SharedTransport, serial: &str, rule: &DetectionRule, facts: &DeviceFacts) -> Result<Finding> {
+        let target = rule.process.as_deref().unwrap_or("zygote");
+        let targets: Vec<_> = facts
+            .processes
+            .iter()
+            .filter(|p| p.name == target || p.name.strip_prefix(target).is_some_and(|rest| rest == "64" || rest == "32"))
+            .collect();
+        let mut evidence: Vec<String> = Vec::new();
+        let mut unreadable = Vec::new();
+        for process in &targets {
+            let Some(regions) = facts::read_maps(client, serial, &process.pid).await? else {
+                unreadable.push(format!("{} (pid {})", process.name, process.pid));
+                continue;
+            };
+            for path in regions.iter().filter(|path| rule.patterns.iter().any(|p| path.contains(p.as_str()))) {
+                let line = format!("{} (pid {}): {}", process.name, process.pid, path);
+                if !evidence.contains(&line) {
+                    evidence.push(line);
+                }
+            }
+        }
+        let mut finding = rule.finding(evidence);
+        if !finding.detected && !unreadable.is_empty() {
+            finding.evidence.push(format!("无权读取 maps: {}", unreadable.join(", ")));
+        }
+        Ok(finding)
+    }
+}
@@ -0,0 +1,1022 @@
+﻿use crate::adb::inventory::Inventory;
+use crate::adb::policy::RetryPolicy;
+use crate::adb::protocol::{self, AdbServer};
+use crate::adb::wireless::{RememberedDevices, DEFAULT_TCPIP_PORT};
+use crate::adb::{AdbClient, AdbDiscovery, DeviceManager, DeviceWatcher, SharedTransport};
+use crate::audit::AuditRunner;
+use crate::backup::{BackupEngine, BackupItem, RestoreEngine, RestoreMode};
+use crate::batch::{BatchOperation, BatchRunner};
+use crate::checks::{BootloaderChecker, EmulatorChecker, HookChecker, RootChecker};
+use crate::core::config::Config;
+use crate::core::AdbError;
+use crate::snapshot::{self, SnapshotDiff, SnapshotStore};
+use crate::stress::StressRunner;
+use crate::ui::ConsoleUi;
+use serde_json::json;
+use std::path::PathBuf;
+use std::time::Duration;
+pub const EXIT_OK: i32 = 0;
+pub const EXIT_ERROR: i32 = 1;
+pub const EXIT_FINDINGS: i32 = 2;
+pub const EXIT_USAGE: i32 = 64;
+pub const EXIT_CONFIG: i32 = 78;
+pub const EXIT_INTERRUPTED: i32 = 130;
+const TCPIP_RESTART_DELAY: Duration = Duration::from_secs(2);
+pub const USAGE: &str = "用法: androidchecker [命令] [选项]
+
+不带参数运行时进入交互菜单。
+
+命令:
+  devices                   列出已连接设备
+  root-check                Root 环境检测 (检测到 Root 时退出码为 2)
+  bootloader                引导/BL 锁状态 (已解锁或验证失败时退出码为 2)
+  hook-check                Hook/注入框架检测 (Xposed/LSPosed、Frida、Riru/Zygisk，检测到时退出码为 2)
+  emulator-check            模拟器/虚拟设备检测 (判定为模拟器时退出码为 2)
+  audit                     系统与硬件安全检查 (任一检测命中时退出码为 2)
+  screencap                 截取屏幕，保存为 PNG (-o 指定文件，默认保存到当前目录)
+  cat <设备路径>            按原始字节输出设备上的文件 (可配合 -o 写入文件)
+  ls <设备路径>             列出设备目录 (经 adb server 时带类型、大小和修改时间)
+  backup [--items a,b]      创建备份 (user-files, app-list, app-data, settings)
+  restore <file> [--items]  从备份文件恢复
+  stress [--duration 秒]    ADB 稳定性测试 (存在失败时退出码为 2)
+  watch                     持续输出设备接入/断开/状态变化事件 (Ctrl+C 结束)
+  pair <地址:端口> <配对码>  无线调试配对 (Android 11+)
+  connect [地址[:端口]]     连接网络设备 (省略地址时重连所有记住的设备)
+  disconnect [地址]         断开网络设备 (省略地址时断开全部)
+  tcpip [--port 端口]       将 USB 设备切换到 TCP/IP 模式并自动连接
+  network                   列出记住的网络设备及 mDNS 发现的服务
+  forget <地址>             从记住的网络设备中移除
+  snapshot save <名称>      保存设备属性快照
+  snapshot list             列出设备的属性快照
+  snapshot diff <旧> [新]   比较两个快照 (新快照省略或为 current 时使用设备当前属性)
+  snapshot delete <名称>    删除属性快照
+  batch <操作>              在多台设备上并发执行 root-check/bootloader/hook-check/emulator-check/audit/backup/stress
+                            (默认全部就绪设备，-s 可用逗号指定多台)
+  inventory [list] [关键字]  列出资产清单中出现过的设备 (可按序列号/品牌/型号筛选)
+  inventory show <serial>   显示单台设备的资产记录
+  inventory export          导出资产清单 (--format csv|json，配合 -o 写入文件)
+  config                    显示生效的配置及其来源
+  doctor                    诊断 adb 查找过程及服务状态
+  help                      显示本帮助
+
+选项:
+  -s, --serial <serial>     目标设备 (仅连接一台设备时可省略)
+      --json                以 JSON 输出结果
+      --port <端口>         tcpip 使用的端口 (默认 5555)
+      --prefix <前缀,...>   snapshot diff 只比较指定前缀的属性 (如 ro.boot.,persist.)
+      --format <csv|json>   inventory export 的格式 (默认按 -o 扩展名，否则 csv)
+      --concurrency <数量>  batch 同时处理的设备数 (默认取配置 batch_concurrency)
+  -o, --output <path>       结果写入文件 (backup 为备份输出目录，screencap/cat 为数据文件)
+";
+#[derive(Debug, Clone)]
+pub enum Command {
+    Devices,
+    RootCheck,
+    Bootloader,
+    HookCheck,
+    EmulatorCheck,
+    Audit,
+    Screencap,
+    Cat { path: String },
+    Ls { path: String },
+    Backup { items: Vec<BackupItem> },
+    Restore { file: PathBuf, items: Vec<BackupItem> },
+    Stress { duration: Option<u64> },
+    Watch,
+    Pair { address: String, code: String },
+    Connect { address: Option<String> },
+    Disconnect { address: Option<String> },
+    Tcpip { port: u16 },
+    Network,
+    Forget { address: String },
+    Snapshot { action: SnapshotAction, prefixes: Vec<String> },
+    Batch { operation: BatchOperation, concurrency: Option<usize> },
+    Inventory(InventoryAction),
+    Config,
+    Doctor,
+    Help,
+}
+#[derive(Debug, Clone)]
+pub enum SnapshotAction {
+    Save(String),
+    List,
+    Diff { from: String, to: String },
+    Delete(String),
+}
+#[derive(Debug, Clone)]
+pub enum InventoryAction {
+    List(Option<String>),
+    Show(String),
+    Export { json: bool },
+}
+#[derive(Debug, Clone)]
+pub struct Cli {
+    pub command: Command,
+    pub serial: Option<String>,
+    pub json: bool,
+    pub output: Option<PathBuf>,
+}
+impl Cli {
+    // 参数有误时输出原因和用法，返回应使用的退出码
+    pub fn from_args(args: &[String]) -> Result<Self, i32> {
+        Self::parse(args).map_err(|msg| {
+            ConsoleUi::error(&msg);
+            eprint!("\n{}", USAGE);
+            EXIT_USAGE
+        })
+    }
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let mut serial = None;
+        let mut json = false;
+        let mut output = None;
+        let mut items = Vec::new();
+        let mut duration = None;
+        let mut port = None;
+        let mut prefixes = Vec::new();
+        let mut format = None;
+        let mut concurrency = None;
+        let mut positional = Vec::new();
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "-s" | "--serial" => serial = Some(Self::value(&mut iter, arg)?),
+                "--json" => json = true,
+                "-o" | "--output" => output = Some(PathBuf::from(Self::value(&mut iter, arg)?)),
+                "--items" => items = Self::parse_items(&Self::value(&mut iter, arg)?)?,
+                "--duration" => {
+                    let v = Self::value(&mut iter, arg)?;
+                    duration = Some(v.parse::<u64>().map_err(|_| format!("无效的 --duration: {}", v))?);
+                }
+                "--port" => {
+                    let v = Self::value(&mut iter, arg)?;
+                    port = Some(v.parse::<u16>().map_err(|_| format!("无效的 --port: {}", v))?);
+                }
+                "--prefix" => prefixes.extend(
+                    Self::value(&mut iter, arg)?
+                        .split(',')
+                        .map(|s| s.trim().to_string())
+                        .filter(|s| !s.is_empty()),
+                ),
+                "--format" => format = Some(Self::value(&mut iter, arg)?),
+                "--concurrency" => {
+                    let v = Self::value(&mut iter, arg)?;
+                    concurrency = Some(
+                        v.parse::<usize>()
+                            .ok()
+                            .filter(|n| *n > 0)
+                            .ok_or_else(|| format!("无效的 --concurrency: {}", v))?,
+                    );
+                }
+                "-h" | "--help" => positional.insert(0, "help".to_string()),
+                s if s.starts_with('-') => return Err(format!("未知选项: {}", s)),
+                s => positional.push(s.to_string()),
+            }
+        }
+        let name = positional.first().map(|s| s.as_str()).unwrap_or("help");
+        let command = match name {
+            "devices" => Command::Devices,
+            "root-check" => Command::RootCheck,
+            "bootloader" => Command::Bootloader,
+            "hook-check" => Command::HookCheck,
+            "emulator-check" => Command::EmulatorCheck,
+            "audit" => Command::Audit,
+            "screencap" => Command::Screencap,
+            "cat" => Command::Cat {
+                path: positional
+                    .get(1)
+                    .cloned()
+                    .ok_or_else(|| "cat 需要设备上的文件路径".to_string())?,
+            },
+            "ls" => Command::Ls {
+                path: positional
+                    .get(1)
+                    .cloned()
+                    .ok_or_else(|| "ls 需要设备上的路径".to_string())?,
+            },
+            "backup" => Command::Backup {
+                items: if items.is_empty() { BackupItem::all_items() } else { items },
+            },
+            "restore" => Command::Restore {
+                file: positional
+                    .get(1)
+                    .map(PathBuf::from)
+                    .ok_or_else(|| "restore 需要备份文件路径".to_string())?,
+                items,
+            },
+            "stress" => Command::Stress { duration },
+            "watch" => Command::Watch,
+            "pair" => match (positional.get(1), positional.get(2)) {
+                (Some(address), Some(code)) => Command::Pair {
+                    address: address.clone(),
+                    code: code.clone(),
+                },
+                _ => return Err("pair 需要地址和配对码".to_string()),
+            },
+            "connect" => Command::Connect {
+                address: positional.get(1).cloned(),
+            },
+            "disconnect" => Command::Disconnect {
+                address: positional.get(1).cloned(),
+            },
+            "tcpip" => Command::Tcpip {
+                port: port.unwrap_or(DEFAULT_TCPIP_PORT),
+            },
+            "network" => Command::Network,
+            "forget" => Command::Forget {
+                address: positional
+                    .get(1)
+                    .cloned()
+                    .ok_or_else(|| "forget 需要设备地址".to_string())?,
+            },
+            "snapshot" => Command::Snapshot {
+                action: Self::parse_snapshot(&positional[1..])?,
+                prefixes,
+            },
+            "batch" => {
+                let key = positional.get(1).ok_or_else(|| "batch 需要操作名称".to_string())?;
+                Command::Batch {
+                    operation: BatchOperation::from_key(key).ok_or_else(|| format!("未知的批量操作: {}", key))?,
+                    concurrency,
+                }
+            }
+            "inventory" => Command::Inventory(Self::parse_inventory(
+                &positional[1..],
+                format.as_deref().or(json.then_some("json")),
+                output.as_ref(),
+            )?),
+            "config" => Command::Config,
+            "doctor" => Command::Doctor,
+            "help" => Command::Help,
+            other => return Err(format!("未知命令: {}", other)),
+        };
+        Ok(Self {
+            command,
+            serial,
+            json,
+            output,
+        })
+    }
+    fn value<'a>(iter: &mut impl Iterator<Item = &'a String>, flag: &str) -> Result<String, String> {
+        iter.next().cloned().ok_or_else(|| format!("{} 需要参数", flag))
+    }
+    fn parse_snapshot(args: &[String]) -> Result<SnapshotAction, String> {
+        let name = |idx: usize, what: &str| args.get(idx).cloned().ok_or_else(|| format!("snapshot {} 需要快照名称", what));
+        match args.first().map(|s| s.as_str()) {
+            Some("save") => Ok(SnapshotAction::Save(name(1, "save")?)),
+            Some("list") | None => Ok(SnapshotAction::List),
+            Some("diff") => Ok(SnapshotAction::Diff {
+                from: name(1, "diff")?,
+                to: args.get(2).cloned().unwrap_or_else(|| snapshot::CURRENT.to_string()),
+            }),
+            Some("delete") => Ok(SnapshotAction::Delete(name(1, "delete")?)),
+            Some(other) => Err(format!("未知的 snapshot 操作: {}", other)),
+        }
+    }
+    fn parse_inventory(args: &[String], format: Option<&str>, output: Option<&PathBuf>) -> Result<InventoryAction, String> {
+        match args.first().map(|s| s.as_str()) {
+            Some("list") => Ok(InventoryAction::List(args.get(1).cloned())),
+            None => Ok(InventoryAction::List(None)),
+            Some("show") => args
+                .get(1)
+                .cloned()
+                .map(InventoryAction::Show)
+                .ok_or_else(|| "inventory show 需要设备序列号".to_string()),
+            Some("export") => {
+                let from_ext = output.and_then(|p| p.extension()).and_then(|e| e.to_str());
+                match format.or(from_ext).unwrap_or("csv") {
+                    "csv" => Ok(InventoryAction::Export { json: false }),
+                    "json" => Ok(InventoryAction::Export { json: true }),
+                    other => Err(format!("不支持的导出格式: {}", other)),
+                }
+            }
+            Some(filter) => Ok(InventoryAction::List(Some(filter.to_string()))),
+        }
+    }
+    fn parse_items(list: &str) -> Result<Vec<BackupItem>, String> {
+        list.split(',')
+            .map(|s| s.trim())
+            .filter(|s| !s.is_empty())
+            .map(|s| BackupItem::from_key(s).ok_or_else(|| format!("未知备份项目: {}", s)))
+            .collect()
+    }
+}
+pub struct CliRunner {
+    client: SharedTransport,
+    device_mgr: DeviceManager,
+    config: Config,
+    cli: Cli,
+}
+impl CliRunner {
+    pub fn new(client: SharedTransport, config: Config, cli: Cli) -> Self {
+        let device_mgr = DeviceManager::new(client.clone())
+            .with_remembered_file(RememberedDevices::default_path())
+            .with_inventory_file(Inventory::default_path());
+        Self {
+            client,
+            device_mgr,
+            config,
+            cli,
+        }
+    }
+    pub fn show_config(config: &Config, as_json: bool) -> i32 {
+        let rendered = if as_json {
+            serde_json::to_string_pretty(config).map_err(|e| e.to_string())
+        } else {
+            toml::to_string_pretty(config).map_err(|e| e.to_string())
+        };
+        match rendered {
+            Ok(text) => {
+                println!("{}", text.trim_end());
+                let sources: Vec<String> = config.sources.iter().map(|p| p.display().to_string()).collect();
+                eprintln!("\n配置来源: {}", if sources.is_empty() { "(默认值)".to_string() } else { sources.join(", ") });
+                EXIT_OK
+            }
+            Err(e) => {
+                eprintln!("错误: {}", e);
+                EXIT_ERROR
+            }
+        }
+    }
+    pub async fn run(&self) -> i32 {
+        match self.dispatch().await {
+            Ok(code) => code,
+            Err(e) => {
+                let hint = e.downcast_ref::<AdbError>().and_then(AdbError::hint);
+                if self.cli.json {
+                    let body = json!({ "error": e.to_string(), "hint": hint }).to_string();
+                    if self.emit(body.clone()).is_err() {
+                        println!("{}", body);
+                    }
+                } else {
+                    eprintln!("错误: {}", e);
+                    if let Some(hint) = hint {
+                        eprintln!("提示: {}", hint);
+                    }
+                }
+                match e.downcast_ref::<AdbError>() {
+                    Some(AdbError::Cancelled(_)) => EXIT_INTERRUPTED,
+                    _ => EXIT_ERROR,
+                }
+            }
+        }
+    }
+    pub async fn doctor(config: &Config, as_json: bool) -> i32 {
+        let discovery = AdbDiscovery::run(config.adb_path.as_deref());
+        let (version, calls) = match AdbClient::from_config(config) {
+            Ok(client) => {
+                let client = client.connect_server().await;
+                let channel = if client.is_native() { "adb server 协议" } else { "adb 子进程" };
+                let options = client.options();
+                let calls = format!(
+                    "经 {}，超时 {} 秒，最多尝试 {} 次",
+                    channel,
+                    options.timeout.as_secs(),
+                    options.retry.max_attempts
+                );
+                // 诊断要看到第一次调用的真实结果，不重试
+                let version = match client.with_retry(RetryPolicy::none()).execute(&["version"]).await {
+                    Ok((out, _)) => out.lines().next().unwrap_or_default().to_string(),
+                    Err(e) => format!("adb version 失败: {}", e),
+                };
+                (version, Some(calls))
+            }
+            Err(e) => (e.to_string(), None),
+        };
+        let server_addr = protocol::default_server_addr();
+        let server = match AdbServer::new(server_addr).version().await {
+            Ok(v) => format!("{} 在线 (协议版本 {})", server_addr, v),
+            Err(e) => format!("{} 不可用: {}", server_addr, e),
+        };
+        if as_json {
+            println!(
+                "{}",
+                json!({ "discovery": discovery, "adb_version": version, "calls": calls, "server": server })
+            );
+        } else {
+            print!("{}", discovery.render());
+            println!("adb 版本: {}", version);
+            if let Some(calls) = &calls {
+                println!("adb 调用: {}", calls);
+            }
+            println!("adb 服务: {}", server);
+        }
+        if discovery.selected.is_some() { EXIT_OK } else { EXIT_ERROR }
+    }
+    async fn dispatch(&self) -> anyhow::Result<i32> {
+        match &self.cli.command {
+            Command::Help => {
+                print!("{}", USAGE);
+                Ok(EXIT_OK)
+            }
+            Command::Config => Ok(Self::show_config(&self.config, self.cli.json)),
+            Command::Doctor => Ok(Self::doctor(&self.config, self.cli.json).await),
+            Command::Devices => {
+                let devices = self.device_mgr.list_devices().await?;
+                if self.cli.json {
+                    self.emit(serde_json::to_string_pretty(&devices)?)?;
+                } else {
+                    let text = devices
+                        .iter()
+                        .map(|d| format!("{}\t{}\t{}", d.serial, d.state, d.display_name()))
+                        .collect::<Vec<_>>()
+                        .join("\n");
+                    self.emit(text)?;
+                }
+                Ok(EXIT_OK)
+            }
+            Command::RootCheck => {
+                let serial = self.resolve_serial().await?;
+                let checker = RootChecker::from_config(self.device_mgr.scoped(), &self.config)?;
+                let report = checker.detect(&serial).await?;
+                if self.cli.json {
+                    self.emit(serde_json::to_string_pretty(&report)?)?;
+                } else {
+                    self.emit(report.render())?;
+                }
+                Ok(if report.detected { EXIT_FINDINGS } else { EXIT_OK })
+            }
+            Command::Bootloader => {
+                let serial = self.resolve_serial().await?;
+                let checker = BootloaderChecker::new(self.device_mgr.scoped());
+                let report = checker.inspect(&serial).await?;
+                if self.cli.json {
+                    self.emit(serde_json::to_string_pretty(&report)?)?;
+                } else {
+                    self.emit(report.render())?;
+                }
+                Ok(if report.detected { EXIT_FINDINGS } else { EXIT_OK })
+            }
+            Command::HookCheck => {
+                let serial = self.resolve_serial().await?;
+                let checker = HookChecker::from_config(self.device_mgr.scoped(), &self.config)?;
+                let report = checker.detect(&serial).await?;
+                if self.cli.json {
+                    self.emit(serde_json::to_string_pretty(&report)?)?;
+                } else {
+                    self.emit(report.render())?;
+                }
+                Ok(if report.detected { EXIT_FINDINGS } else { EXIT_OK })
+            }
+            Command::EmulatorCheck => {
+                let serial = self.resolve_serial().await?;
+                let checker = EmulatorChecker::from_config(self.device_mgr.scoped(), &self.config)?;
+                let report = checker.detect(&serial).await?;
+                if self.cli.json {
+                    self.emit(serde_json::to_string_pretty(&report)?)?;
+                } else {
+                    self.emit(report.render())?;
+                }
+                Ok(if report.detected { EXIT_FINDINGS } else { EXIT_OK })
+            }
+            Command::Audit => {
+                let serial = self.resolve_serial().await?;
+                let dm = self.device_mgr.scoped();
+                let report = AuditRunner::new(dm.clone())
+                    .with_root_checker(RootChecker::from_config(dm.clone(), &self.config)?)
+                    .with_hook_checker(HookChecker::from_config(dm.clone(), &self.config)?)
+                    .with_emulator_checker(EmulatorChecker::from_config(dm, &self.config)?)
+                    .run(&serial, None)
+                    .await?;
+                if self.cli.json {
+                    self.emit(serde_json::to_string_pretty(&report)?)?;
+                } else {
+                    self.emit(report.summary())?;
+                }
+                Ok(if report.detected() { EXIT_FINDINGS } else { EXIT_OK })
+            }
+            Command::Screencap => {
+                let serial = self.resolve_serial().await?;
+                let png = self.device_mgr.screencap(&serial).await?;
+                let path = self.cli.output.clone().unwrap_or_else(|| {
+                    PathBuf::from(format!("screencap_{}_{}.png", serial.replace(':', "_"), chrono::Local::now().format("%Y%m%d_%H%M%S")))
+                });
+                std::fs::write(&path, &png)?;
+                if self.cli.json {
+                    self.emit(json!({ "serial": serial, "path": path, "bytes": png.len() }).to_string())?;
+                } else {
+                    self.emit(format!("已保存截图 ({} 字节): {}", png.len(), path.display()))?;
+                }
+                Ok(EXIT_OK)
+            }
+            Command::Cat { path } => {
+                let serial = self.resolve_serial().await?;
+                let bytes = self.device_mgr.read_file(&serial, path).await?;
+                match &self.cli.output {
+                    Some(output) => std::fs::write(output, &bytes)?,
+                    None => {
+                        use std::io::Write;
+                        let mut stdout = std::io::stdout().lock();
+                        stdout.write_all(&bytes)?;
+                        stdout.flush()?;
+                    }
+                }
+                Ok(EXIT_OK)
+            }
+            Command::Ls { path } => {
+                let serial = self.resolve_serial().await?;
+                let mut entries = self.client.list_dir(&serial, path).await?;
+                entries.sort_by(|a, b| a.name.cmp(&b.name));
+                if self.cli.json {
+                    let body: Vec<_> = entries
+                        .iter()
+                        .map(|e| json!({ "name": e.name_lossy(), "mode": e.stat.mode, "size": e.stat.size, "mtime": e.stat.mtime }))
+                        .collect();
+                    self.emit(serde_json::to_string_pretty(&body)?)?;
+                } else {
+                    let text = entries
+                        .iter()
+                        .map(|e| {
+                            if !e.stat.exists() {
+                                return e.name_lossy();
+                            }
+                            let kind = if e.stat.is_dir() { 'd' } else if e.stat.is_symlink() { 'l' } else { '-' };
+                            let mtime = chrono::DateTime::from_timestamp(e.stat.mtime as i64, 0)
+                                .map(|t| t.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string())
+                                .unwrap_or_default();
+                            format!("{} {:>12} {} {}", kind, e.stat.size, mtime, e.name_lossy())
+                        })
+                        .collect::<Vec<_>>()
+                        .join("\n");
+                    self.emit(text)?;
+                }
+                Ok(EXIT_OK)
+            }
+            Command::Backup { items } => {
+                let serial = self.resolve_serial().await?;
+                let mut engine = BackupEngine::new(self.device_mgr.scoped()).with_verify(self.config.verify_transfers);
+                if let Some(dir) = self.cli.output.as_ref().or(self.config.backup_dir.as_ref()) {
+                    engine = engine.with_output_dir(dir.clone());
+                }
+                let archive = engine.start_backup(&serial, items.clone()).await?;
+                if self.cli.json {
+                    self.emit(json!({ "serial": serial, "archive": archive }).to_string())?;
+                }
+                Ok(EXIT_OK)
+            }
+            Command::Restore { file, items } => {
+                let serial = self.resolve_serial().await?;
+                let mode = if items.is_empty() {
+                    RestoreMode::Full
+                } else {
+                    RestoreMode::Selective(items.clone())
+                };
+                RestoreEngine::new(self.client.clone())
+                    .with_verify(self.config.verify_transfers)
+                    .start_restore(&serial, file, mode)
+                    .await?;
+                if self.cli.json {
+                    self.emit(json!({ "serial": serial, "restored": file }).to_string())?;
+                }
+                Ok(EXIT_OK)
+            }
+            Command::Watch => self.watch().await,
+            Command::Pair { address, code } => {
+                let message = self.device_mgr.pair(address, code).await?;
+                self.emit_status(address, &message)?;
+                Ok(EXIT_OK)
+            }
+            Command::Connect { address: Some(address) } => {
+                let message = self.device_mgr.connect(address).await?;
+                self.emit_status(address, &message)?;
+                Ok(EXIT_OK)
+            }
+            Command::Connect { address: None } => self.reconnect_remembered().await,
+            Command::Disconnect { address } => {
+                let message = self.device_mgr
+                    .disconnect(address.as_deref())
+                    .await?;
+                self.emit_status(address.as_deref().unwrap_or("*"), &message)?;
+                Ok(EXIT_OK)
+            }
+            Command::Tcpip { port } => {
+                let serial = self.resolve_serial().await?;
+                let manager = &self.device_mgr;
+                let address = manager.tcpip(&serial, *port).await?;
+                let Some(address) = address else {
+                    anyhow::bail!("已切换到 TCP/IP 模式，但无法获取设备 Wi-Fi 地址，请手动执行 connect <地址>:{}", port);
+                };
+                tokio::time::sleep(TCPIP_RESTART_DELAY).await;
+                let message = manager.connect(&address).await?;
+                self.emit_status(&address, &message)?;
+                Ok(EXIT_OK)
+            }
+            Command::Network => self.network().await,
+            Command::Snapshot { action, prefixes } => self.snapshot(action, prefixes).await,
+            Command::Batch { operation, concurrency } => self.batch(*operation, *concurrency).await,
+            Command::Inventory(action) => self.inventory(action),
+            Command::Forget { address } => {
+                let removed = self.device_mgr.forget(address)?;
+                if !removed {
+                    anyhow::bail!("未记住设备: {}", address);
+                }
+                self.emit_status(address, "已移除")?;
+                Ok(EXIT_OK)
+            }
+            Command::Stress { duration } => {
+                let serial = self.resolve_serial().await?;
+                let duration = duration.unwrap_or(self.config.stress_duration_secs);
+                let watcher = DeviceWatcher::start(self.client.clone());
+                let stress = StressRunner::new(self.client.clone()).with_watcher(&watcher);
+                let (ok, fail) = stress.adb_stability_test(&serial, duration).await?;
+                if self.cli.json {
+                    let body = json!({ "serial": serial, "duration_secs": duration, "success": ok, "failed": fail });
+                    self.emit(serde_json::to_string_pretty(&body)?)?;
+                } else {
+                    self.emit(format!("测试完成: 成功 {} 次, 失败 {} 次", ok, fail))?;
+                }
+                Ok(if fail > 0 { EXIT_FINDINGS } else { EXIT_OK })
+            }
+        }
+    }
+    async fn watch(&self) -> anyhow::Result<i32> {
+        let watcher = DeviceWatcher::start(self.client.clone());
+        let mut events = watcher.subscribe();
+        loop {
+            let event = tokio::select! {
+                _ = tokio::signal::ctrl_c() => {
+                    watcher.stop();
+                    return Ok(EXIT_INTERRUPTED);
+                }
+                event = events.recv() => event,
+            };
+            let event = match event {
+                Ok(event) => event,
+                Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(tokio::sync::broadcast::error::RecvError::Closed) => return Ok(EXIT_ERROR),
+            };
+            let time = chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string();
+            if self.cli.json {
+                let mut body = serde_json::to_value(&event)?;
+                body["time"] = json!(time);
+                println!("{}", body);
+            } else {
+                println!("[{}] {}", time, event.describe());
+            }
+        }
+    }
+    async fn batch(&self, operation: BatchOperation, concurrency: Option<usize>) -> anyhow::Result<i32> {
+        let devices = self.device_mgr.list_devices().await?;
+        let devices: Vec<_> = match &self.cli.serial {
+            Some(list) => {
+                let wanted: Vec<&str> = list.split(',').map(str::trim).filter(|s| !s.is_empty()).collect();
+                if let Some(missing) = wanted.iter().find(|s| !devices.iter().any(|d| d.serial == **s)) {
+                    anyhow::bail!("未找到设备: {}", missing);
+                }
+                devices.into_iter().filter(|d| wanted.contains(&d.serial.as_str())).collect()
+            }
+            None => devices.into_iter().filter(|d| d.state.is_online()).collect(),
+        };
+        if devices.is_empty() {
+            anyhow::bail!("未发现已授权的设备");
+        }
+        let mut runner = BatchRunner::new(self.device_mgr.clone(), self.config.clone());
+        if let Some(concurrency) = concurrency {
+            runner = runner.with_concurrency(concurrency);
+        }
+        let results = runner.run(&devices, operation).await;
+        if self.cli.json {
+            let body = json!({ "operation": operation, "results": results });
+            self.emit(serde_json::to_string_pretty(&body)?)?;
+        } else {
+            self.emit(BatchRunner::render(&results))?;
+        }
+        Ok(if results.iter().any(|r| !r.ok) {
+            EXIT_ERROR
+        } else if results.iter().any(|r| r.findings) {
+            EXIT_FINDINGS
+        } else {
+            EXIT_OK
+        })
+    }
+    fn inventory(&self, action: &InventoryAction) -> anyhow::Result<i32> {
+        let inventory = self.device_mgr.inventory()?;
+        match action {
+            InventoryAction::List(filter) => {
+                let records = inventory.query(filter.as_deref());
+                if self.cli.json {
+                    self.emit(serde_json::to_string_pretty(&records)?)?;
+                } else if records.is_empty() {
+                    self.emit("资产清单中没有匹配的设备".to_string())?;
+                } else {
+                    let text = records
+                        .iter()
+                        .map(|r| {
+                            format!(
+                                "{}\t{}\tAndroid {}\t补丁 {}\tBL {}\t最后出现 {}",
+                                r.serial,
+                                r.display_name(),
+                                r.android_version.as_deref().unwrap_or("-"),
+                                r.security_patch.as_deref().unwrap_or("-"),
+                                r.bootloader.as_deref().unwrap_or("-"),
+                                r.last_seen
+                            )
+                        })
+                        .collect::<Vec<_>>()
+                        .join("\n");
+                    self.emit(text)?;
+                }
+            }
+            InventoryAction::Show(serial) => {
+                let Some(record) = inventory.get(serial) else {
+                    anyhow::bail!("资产清单中没有设备 {}", serial);
+                };
+                if self.cli.json {
+                    self.emit(serde_json::to_string_pretty(record)?)?;
+                } else {
+                    let value = |v: &Option<String>| v.clone().unwrap_or_else(|| "-".to_string());
+                    let lines = [
+                        ("序列号", record.serial.clone()),
+                        ("设备", record.display_name()),
+                        ("Android", value(&record.android_version)),
+                        ("SDK", value(&record.sdk_version)),
+                        ("安全补丁", value(&record.security_patch)),
+                        ("Bootloader", value(&record.bootloader)),
+                        ("最后状态", value(&record.last_state)),
+                        ("最近审计", value(&record.last_audit)),
+                        ("审计时间", value(&record.last_audit_at)),
+                        ("最近备份", value(&record.last_backup)),
+                        ("备份时间", value(&record.last_backup_at)),
+                        ("首次出现", record.first_seen.clone()),
+                        ("最后出现", record.last_seen.clone()),
+                    ];
+                    let text = lines
+                        .iter()
+                        .map(|(k, v)| format!("{}: {}", k, v))
+                        .collect::<Vec<_>>()
+                        .join("\n");
+                    self.emit(text)?;
+                }
+            }
+            InventoryAction::Export { json } => {
+                let records = inventory.query(None);
+                if *json {
+                    self.emit(serde_json::to_string_pretty(&records)?)?;
+                } else {
+                    self.emit(Inventory::to_csv(&records))?;
+                }
+                if let Some(path) = &self.cli.output {
+                    eprintln!("已导出 {} 台设备到 {}", records.len(), path.display());
+                }
+            }
+        }
+        Ok(EXIT_OK)
+    }
+    async fn snapshot(&self, action: &SnapshotAction, prefixes: &[String]) -> anyhow::Result<i32> {
+        let serial = self.resolve_serial().await?;
+        let store = SnapshotStore::default_dir()?;
+        let manager = &self.device_mgr;
+        match action {
+            SnapshotAction::Save(name) => {
+                let snapshot = store.take(manager, &serial, name).await?;
+                let path = store.save(&snapshot)?;
+                if self.cli.json {
+                    let body = json!({ "serial": serial, "name": name, "properties": snapshot.properties.len(), "path": path });
+                    self.emit(serde_json::to_string_pretty(&body)?)?;
+                } else {
+                    self.emit(format!("已保存快照 {} ({} 项属性): {}", name, snapshot.properties.len(), path.display()))?;
+                }
+            }
+            SnapshotAction::List => {
+                let snapshots = store.list(&serial)?;
+                if self.cli.json {
+                    let body: Vec<_> = snapshots
+                        .iter()
+                        .map(|s| json!({ "name": s.name, "taken_at": s.taken_at, "properties": s.properties.len() }))
+                        .collect();
+                    self.emit(serde_json::to_string_pretty(&body)?)?;
+                } else {
+                    let text = snapshots
+                        .iter()
+                        .map(|s| format!("{}\t{}\t{} 项", s.name, s.taken_at, s.properties.len()))
+                        .collect::<Vec<_>>()
+                        .join("\n");
+                    self.emit(text)?;
+                }
+            }
+            SnapshotAction::Diff { from, to } => {
+                let before = store.resolve(manager, &serial, from).await?;
+                let after = store.resolve(manager, &serial, to).await?;
+                let diff = SnapshotDiff::between(&before, &after, prefixes);
+                if self.cli.json {
+                    self.emit(serde_json::to_string_pretty(&diff)?)?;
+                } else {
+                    self.emit(diff.render())?;
+                }
+                return Ok(if diff.is_empty() { EXIT_OK } else { EXIT_FINDINGS });
+            }
+            SnapshotAction::Delete(name) => {
+                store.load(&serial, name)?;
+                store.delete(&serial, name)?;
+                self.emit_status(&serial, &format!("已删除快照 {}", name))?;
+            }
+        }
+        Ok(EXIT_OK)
+    }
+    async fn reconnect_remembered(&self) -> anyhow::Result<i32> {
+        let results = self.device_mgr.reconnect_remembered().await?;
+        if results.is_empty() {
+            anyhow::bail!("没有记住的网络设备，请使用 connect <地址> 连接");
+        }
+        let failed = results.iter().filter(|(_, r)| r.is_err()).count();
+        if self.cli.json {
+            let body: Vec<_> = results
+                .iter()
+                .map(|(address, r)| match r {
+                    Ok(message) => json!({ "address": address, "ok": true, "message": message }),
+                    Err(e) => json!({ "address": address, "ok": false, "error": e.to_string(), "hint": e.hint() }),
+                })
+                .collect();
+            self.emit(serde_json::to_string_pretty(&body)?)?;
+        } else {
+            let text = results
+                .iter()
+                .map(|(address, r)| match r {
+                    Ok(message) => format!("{}\t{}", address, message),
+                    Err(e) => format!("{}\t失败: {}", address, e),
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+            self.emit(text)?;
+        }
+        Ok(if failed == results.len() { EXIT_ERROR } else { EXIT_OK })
+    }
+    async fn network(&self) -> anyhow::Result<i32> {
+        let manager = &self.device_mgr;
+        let remembered = manager.remembered_devices()?;
+        let services = manager.mdns_services().await?;
+        let connected: Vec<String> = manager.list_devices().await?.into_iter().map(|d| d.serial).collect();
+        if self.cli.json {
+            let remembered: Vec<_> = remembered
+                .iter()
+                .map(|d| {
+                    let mut body = serde_json::to_value(d).unwrap_or_default();
+                    body["connected"] = json!(connected.contains(&d.address));
+                    body
+                })
+                .collect();
+            let body = json!({ "remembered": remembered, "mdns": services });
+            self.emit(serde_json::to_string_pretty(&body)?)?;
+            return Ok(EXIT_OK);
+        }
+        let mut lines = vec!["记住的网络设备:".to_string()];
+        if remembered.is_empty() {
+            lines.push("  (无)".to_string());
+        }
+        for d in &remembered {
+            let status = if connected.contains(&d.address) { "已连接" } else { "未连接" };
+            let name = d.name.as_deref().unwrap_or("-");
+            lines.push(format!("  {}\t{}\t{}\t上次连接 {}", d.address, name, status, d.last_connected));
+        }
+        lines.push("mDNS 发现的服务:".to_string());
+        if services.is_empty() {
+            lines.push("  (无)".to_string());
+        }
+        for s in &services {
+            let kind = if s.is_pairing() { "配对" } else if s.is_connect() { "连接" } else { "其他" };
+            lines.push(format!("  {}\t{}\t{}\t{}", s.address, kind, s.name, s.service_type));
+        }
+        self.emit(lines.join("\n"))?;
+        Ok(EXIT_OK)
+    }
+    fn emit_status(&self, address: &str, message: &str) -> anyhow::Result<()> {
+        if self.cli.json {
+            self.emit(json!({ "address": address, "message": message }).to_string())
+        } else {
+            self.emit(message.to_string())
+        }
+    }
+    async fn resolve_serial(&self) -> anyhow::Result<String> {
+        if let Some(serial) = &self.cli.serial {
+            return Ok(serial.clone());
+        }
+        let devices = self.device_mgr.list_devices().await?;
+        let online: Vec<_> = devices.iter().filter(|d| d.state.is_online()).collect();
+        match online.as_slice() {
+            [only] => Ok(only.serial.clone()),
+            [] => match devices.iter().find_map(|d| d.state.guidance().map(|g| (d, g))) {
+                Some((d, guidance)) => anyhow::bail!("设备 {} 状态为 {}: {}", d.serial, d.state, guidance),
+                None => anyhow::bail!("未发现已授权的设备"),
+            },
+            _ => anyhow::bail!("连接了多台设备，请使用 --serial 指定"),
+        }
+    }
+    // backup 的 --output 是备份输出目录，screencap/cat 的是数据文件，结果仍写到标准输出
+    fn emit(&self, text: String) -> anyhow::Result<()> {
+        let data_output = matches!(self.cli.command, Command::Backup { .. } | Command::Screencap | Command::Cat { .. });
+        let output = self.cli.output.as_ref().filter(|_| !data_output);
+        match output {
+            Some(path) => std::fs::write(path, text)?,
+            None => println!("{}", text.trim_end()),
+        }
+        Ok(())
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::adb::fake::fixture;
+    use crate::adb::transport::AdbTransport;
+    use async_trait::async_trait;
+    use std::sync::Arc;
+    fn argv(args: &[&str]) -> Vec<String> {
+        args.iter().map(|s| s.to_string()).collect()
+    }
+    fn parse(args: &[&str]) -> Cli {
+        Cli::parse(&argv(args)).unwrap()
+    }
+    fn runner(client: SharedTransport, args: &[&str]) -> CliRunner {
+        CliRunner {
+            device_mgr: DeviceManager::new(client.clone()),
+            client,
+            config: Config::default(),
+            cli: parse(args),
+        }
+    }
+    // 模拟 Ctrl+C 之后 adb 调用全部被取消
+    struct Interrupted;
+    #[async_trait]
+    impl AdbTransport for Interrupted {
+        async fn try_execute(&self, args: &[&str]) -> crate::core::Result<(bool, String, String)> {
+            Err(AdbError::Cancelled(args.join(" ")))
+        }
+        async fn try_shell(&self, _serial: &str, cmd: &[&str]) -> crate::core::Result<(bool, String, String)> {
+            Err(AdbError::Cancelled(cmd.join(" ")))
+        }
+        async fn exec_out(&self, _serial: &str, cmd: &[&str]) -> crate::core::Result<Vec<u8>> {
+            Err(AdbError::Cancelled(cmd.join(" ")))
+        }
+    }
+    #[test]
+    fn subcommands_and_their_arguments_are_parsed() {
+        assert!(matches!(parse(&[]).command, Command::Help));
+        assert!(matches!(parse(&["--help"]).command, Command::Help));
+        assert!(matches!(parse(&["root-check"]).command, Command::RootCheck));
+        assert!(matches!(parse(&["cat", "/proc/version"]).command, Command::Cat { path } if path == "/proc/version"));
+        assert!(matches!(parse(&["tcpip"]).command, Command::Tcpip { port } if port == DEFAULT_TCPIP_PORT));
+        assert!(matches!(parse(&["tcpip", "--port", "5556"]).command, Command::Tcpip { port: 5556 }));
+        assert!(matches!(parse(&["stress", "--duration", "10"]).command, Command::Stress { duration: Some(10) }));
+        assert!(matches!(parse(&["connect"]).command, Command::Connect { address: None }));
+        match parse(&["backup", "--items", "app-list, settings"]).command {
+            Command::Backup { items } => {
+                assert_eq!(items.iter().map(|i| i.key()).collect::<Vec<_>>(), ["app-list", "settings"]);
+            }
+            other => panic!("{:?}", other),
+        }
+        match parse(&["snapshot", "diff", "before", "--prefix", "ro.boot.,persist."]).command {
+            Command::Snapshot { action: SnapshotAction::Diff { from, to }, prefixes } => {
+                assert_eq!((from.as_str(), to.as_str()), ("before", snapshot::CURRENT));
+                assert_eq!(prefixes, ["ro.boot.", "persist."]);
+            }
+            other => panic!("{:?}", other),
+        }
+        assert!(matches!(
+            parse(&["batch", "audit", "--concurrency", "4"]).command,
+            Command::Batch { operation: BatchOperation::Audit, concurrency: Some(4) }
+        ));
+        assert!(matches!(parse(&["inventory", "export", "-o", "devices.json"]).command, Command::Inventory(InventoryAction::Export { json: true })));
+        assert!(matches!(parse(&["inventory", "pixel"]).command, Command::Inventory(InventoryAction::List(Some(_)))));
+    }
+    #[test]
+    fn global_options_are_accepted_anywhere() {
+        let cli = parse(&["-s", "AAA", "audit", "--json", "-o", "report.json"]);
+        assert!(matches!(cli.command, Command::Audit));
+        assert_eq!(cli.serial.as_deref(), Some("AAA"));
+        assert!(cli.json);
+        assert_eq!(cli.output, Some(PathBuf::from("report.json")));
+        let cli = parse(&["devices", "--serial", "BBB", "--output", "out.txt"]);
+        assert_eq!(cli.serial.as_deref(), Some("BBB"));
+        assert!(!cli.json);
+        assert_eq!(cli.output, Some(PathBuf::from("out.txt")));
+    }
+    #[test]
+    fn usage_errors_exit_with_64() {
+        for args in [
+            &["--bogus"][..],
+            &["frobnicate"],
+            &["root-check", "-s"],
+            &["cat"],
+            &["batch", "reboot"],
+            &["batch", "audit", "--concurrency", "0"],
+            &["backup", "--items", "photos"],
+            &["inventory", "export", "--format", "xml"],
+        ] {
+            assert_eq!(Cli::from_args(&argv(args)).err(), Some(EXIT_USAGE), "{:?}", args);
+        }
+        assert_eq!(Cli::parse(&argv(&["--bogus"])).err().unwrap(), "未知选项: --bogus");
+    }
+    #[tokio::test]
+    async fn findings_exit_with_2_and_clean_devices_with_0() {
+        assert_eq!(runner(fixture("pixel7_kernelsu.json"), &["-s", "AAA", "root-check"]).run().await, EXIT_FINDINGS);
+        assert_eq!(runner(fixture("emulator_x86_64.json"), &["-s", "emulator-5554", "emulator-check", "--json"]).run().await, EXIT_FINDINGS);
+        // 只连接一台就绪设备时可以省略 -s
+        assert_eq!(runner(fixture("tablet_wifi.json"), &["root-check"]).run().await, EXIT_OK);
+    }
+    #[tokio::test]
+    async fn errors_exit_with_1_and_cancellation_with_130() {
+        assert_eq!(runner(fixture("tablet_wifi.json"), &["-s", "TAB", "cat", "/data/nope"]).run().await, EXIT_ERROR);
+        // 一台就绪、一台未授权时选中就绪的那台
+        assert_eq!(runner(fixture("pixel7_kernelsu.json"), &["root-check"]).run().await, EXIT_FINDINGS);
+        assert_eq!(runner(Arc::new(Interrupted), &["-s", "AAA", "root-check"]).run().await, EXIT_INTERRUPTED);
+        assert_eq!(runner(Arc::new(Interrupted), &["devices", "--json"]).run().await, EXIT_INTERRUPTED);
+    }
+}
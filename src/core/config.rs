@@ -1,28 +1,344 @@
-﻿use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+﻿use super::error::AdbError;
+use crate::adb::forward::RuleSet;
+use crate::checks::rules::DetectionRules;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+pub const SUPPORTED_LANGUAGES: &[&str] = &["zh", "en"];
+pub const DEFAULT_SUSPICIOUS_PACKAGES: &[&str] = &[
+    "com.topjohnwu.magisk",
+    "org.lsposed.manager",
+    "eu.chainfire.supersu",
+    "stericson.busybox",
+    "com.koushikdutta.superuser",
+    "com.noshufou.android.su",
+];
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
-    pub menu_labels: HashMap<String, String>,
+    pub adb_path: Option<PathBuf>,
+    pub command_timeout_secs: u64,
+    pub retry_attempts: u32,
+    pub retry_backoff_ms: u64,
+    pub backup_dir: Option<PathBuf>,
+    pub verify_transfers: bool,
+    pub suspicious_packages: Vec<String>,
+    pub language: String,
+    pub stress_duration_secs: u64,
+    pub batch_concurrency: usize,
+    pub menu_labels: BTreeMap<String, String>,
+    pub rule_sets: BTreeMap<String, RuleSet>,
+    pub root_rule_files: Vec<PathBuf>,
+    pub hook_rule_files: Vec<PathBuf>,
+    pub emulator_rule_files: Vec<PathBuf>,
+    #[serde(skip)]
+    pub sources: Vec<PathBuf>,
+}
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigLayer {
+    adb_path: Option<PathBuf>,
+    command_timeout_secs: Option<u64>,
+    retry_attempts: Option<u32>,
+    retry_backoff_ms: Option<u64>,
+    backup_dir: Option<PathBuf>,
+    verify_transfers: Option<bool>,
+    suspicious_packages: Option<Vec<String>>,
+    language: Option<String>,
+    stress_duration_secs: Option<u64>,
+    batch_concurrency: Option<u64>,
+    menu_labels: Option<BTreeMap<String, String>>,
+    rule_sets: Option<BTreeMap<String, RuleSet>>,
+    root_rule_files: Option<Vec<PathBuf>>,
+    hook_rule_files: Option<Vec<PathBuf>>,
+    emulator_rule_files: Option<Vec<PathBuf>>,
 }
 impl Default for Config {
     fn default() -> Self {
-        let mut labels = HashMap::new();
-        labels.insert("0".to_string(), "检测是否存在 Root 环境".to_string());
-        labels.insert("1".to_string(), "查看引导/BL 锁状态".to_string());
-        labels.insert("2".to_string(), "手机备份及恢复".to_string());
-        labels.insert("3".to_string(), "ADB 稳定性/压力测试".to_string());
-        labels.insert("4".to_string(), "系统与硬件安全检查".to_string());
-        labels.insert("5".to_string(), "敬请期待...".to_string());
-        labels.insert("6".to_string(), "敬请期待...".to_string());
-        labels.insert("7".to_string(), "敬请期待...".to_string());
-        labels.insert("8".to_string(), "敬请期待...".to_string());
-        labels.insert("9".to_string(), "敬请期待...".to_string());
-        Self { menu_labels: labels }
+        Self {
+            adb_path: None,
+            command_timeout_secs: 30,
+            retry_attempts: 3,
+            retry_backoff_ms: 200,
+            backup_dir: None,
+            verify_transfers: false,
+            suspicious_packages: DEFAULT_SUSPICIOUS_PACKAGES.iter().map(|s| s.to_string()).collect(),
+            language: "zh".to_string(),
+            stress_duration_secs: 60,
+            batch_concurrency: 4,
+            menu_labels: Self::default_labels("zh"),
+            rule_sets: BTreeMap::new(),
+            root_rule_files: Vec::new(),
+            hook_rule_files: Vec::new(),
+            emulator_rule_files: Vec::new(),
+            sources: Vec::new(),
+        }
     }
 }
 impl Config {
     pub fn load() -> anyhow::Result<Self> {
-        Ok(Self::default())
+        let mut config = Self::default();
+        for path in Self::candidate_files() {
+            if !path.is_file() {
+                continue;
+            }
+            let layer = Self::read_layer(&path)?;
+            config.apply(layer, &path.display().to_string(), path.parent())?;
+            config.sources.push(path);
+        }
+        let env_layer = Self::env_layer()?;
+        config.apply(env_layer, "环境变量", None)?;
+        Ok(config)
+    }
+    pub fn user_dir() -> Option<PathBuf> {
+        if cfg!(windows) {
+            std::env::var("APPDATA").ok().map(|dir| Path::new(&dir).join("AndroidChecker"))
+        } else if let Ok(dir) = std::env::var("XDG_CONFIG_HOME") {
+            Some(Path::new(&dir).join("androidchecker"))
+        } else {
+            std::env::var("HOME")
+                .ok()
+                .map(|home| Path::new(&home).join(".config").join("androidchecker"))
+        }
+    }
+    pub fn candidate_files() -> Vec<PathBuf> {
+        let mut files = Vec::new();
+        if cfg!(windows) {
+            if let Ok(dir) = std::env::var("PROGRAMDATA") {
+                files.push(Path::new(&dir).join("AndroidChecker").join("config.toml"));
+            }
+        } else {
+            files.push(PathBuf::from("/etc/androidchecker/config.toml"));
+        }
+        if let Some(dir) = Self::user_dir() {
+            files.push(dir.join("config.toml"));
+        }
+        files.push(PathBuf::from("androidchecker.toml"));
+        if let Ok(path) = std::env::var("ANDROIDCHECKER_CONFIG") {
+            files.push(PathBuf::from(path));
+        }
+        files
+    }
+    fn read_layer(path: &Path) -> anyhow::Result<ConfigLayer> {
+        let content = std::fs::read_to_string(path)?;
+        toml::from_str(&content)
+            .map_err(|e| AdbError::Config(format!("{}: {}", path.display(), e)).into())
+    }
+    fn env_layer() -> anyhow::Result<ConfigLayer> {
+        let var = |name: &str| std::env::var(name).ok().filter(|v| !v.trim().is_empty());
+        // 逗号分隔的列表
+        let list = |name: &str| -> Option<Vec<String>> {
+            var(name).map(|v| v.split(',').map(str::trim).filter(|s| !s.is_empty()).map(String::from).collect())
+        };
+        let paths = |name: &str| list(name).map(|v| v.into_iter().map(PathBuf::from).collect());
+        let number = |name: &str| -> anyhow::Result<Option<u64>> {
+            match var(name) {
+                Some(v) => v.trim().parse::<u64>().map(Some).map_err(|_| {
+                    AdbError::Config(format!("环境变量 {}: 需要整数，实际为 \"{}\"", name, v)).into()
+                }),
+                None => Ok(None),
+            }
+        };
+        let flag = |name: &str| -> anyhow::Result<Option<bool>> {
+            match var(name).map(|v| v.trim().to_lowercase()) {
+                Some(v) if ["1", "true", "yes", "on"].contains(&v.as_str()) => Ok(Some(true)),
+                Some(v) if ["0", "false", "no", "off"].contains(&v.as_str()) => Ok(Some(false)),
+                Some(v) => Err(AdbError::Config(format!("环境变量 {}: 需要 true/false，实际为 \"{}\"", name, v)).into()),
+                None => Ok(None),
+            }
+        };
+        Ok(ConfigLayer {
+            adb_path: var("ANDROIDCHECKER_ADB_PATH").map(PathBuf::from),
+            command_timeout_secs: number("ANDROIDCHECKER_TIMEOUT")?,
+            retry_attempts: number("ANDROIDCHECKER_RETRY_ATTEMPTS")?.map(|n| n.min(u32::MAX as u64) as u32),
+            retry_backoff_ms: number("ANDROIDCHECKER_RETRY_BACKOFF_MS")?,
+            backup_dir: var("ANDROIDCHECKER_BACKUP_DIR").map(PathBuf::from),
+            verify_transfers: flag("ANDROIDCHECKER_VERIFY_TRANSFERS")?,
+            suspicious_packages: list("ANDROIDCHECKER_SUSPICIOUS_PACKAGES"),
+            language: var("ANDROIDCHECKER_LANG"),
+            stress_duration_secs: number("ANDROIDCHECKER_STRESS_DURATION")?,
+            batch_concurrency: number("ANDROIDCHECKER_BATCH_CONCURRENCY")?,
+            menu_labels: None,
+            rule_sets: None,
+            root_rule_files: paths("ANDROIDCHECKER_ROOT_RULES"),
+            hook_rule_files: paths("ANDROIDCHECKER_HOOK_RULES"),
+            emulator_rule_files: paths("ANDROIDCHECKER_EMULATOR_RULES"),
+        })
+    }
+    // base 为配置文件所在目录，其中的相对路径据此解析；环境变量层为 None，相对于当前目录
+    fn apply(&mut self, layer: ConfigLayer, source: &str, base: Option<&Path>) -> anyhow::Result<()> {
+        let invalid = |key: &str, reason: &str| -> anyhow::Error {
+            AdbError::Config(format!("{}: {}: {}", source, key, reason)).into()
+        };
+        if let Some(path) = layer.adb_path {
+            if path.as_os_str().is_empty() {
+                return Err(invalid("adb_path", "路径不能为空"));
+            }
+            self.adb_path = Some(path);
+        }
+        if let Some(secs) = layer.command_timeout_secs {
+            if secs == 0 {
+                return Err(invalid("command_timeout_secs", "必须大于 0"));
+            }
+            self.command_timeout_secs = secs;
+        }
+        if let Some(attempts) = layer.retry_attempts {
+            if attempts == 0 {
+                return Err(invalid("retry_attempts", "至少为 1（1 表示不重试）"));
+            }
+            self.retry_attempts = attempts;
+        }
+        if let Some(ms) = layer.retry_backoff_ms {
+            self.retry_backoff_ms = ms;
+        }
+        if let Some(dir) = layer.backup_dir {
+            if dir.as_os_str().is_empty() {
+                return Err(invalid("backup_dir", "路径不能为空"));
+            }
+            self.backup_dir = Some(dir);
+        }
+        if let Some(verify) = layer.verify_transfers {
+            self.verify_transfers = verify;
+        }
+        if let Some(packages) = layer.suspicious_packages {
+            if let Some(idx) = packages.iter().position(|p| p.trim().is_empty()) {
+                return Err(invalid(&format!("suspicious_packages[{}]", idx), "包名不能为空"));
+            }
+            self.suspicious_packages = packages;
+        }
+        if let Some(lang) = layer.language {
+            if !SUPPORTED_LANGUAGES.contains(&lang.as_str()) {
+                return Err(invalid(
+                    "language",
+                    &format!("不支持 \"{}\"，可选: {}", lang, SUPPORTED_LANGUAGES.join(", ")),
+                ));
+            }
+            // 只替换仍是原语言默认文字的菜单项，之前的层自定义过的保持不变
+            if lang != self.language {
+                let previous = Self::default_labels(&self.language);
+                for (k, v) in Self::default_labels(&lang) {
+                    if self.menu_labels.get(&k) == previous.get(&k) {
+                        self.menu_labels.insert(k, v);
+                    }
+                }
+            }
+            self.language = lang;
+        }
+        if let Some(secs) = layer.stress_duration_secs {
+            if secs == 0 {
+                return Err(invalid("stress_duration_secs", "必须大于 0"));
+            }
+            self.stress_duration_secs = secs;
+        }
+        if let Some(limit) = layer.batch_concurrency {
+            if limit == 0 {
+                return Err(invalid("batch_concurrency", "必须大于 0"));
+            }
+            self.batch_concurrency = limit.min(usize::MAX as u64) as usize;
+        }
+        if let Some(labels) = layer.menu_labels {
+            for (k, v) in labels {
+                if !Self::default_labels("zh").contains_key(&k) {
+                    return Err(invalid(&format!("menu_labels.{}", k), "未知菜单编号"));
+                }
+                self.menu_labels.insert(k, v);
+            }
+        }
+        if let Some(sets) = layer.rule_sets {
+            for (name, set) in sets {
+                if name.trim().is_empty() {
+                    return Err(invalid("rule_sets", "规则集名称不能为空"));
+                }
+                if let Err(e) = set.validate() {
+                    return Err(invalid(&format!("rule_sets.{}", name), &e.to_string()));
+                }
+                self.rule_sets.insert(name, set);
+            }
+        }
+        let rule_files = [
+            ("root_rule_files", layer.root_rule_files, &mut self.root_rule_files),
+            ("hook_rule_files", layer.hook_rule_files, &mut self.hook_rule_files),
+            ("emulator_rule_files", layer.emulator_rule_files, &mut self.emulator_rule_files),
+        ];
+        for (kind, files, target) in rule_files {
+            let Some(files) = files else {
+                continue;
+            };
+            let files: Vec<PathBuf> = files
+                .into_iter()
+                .map(|path| match base {
+                    Some(dir) if path.is_relative() => dir.join(path),
+                    _ => path,
+                })
+                .collect();
+            if let Err((key, reason)) = Self::validate_rule_files(kind, &files) {
+                return Err(invalid(&key, &reason));
+            }
+            *target = files;
+        }
+        Ok(())
+    }
+    fn validate_rule_files(kind: &str, paths: &[PathBuf]) -> std::result::Result<(), (String, String)> {
+        for (idx, path) in paths.iter().enumerate() {
+            if let Err(e) = DetectionRules::load_path(path) {
+                return Err((format!("{}[{}]", kind, idx), e.to_string()));
+            }
+        }
+        Ok(())
+    }
+    pub fn save_rule_set(name: &str, set: &RuleSet) -> anyhow::Result<PathBuf> {
+        let path = Self::user_dir()
+            .ok_or_else(|| AdbError::Config("无法确定用户配置目录".to_string()))?
+            .join("config.toml");
+        let mut doc = if path.is_file() {
+            std::fs::read_to_string(&path)?.parse::<toml::Value>()?
+        } else {
+            toml::Value::Table(Default::default())
+        };
+        let root = doc
+            .as_table_mut()
+            .ok_or_else(|| AdbError::Config(format!("{}: 不是有效的配置文件", path.display())))?;
+        let sets = root
+            .entry("rule_sets")
+            .or_insert_with(|| toml::Value::Table(Default::default()));
+        let sets = sets
+            .as_table_mut()
+            .ok_or_else(|| AdbError::Config(format!("{}: rule_sets: 需要表", path.display())))?;
+        sets.insert(name.to_string(), toml::Value::try_from(set)?);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&path, toml::to_string_pretty(&doc)?)?;
+        Ok(path)
+    }
+    pub fn default_labels(language: &str) -> BTreeMap<String, String> {
+        let items: [(&str, &str); 10] = if language == "en" {
+            [
+                ("0", "Root environment check"),
+                ("1", "Bootloader / verified boot status"),
+                ("2", "Backup & restore"),
+                ("3", "ADB stability / stress test"),
+                ("4", "System & hardware security audit"),
+                ("5", "Port forward & reverse"),
+                ("6", "Hooking / instrumentation framework check"),
+                ("7", "Emulator / virtual device check"),
+                ("8", "Coming soon..."),
+                ("9", "Coming soon..."),
+            ]
+        } else {
+            [
+                ("0", "检测是否存在 Root 环境"),
+                ("1", "查看引导/BL 锁状态"),
+                ("2", "手机备份及恢复"),
+                ("3", "ADB 稳定性/压力测试"),
+                ("4", "系统与硬件安全检查"),
+                ("5", "端口转发管理"),
+                ("6", "检测 Hook/注入框架"),
+                ("7", "检测模拟器/虚拟设备"),
+                ("8", "敬请期待..."),
+                ("9", "敬请期待..."),
+            ]
+        };
+        items.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect()
     }
     pub fn get_label(&self, key: &str, fallback: &str) -> String {
         self.menu_labels
@@ -30,4 +346,71 @@ impl Config {
             .cloned()
             .unwrap_or_else(|| fallback.to_string())
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn rule_files_resolve_against_the_config_directory() {
+        let dir = std::env::temp_dir().join(format!("androidchecker-config-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("rules")).unwrap();
+        std::fs::write(dir.join("rules").join("extra.toml"), "rules = []\n").unwrap();
+        let path = dir.join("config.toml");
+        std::fs::write(&path, "root_rule_files = [\"rules/extra.toml\"]\nhook_rule_files = [\"rules/extra.toml\"]\n").unwrap();
+        let mut config = Config::default();
+        let layer = Config::read_layer(&path).unwrap();
+        config.apply(layer, "config.toml", path.parent()).unwrap();
+        assert_eq!(config.root_rule_files, vec![dir.join("rules").join("extra.toml")]);
+        assert_eq!(config.hook_rule_files, config.root_rule_files);
+        let layer: ConfigLayer = toml::from_str("emulator_rule_files = [\"missing.toml\"]\n").unwrap();
+        let err = config.apply(layer, "config.toml", path.parent()).unwrap_err().to_string();
+        std::fs::remove_dir_all(&dir).ok();
+        assert!(err.contains("emulator_rule_files[0]"), "{}", err);
+    }
+    fn layer(content: &str) -> ConfigLayer {
+        toml::from_str(content).unwrap()
+    }
+    #[test]
+    fn later_layers_override_earlier_ones_key_by_key() {
+        let mut config = Config::default();
+        config
+            .apply(layer("command_timeout_secs = 10\nbatch_concurrency = 2\nverify_transfers = true\n"), "/etc", None)
+            .unwrap();
+        config.apply(layer("command_timeout_secs = 60\n"), "user", None).unwrap();
+        config.apply(layer("batch_concurrency = 8\n"), "环境变量", None).unwrap();
+        assert_eq!(config.command_timeout_secs, 60);
+        assert_eq!(config.batch_concurrency, 8);
+        assert!(config.verify_transfers);
+        assert_eq!(config.retry_attempts, Config::default().retry_attempts);
+    }
+    #[test]
+    fn changing_language_keeps_customised_menu_labels() {
+        let mut config = Config::default();
+        config.apply(layer("[menu_labels]\n\"0\" = \"Root 检查\"\n"), "/etc", None).unwrap();
+        config.apply(layer("language = \"en\"\n"), "user", None).unwrap();
+        assert_eq!(config.language, "en");
+        assert_eq!(config.menu_labels["0"], "Root 检查");
+        assert_eq!(config.menu_labels["1"], Config::default_labels("en")["1"]);
+        // 同一层里的 menu_labels 在语言之后生效
+        config.apply(layer("language = \"zh\"\n[menu_labels]\n\"1\" = \"BL\"\n"), "环境变量", None).unwrap();
+        assert_eq!(config.menu_labels["0"], "Root 检查");
+        assert_eq!(config.menu_labels["1"], "BL");
+        assert_eq!(config.menu_labels["2"], Config::default_labels("zh")["2"]);
+    }
+    #[test]
+    fn invalid_values_name_the_source_and_key() {
+        let mut config = Config::default();
+        let err = config.apply(layer("batch_concurrency = 0\n"), "config.toml", None).unwrap_err().to_string();
+        assert!(err.contains("config.toml: batch_concurrency"), "{}", err);
+        let err = config.apply(layer("language = \"fr\"\n"), "环境变量", None).unwrap_err().to_string();
+        assert!(err.contains("环境变量: language"), "{}", err);
+        let err = config.apply(layer("[menu_labels]\n\"42\" = \"x\"\n"), "config.toml", None).unwrap_err().to_string();
+        assert!(err.contains("menu_labels.42"), "{}", err);
+        let path = std::env::temp_dir().join(format!("androidchecker-unknown-key-{}.toml", std::process::id()));
+        std::fs::write(&path, "retry_attempts = 2\nbatch_concurency = 4\n").unwrap();
+        let err = Config::read_layer(&path).err().unwrap().to_string();
+        std::fs::remove_file(&path).ok();
+        assert!(err.contains("batch_concurency"), "{}", err);
+        assert!(err.contains(&path.display().to_string()), "{}", err);
+    }
+}
@@ -5,10 +5,22 @@ pub enum AdbError {
     AdbNotFound,
     #[error("ADB command failed: {0}")]
     CommandFailed(String),
-    #[error("ADB timeout after {0}s")]
-    Timeout(u64),
+    #[error("ADB timeout after {secs}s: {command}")]
+    Timeout { command: String, secs: u64 },
+    #[error("ADB command cancelled: {0}")]
+    Cancelled(String),
+    #[error("Device unauthorized: {0}")]
+    Unauthorized(String),
+    #[error("Device offline: {0}")]
+    Offline(String),
     #[error("Device not found: {0}")]
     DeviceNotFound(String),
+    #[error("Not found on device: {0}")]
+    NotFoundOnDevice(String),
+    #[error("ADB server died: {0}")]
+    ServerDied(String),
+    #[error("ADB protocol error: {0}")]
+    Protocol(String),
     #[error("No devices connected")]
     NoDevices,
     #[error("IO error: {0}")]
@@ -21,5 +33,61 @@ pub enum AdbError {
     Json(#[from] serde_json::Error),
     #[error("Permission denied: {0}")]
     PermissionDenied(String),
+    #[error("Config error: {0}")]
+    Config(String),
+}
+impl AdbError {
+    pub fn classify(stderr: &str) -> Self {
+        let msg = stderr.trim().to_string();
+        let lower = msg.to_lowercase();
+        let has = |patterns: &[&str]| patterns.iter().any(|p| lower.contains(p));
+        if has(&["unauthorized"]) {
+            Self::Unauthorized(msg)
+        } else if has(&["device offline", "still connecting", "still authorizing"]) {
+            Self::Offline(msg)
+        } else if has(&["no devices/emulators found", "no devices found"]) {
+            Self::NoDevices
+        } else if lower.contains("device") && lower.contains("not found") && !lower.contains("no such file") {
+            Self::DeviceNotFound(msg)
+        } else if has(&[
+            "cannot connect to daemon",
+            "daemon not running",
+            "failed to start daemon",
+            "server killed",
+            "doesn't match this client",
+        ]) {
+            Self::ServerDied(msg)
+        } else if has(&["protocol fault", "error: closed", "connection reset", "broken pipe"]) {
+            Self::Protocol(msg)
+        } else if has(&["permission denied", "operation not permitted"]) {
+            Self::PermissionDenied(msg)
+        } else if has(&["not found", "no such file or directory", "inaccessible or not found"]) {
+            Self::NotFoundOnDevice(msg)
+        } else {
+            Self::CommandFailed(msg)
+        }
+    }
+    pub fn is_device_unavailable(&self) -> bool {
+        matches!(
+            self,
+            Self::Unauthorized(_)
+                | Self::Offline(_)
+                | Self::DeviceNotFound(_)
+                | Self::NoDevices
+                | Self::ServerDied(_)
+                | Self::Protocol(_)
+        )
+    }
+    pub fn hint(&self) -> Option<&'static str> {
+        match self {
+            Self::Unauthorized(_) => Some("请在手机上确认 \"允许 USB 调试\" 授权弹窗"),
+            Self::Offline(_) => Some("设备离线，请重新插拔数据线或重启 adb 服务"),
+            Self::DeviceNotFound(_) | Self::NoDevices => Some("请确认设备已连接并开启 USB 调试"),
+            Self::ServerDied(_) => Some("adb 服务异常，请执行 adb kill-server 后重试"),
+            Self::PermissionDenied(_) => Some("该操作需要更高权限 (Root)"),
+            Self::AdbNotFound => Some("运行 `androidchecker doctor` 查看已尝试的 adb 路径"),
+            _ => None,
+        }
+    }
 }
 pub type Result<T> = std::result::Result<T, AdbError>;
\ No newline at end of file
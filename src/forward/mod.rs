@@ -0,0 +1,202 @@
+﻿use crate::adb::forward::{self, Endpoint, ForwardDirection, PortRule, RuleSet};
+use crate::adb::{DeviceEvent, DeviceWatcher, SharedTransport};
+use crate::core::config::Config;
+use crate::core::Result;
+use crate::ui::ConsoleUi;
+use std::collections::BTreeMap;
+use tokio::sync::broadcast;
+pub struct ForwardRunner {
+    client: SharedTransport,
+    rule_sets: BTreeMap<String, RuleSet>,
+}
+impl ForwardRunner {
+    pub fn new(client: SharedTransport) -> Self {
+        Self {
+            client,
+            rule_sets: BTreeMap::new(),
+        }
+    }
+    pub fn with_rule_sets(mut self, rule_sets: BTreeMap<String, RuleSet>) -> Self {
+        self.rule_sets = rule_sets;
+        self
+    }
+    pub async fn list(&self, serial: &str) -> Result<Vec<PortRule>> {
+        let mut rules = self.client.list_forwards(serial, ForwardDirection::Forward).await?;
+        rules.extend(self.client.list_forwards(serial, ForwardDirection::Reverse).await?);
+        Ok(rules)
+    }
+    pub async fn show_menu(&mut self, serial: &str) -> Result<()> {
+        loop {
+            println!("\n=== 端口转发管理 ===");
+            println!("1) 查看当前规则");
+            println!("2) 新建 forward 规则 (电脑 → 手机)");
+            println!("3) 新建 reverse 规则 (手机 → 电脑)");
+            println!("4) 删除规则");
+            println!("5) 应用规则集");
+            println!("6) 将当前规则保存为规则集");
+            println!("0) 返回");
+            print!("\n请选择: ");
+            std::io::Write::flush(&mut std::io::stdout())?;
+            let inp = ConsoleUi::read_line();
+            let result = match inp.as_str() {
+                "1" => self.do_list(serial).await.map(|_| ()),
+                "2" => self.do_add(serial, ForwardDirection::Forward).await,
+                "3" => self.do_add(serial, ForwardDirection::Reverse).await,
+                "4" => self.do_remove(serial).await,
+                "5" => self.do_apply(serial).await,
+                "6" => self.do_save(serial).await,
+                "0" => break,
+                _ => {
+                    ConsoleUi::warn("无效选择");
+                    continue;
+                }
+            };
+            if let Err(e) = result {
+                ConsoleUi::error(&format!("操作失败: {}", e));
+            }
+        }
+        Ok(())
+    }
+    async fn do_list(&self, serial: &str) -> Result<Vec<PortRule>> {
+        let rules = self.list(serial).await?;
+        if rules.is_empty() {
+            ConsoleUi::info("当前没有转发规则");
+        }
+        for (idx, rule) in rules.iter().enumerate() {
+            println!("  {}) [{}] {}", idx + 1, rule.direction.as_str(), rule.describe());
+        }
+        Ok(rules)
+    }
+    async fn do_add(&self, serial: &str, direction: ForwardDirection) -> Result<()> {
+        let (listen_hint, target_hint) = match direction {
+            ForwardDirection::Forward => ("电脑端监听端点 (如 tcp:9222)", "手机端目标 (如 localabstract:chrome_devtools_remote、jdwp:<pid>)"),
+            ForwardDirection::Reverse => ("手机端监听端点 (如 tcp:8081)", "电脑端目标 (如 tcp:8081)"),
+        };
+        print!("{}: ", listen_hint);
+        std::io::Write::flush(&mut std::io::stdout())?;
+        let listen = Endpoint::parse(&ConsoleUi::read_line())?;
+        print!("{}: ", target_hint);
+        std::io::Write::flush(&mut std::io::stdout())?;
+        let target = Endpoint::parse(&ConsoleUi::read_line())?;
+        let rule = PortRule::new(direction, listen, target)?;
+        self.client.add_forward(serial, &rule).await?;
+        ConsoleUi::success(&format!("已添加: {}", rule.describe()));
+        Ok(())
+    }
+    async fn do_remove(&self, serial: &str) -> Result<()> {
+        let rules = self.do_list(serial).await?;
+        if rules.is_empty() {
+            return Ok(());
+        }
+        print!("\n输入要删除的编号 (a 删除全部): ");
+        std::io::Write::flush(&mut std::io::stdout())?;
+        let inp = ConsoleUi::read_line();
+        if inp.eq_ignore_ascii_case("a") {
+            self.client.remove_all_forwards(serial, ForwardDirection::Forward).await?;
+            self.client.remove_all_forwards(serial, ForwardDirection::Reverse).await?;
+            ConsoleUi::success("已删除全部规则");
+            return Ok(());
+        }
+        match inp.trim().parse::<usize>().ok().and_then(|n| rules.get(n.wrapping_sub(1))) {
+            Some(rule) => {
+                self.client.remove_forward(serial, rule.direction, &rule.listen).await?;
+                ConsoleUi::success(&format!("已删除: {}", rule.describe()));
+            }
+            None => ConsoleUi::warn("无效编号"),
+        }
+        Ok(())
+    }
+    async fn do_apply(&self, serial: &str) -> Result<()> {
+        if self.rule_sets.is_empty() {
+            ConsoleUi::info("配置中没有规则集，可先用 6) 保存当前规则");
+            return Ok(());
+        }
+        let names: Vec<&String> = self.rule_sets.keys().collect();
+        for (idx, name) in names.iter().enumerate() {
+            println!("  {}) {} ({} 条规则)", idx + 1, name, self.rule_sets[*name].rules.len());
+        }
+        print!("\n选择规则集: ");
+        std::io::Write::flush(&mut std::io::stdout())?;
+        let inp = ConsoleUi::read_line();
+        let Some(name) = inp.trim().parse::<usize>().ok().and_then(|n| names.get(n.wrapping_sub(1))) else {
+            ConsoleUi::warn("无效编号");
+            return Ok(());
+        };
+        let rules = self.rule_sets[*name].parsed_rules()?;
+        report(name, &forward::apply_rules(&self.client, serial, &rules).await);
+        Ok(())
+    }
+    async fn do_save(&mut self, serial: &str) -> Result<()> {
+        let rules = self.list(serial).await?;
+        if rules.is_empty() {
+            ConsoleUi::info("当前没有转发规则可保存");
+            return Ok(());
+        }
+        print!("规则集名称: ");
+        std::io::Write::flush(&mut std::io::stdout())?;
+        let name = ConsoleUi::read_line();
+        if name.trim().is_empty() {
+            ConsoleUi::warn("名称不能为空");
+            return Ok(());
+        }
+        print!("设备重新连接时自动应用? (y/N): ");
+        std::io::Write::flush(&mut std::io::stdout())?;
+        let auto_apply = ConsoleUi::read_line().eq_ignore_ascii_case("y");
+        let mut rules = rules;
+        if auto_apply {
+            let before = rules.len();
+            rules.retain(|r| !r.is_jdwp());
+            if rules.len() < before {
+                ConsoleUi::warn(&format!("已跳过 {} 条 jdwp 规则：进程号在重连后失效，不能自动应用", before - rules.len()));
+            }
+            if rules.is_empty() {
+                ConsoleUi::warn("没有可自动应用的规则，未保存");
+                return Ok(());
+            }
+        }
+        let set = RuleSet {
+            devices: vec![serial.to_string()],
+            auto_apply,
+            rules: rules.iter().map(|r| r.to_string()).collect(),
+        };
+        set.validate()?;
+        let path = Config::save_rule_set(name.trim(), &set)
+            .map_err(|e| crate::core::AdbError::Config(e.to_string()))?;
+        ConsoleUi::success(&format!("规则集 {} 已保存到 {}", name.trim(), path.display()));
+        self.rule_sets.insert(name.trim().to_string(), set);
+        Ok(())
+    }
+}
+// 使用进程启动时合并好的分层配置（最后一层是 ANDROIDCHECKER_CONFIG 指定的文件），不在每次事件时重新读取
+pub fn spawn_reapply(client: SharedTransport, watcher: &DeviceWatcher, config: &Config) -> tokio::task::JoinHandle<()> {
+    let mut events = watcher.subscribe();
+    let rule_sets = config.rule_sets.clone();
+    tokio::spawn(async move {
+        loop {
+            let serial = match events.recv().await {
+                Ok(DeviceEvent::Connected { serial, state }) if state.is_online() => serial,
+                Ok(DeviceEvent::StateChanged { serial, to, .. }) if to.is_online() => serial,
+                Ok(_) | Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                Err(broadcast::error::RecvError::Closed) => return,
+            };
+            for (name, set) in rule_sets.iter().filter(|(_, s)| s.auto_apply && s.applies_to(&serial)) {
+                if let Ok(rules) = set.validate() {
+                    report(name, &forward::apply_rules(&client, &serial, &rules).await);
+                }
+            }
+        }
+    })
+}
+fn report(name: &str, results: &[(PortRule, Result<()>)]) {
+    let failed = results.iter().filter(|(_, r)| r.is_err()).count();
+    for (rule, result) in results {
+        if let Err(e) = result {
+            ConsoleUi::warn(&format!("规则 {} 应用失败: {}", rule, e));
+        }
+    }
+    if failed == 0 {
+        ConsoleUi::success(&format!("规则集 {} 已应用 ({} 条)", name, results.len()));
+    } else {
+        ConsoleUi::warn(&format!("规则集 {} 部分失败: {}/{}", name, failed, results.len()));
+    }
+}
@@ -1,6 +1,6 @@
 ﻿pub mod monitor_window;
 pub use monitor_window::MonitorApp;
-use crate::adb::AdbClient;
+use crate::adb::{AdbClient, SharedTransport};
 use anyhow::Result;
 pub fn launch_monitor_gui(serial: String) -> Result<()> {
     let native_options = eframe::NativeOptions {
@@ -11,7 +11,7 @@ pub fn launch_monitor_gui(serial: String) -> Result<()> {
             .with_min_inner_size([1000.0, 600.0]),
         ..Default::default()
     };
-    let client = AdbClient::new()?;
+    let client: SharedTransport = std::sync::Arc::new(AdbClient::new()?);
     eframe::run_native(
         "AndroidChecker Monitor",
         native_options,
@@ -1,5 +1,5 @@
 ﻿use egui::{CentralPanel, Color32, RichText, ScrollArea, SidePanel, TopBottomPanel, Ui};
-use crate::adb::AdbClient;
+use crate::adb::SharedTransport;
 use crate::monitor::{ProcessMonitor, AppManager, ResourceMonitor};
 use std::sync::{Arc, Mutex};
 use tokio::runtime::Runtime;
@@ -47,7 +47,7 @@ enum ProcessType {
     System,
 }
 impl MonitorApp {
-    pub fn new(client: AdbClient, serial: String) -> Self {
+    pub fn new(client: SharedTransport, serial: String) -> Self {
         let process_monitor = ProcessMonitor::new(client.clone());
         let app_manager = AppManager::new(client.clone());
         let resource_monitor = ResourceMonitor::new(client);
@@ -1,22 +1,49 @@
 ﻿mod adb;
 mod audit;
 mod backup;
+mod batch;
 mod checks;
+mod cli;
 mod core;
+mod forward;
+mod snapshot;
 mod stress;
 mod ui;
-use adb::{AdbClient, DeviceManager};
+use adb::inventory::Inventory;
+use adb::wireless::RememberedDevices;
+use adb::{
+    AdbClient, Device, DeviceManager, DeviceOperation, DeviceWatcher, FakeTransport, RecordingTransport,
+    ReplayTransport, SharedTransport,
+};
 use audit::AuditRunner;
 use backup::BackupRunner;
-use checks::{BootloaderChecker, RootChecker};
+use batch::{BatchOperation, BatchRunner};
+use checks::{BootloaderChecker, EmulatorChecker, HookChecker, RootChecker};
+use cli::{Cli, CliRunner};
 use core::config::Config;
+use core::AdbError;
+use forward::ForwardRunner;
 use stress::StressRunner;
 use ui::{ConsoleUi, Menu};
 use chrono::{Datelike, Local};
 use colored::Colorize;
+use std::sync::Arc;
+use tokio_util::sync::CancellationToken;
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        std::process::exit(run_cli(&args).await);
+    }
     ConsoleUi::write_banner();
+    let config = match Config::load() {
+        Ok(c) => c,
+        Err(e) => {
+            ConsoleUi::error(&format!("配置文件无效: {}", e));
+            ConsoleUi::pause_exit();
+            return Ok(());
+        }
+    };
     let now = Local::now();
     if now.month() == 10 && now.day() == 24 {
         println!();
@@ -29,118 +56,284 @@ async fn main() -> anyhow::Result<()> {
         println!();
         std::thread::sleep(std::time::Duration::from_millis(800));
     }
-    let config = Config::load()?;
-    let client = match AdbClient::new() {
-        Ok(c) => c,
-        Err(_) => {
-            ConsoleUi::error("未检测到 adb。请确保内置 adb 存在或已安装 Android Platform-Tools。");
+    let client = match init_transport(&config, CancellationToken::new()).await {
+        Some(c) => c,
+        None => {
             ConsoleUi::pause_exit();
             return Ok(());
         }
     };
-
-    if client.ensure_present().await.is_err() {
-        ConsoleUi::error("ADB 版本检测失败");
-        ConsoleUi::pause_exit();
-        return Ok(());
-    }
-
-    client.start_server().await?;
+    let watcher = DeviceWatcher::start(client.clone());
+    let _reapply = AbortOnDrop(forward::spawn_reapply(client.clone(), &watcher, &config));
+    let device_mgr = DeviceManager::new(client.clone())
+        .with_remembered_file(RememberedDevices::default_path())
+        .with_inventory_file(Inventory::default_path());
 
     loop {
-        let device_mgr = DeviceManager::new(client.clone());
         let devices = match device_mgr.list_devices().await {
             Ok(d) => d,
             Err(e) => {
                 ConsoleUi::error(&format!("ADB 调用失败: {}", e));
+                if let Some(hint) = e.hint() {
+                    ConsoleUi::info(hint);
+                }
                 ConsoleUi::pause_exit();
                 return Ok(());
             }
         };
 
         if devices.is_empty() {
-            ConsoleUi::warn("未发现已授权的设备。请连接设备并开启 USB 调试，按回车重试，或输入 q 退出。");
-            let input = ConsoleUi::read_line();
-            if input.eq_ignore_ascii_case("q") {
-                return Ok(());
+            ConsoleUi::warn("未发现已授权的设备。请连接设备并开启 USB 调试，正在等待设备接入 (Ctrl+C 退出)...");
+            if let Some(serial) = watcher.wait_for_online().await {
+                ConsoleUi::info(&format!("检测到设备: {}", serial));
             }
             continue;
         }
 
         println!("\n已连接设备：");
         ConsoleUi::render_device_table(&devices);
-        println!("\n输入数字选择设备 (q 退出，可选编号: 1..{}): ", devices.len());
+        let usable = devices
+            .iter()
+            .any(|d| d.state.is_online() || !d.state.limited_operations().is_empty());
+        if !usable {
+            ConsoleUi::warn("暂无可操作的设备，请按上方提示处理，正在等待设备就绪 (Ctrl+C 退出)...");
+            if let Some(serial) = watcher.wait_for_online().await {
+                ConsoleUi::info(&format!("检测到设备: {}", serial));
+            }
+            continue;
+        }
+        println!(
+            "\n输入数字选择设备 (q 退出，可选编号: 1..{}；多个编号如 1,3 或 1-3、a 选择全部可批量执行): ",
+            devices.len()
+        );
         let input = ConsoleUi::read_line();
 
         if input.eq_ignore_ascii_case("q") {
             return Ok(());
         }
 
-        let idx = match Menu::parse_index(&input) {
-            Some(n) if n >= 1 && n <= devices.len() => n - 1,
-            _ => {
+        let idx = match Menu::parse_selection(&input, devices.len()) {
+            Some(picked) if picked.len() == 1 => picked[0] - 1,
+            Some(picked) => {
+                let selected: Vec<Device> = picked
+                    .iter()
+                    .map(|n| devices[n - 1].clone())
+                    .filter(|d| d.state.is_online())
+                    .collect();
+                if let Err(e) = batch_menu(&selected, &device_mgr, &config).await {
+                    ConsoleUi::error(&format!("批量执行失败: {}", e));
+                }
+                continue;
+            }
+            None => {
                 ConsoleUi::warn("无效输入，请重试");
                 continue;
             }
         };
 
         let device = &devices[idx];
-        if let Err(e) = device_menu(&device.serial, &device.display_name(), &client, &config).await
-        {
+        let result = if device.state.is_online() {
+            device_menu(&device.serial, &device.display_name(), &device_mgr, &config, &watcher).await
+        } else if !device.state.limited_operations().is_empty() {
+            limited_menu(device, &device_mgr).await
+        } else {
+            ConsoleUi::warn(&format!(
+                "设备 {} 当前状态为 {}: {}",
+                device.serial,
+                device.state,
+                device.state.guidance().unwrap_or_default()
+            ));
+            continue;
+        };
+        if let Err(e) = result {
             ConsoleUi::error(&format!("发生异常: {}", e));
+            if let Some(hint) = e.downcast_ref::<AdbError>().and_then(AdbError::hint) {
+                ConsoleUi::info(hint);
+            }
             ConsoleUi::pause_exit();
             return Ok(());
         }
+        if watcher.is_lost(&device.serial) {
+            device_mgr.cache().invalidate(&device.serial);
+        }
+    }
+}
+
+async fn run_cli(args: &[String]) -> i32 {
+    let cli = match Cli::from_args(args) {
+        Ok(c) => c,
+        Err(code) => return code,
+    };
+    if matches!(cli.command, cli::Command::Help) {
+        print!("{}", cli::USAGE);
+        return cli::EXIT_OK;
+    }
+    ConsoleUi::set_quiet(cli.json);
+    let config = match Config::load() {
+        Ok(c) => c,
+        Err(e) => {
+            ConsoleUi::error(&format!("配置文件无效: {}", e));
+            return cli::EXIT_CONFIG;
+        }
+    };
+    match cli.command {
+        cli::Command::Config => return CliRunner::show_config(&config, cli.json),
+        cli::Command::Doctor => return CliRunner::doctor(&config, cli.json).await,
+        _ => {}
+    }
+    let cancel = CancellationToken::new();
+    let on_interrupt = cancel.clone();
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            on_interrupt.cancel();
+        }
+    });
+    match init_transport(&config, cancel).await {
+        Some(client) => CliRunner::new(client, config, cli).run().await,
+        None => cli::EXIT_ERROR,
+    }
+}
+
+async fn init_transport(config: &Config, cancel: CancellationToken) -> Option<SharedTransport> {
+    if let Ok(fixture) = std::env::var("ANDROIDCHECKER_FIXTURE") {
+        return match FakeTransport::from_file(std::path::Path::new(&fixture)) {
+            Ok(fake) => {
+                ConsoleUi::warn(&format!("使用模拟设备数据: {}", fixture));
+                Some(Arc::new(fake))
+            }
+            Err(e) => {
+                ConsoleUi::error(&format!("模拟设备数据加载失败: {}", e));
+                None
+            }
+        };
+    }
+    if let Ok(transcript) = std::env::var("ANDROIDCHECKER_REPLAY") {
+        return match ReplayTransport::from_file(std::path::Path::new(&transcript)) {
+            Ok(replay) => {
+                ConsoleUi::warn(&format!("回放设备会话: {}", transcript));
+                let honor_timing = std::env::var_os("ANDROIDCHECKER_REPLAY_TIMING").is_some();
+                Some(Arc::new(replay.with_timing(honor_timing)))
+            }
+            Err(e) => {
+                ConsoleUi::error(&format!("会话记录加载失败: {}", e));
+                None
+            }
+        };
+    }
+    let client = match AdbClient::from_config(config) {
+        Ok(c) => c.with_cancel(cancel),
+        Err(_) => {
+            ConsoleUi::error("未检测到 adb。请确保内置 adb 存在或已安装 Android Platform-Tools。");
+            ConsoleUi::info("运行 `androidchecker doctor` 查看已尝试的路径及原因。");
+            return None;
+        }
+    };
+    if client.ensure_present().await.is_err() {
+        ConsoleUi::error(&format!("ADB 版本检测失败: {}", client.adb_path().display()));
+        return None;
+    }
+    if let Err(e) = client.start_server().await {
+        ConsoleUi::error(&format!("ADB 服务启动失败: {}", e));
+        return None;
+    }
+    let client: SharedTransport = Arc::new(client.connect_server().await);
+    if let Ok(transcript) = std::env::var("ANDROIDCHECKER_RECORD") {
+        return match RecordingTransport::new(client, std::path::Path::new(&transcript)) {
+            Ok(recorder) => {
+                ConsoleUi::info(&format!("正在录制设备会话: {}", transcript));
+                Some(Arc::new(recorder))
+            }
+            Err(e) => {
+                ConsoleUi::error(&format!("会话记录创建失败: {}", e));
+                None
+            }
+        };
+    }
+    Some(client)
+}
+
+struct AbortOnDrop(tokio::task::JoinHandle<()>);
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
     }
 }
 
 async fn device_menu(
     serial: &str,
     display_name: &str,
-    client: &AdbClient,
+    device_mgr: &DeviceManager,
     config: &Config,
+    watcher: &DeviceWatcher,
 ) -> anyhow::Result<()> {
+    let client = device_mgr.client();
     let menu = Menu::new(config.clone());
+    let mut events = watcher.subscribe();
+    let target = serial.to_string();
+    let notifier = tokio::spawn(async move {
+        while let Ok(event) = events.recv().await {
+            if event.serial() == target {
+                ConsoleUi::warn(&event.describe());
+            }
+        }
+    });
+    let _notifier = AbortOnDrop(notifier);
     loop {
         menu.render_device_menu(display_name);
         let choice = ConsoleUi::read_line();
+        if watcher.is_lost(serial) {
+            ConsoleUi::warn(&format!("设备 {} 已断开或不可用，返回设备列表", display_name));
+            return Ok(());
+        }
         match choice.as_str() {
             "0" => {
-                let checker = RootChecker::new(client.clone());
+                let checker = RootChecker::from_config(device_mgr.scoped(), config)?;
                 let report = checker.check(serial).await?;
                 println!("{}", report);
             }
             "1" => {
-                let device_mgr = DeviceManager::new(client.clone());
-                let checker = BootloaderChecker::new(device_mgr);
+                let checker = BootloaderChecker::new(device_mgr.scoped());
                 let report = checker.check(serial).await?;
                 println!("{}", report);
             }
             "2" => {
-                let backup = BackupRunner::new(client.clone());
+                let backup = BackupRunner::new(device_mgr.scoped())
+                    .with_output_dir(config.backup_dir.clone())
+                    .with_verify(config.verify_transfers);
                 backup.show_menu(serial).await?;
             }
             "3" => {
-                ConsoleUi::info("开始 60 秒 ADB 稳定性测试...");
-                let stress = StressRunner::new(client.clone());
-                let (ok, fail) = stress.adb_stability_test(serial, 60).await?;
+                let secs = config.stress_duration_secs;
+                ConsoleUi::info(&format!("开始 {} 秒 ADB 稳定性测试...", secs));
+                let stress = StressRunner::new(client.clone()).with_watcher(watcher);
+                let (ok, fail) = stress.adb_stability_test(serial, secs).await?;
                 ConsoleUi::success(&format!("测试完成: 成功 {} 次, 失败 {} 次", ok, fail));
             }
             "4" => {
                 ConsoleUi::info("开始安全审计...");
-                let audit = AuditRunner::new(client.clone());
+                let dm = device_mgr.scoped();
+                let audit = AuditRunner::new(dm.clone())
+                    .with_root_checker(RootChecker::from_config(dm.clone(), config)?)
+                    .with_hook_checker(HookChecker::from_config(dm.clone(), config)?)
+                    .with_emulator_checker(EmulatorChecker::from_config(dm, config)?);
                 let report = audit.run(serial, None).await?;
-                println!("\n审计报告:");
-                println!("设备: {} {}", report.device_info.brand, report.device_info.marketing_name);
-                println!("Android: {} (SDK {})", report.device_info.android, report.device_info.sdk);
-                println!("Root 检测: su={}, 可疑包={}",
-                    if report.root.su_in_path.is_empty() { "否" } else { "是" },
-                    report.root.suspicious_packages.len());
-                println!("Bootloader: verifiedbootstate={}", report.boot.verifiedbootstate);
-                println!("SELinux: {}", report.security_env.selinux);
-                println!("安全补丁: {}", report.integrity.security_patch);
-            }
-            "5" | "6" | "7" | "8" | "9" => {
+                print!("{}", report.summary());
+            }
+            "5" => {
+                let mut runner = ForwardRunner::new(client.clone()).with_rule_sets(config.rule_sets.clone());
+                runner.show_menu(serial).await?;
+            }
+            "6" => {
+                let checker = HookChecker::from_config(device_mgr.scoped(), config)?;
+                let report = checker.check(serial).await?;
+                println!("{}", report);
+            }
+            "7" => {
+                let checker = EmulatorChecker::from_config(device_mgr.scoped(), config)?;
+                let report = checker.check(serial).await?;
+                println!("{}", report);
+            }
+            "8" | "9" => {
                 ConsoleUi::info("功能敬请期待...");
             }
             "q" | "Q" => {
@@ -151,4 +344,90 @@ async fn device_menu(
             }
         }
     }
-}
\ No newline at end of file
+}
+
+async fn limited_menu(device: &Device, device_mgr: &DeviceManager) -> anyhow::Result<()> {
+    let operations = device.state.limited_operations();
+    loop {
+        println!(
+            "\n{} {} {}",
+            "===".bright_cyan(),
+            format!("设备 {} ({}) 可用功能", device.display_name(), device.state).bright_white().bold(),
+            "===".bright_cyan()
+        );
+        for (idx, op) in operations.iter().enumerate() {
+            println!("  {}) {}", (idx + 1).to_string().bright_cyan(), op.label());
+        }
+        println!("  {}) 返回设备列表", "b".bright_cyan());
+        print!("\n选择编号后回车：");
+        std::io::Write::flush(&mut std::io::stdout())?;
+        let input = ConsoleUi::read_line();
+        if input.eq_ignore_ascii_case("b") {
+            return Ok(());
+        }
+        let op = match Menu::parse_index(&input) {
+            Some(n) if n <= operations.len() => operations[n - 1],
+            _ => {
+                ConsoleUi::warn("无效选择，请重试");
+                continue;
+            }
+        };
+        match op {
+            DeviceOperation::Properties => {
+                let props = device_mgr.get_properties(&device.serial).await?;
+                for key in props.keys_sorted() {
+                    println!("[{}]: [{}]", key, props.raw(key).unwrap_or_default());
+                }
+            }
+            DeviceOperation::Sideload => {
+                ConsoleUi::info("请输入 OTA 包路径:");
+                let package = ConsoleUi::read_line();
+                if package.is_empty() {
+                    continue;
+                }
+                ConsoleUi::info("正在刷入，请勿断开设备...");
+                let output = device_mgr.sideload(&device.serial, &package).await?;
+                println!("{}", output.trim_end());
+                ConsoleUi::success("刷入完成");
+            }
+            DeviceOperation::RebootSystem => {
+                device_mgr.reboot(&device.serial, None).await?;
+                ConsoleUi::success("已发送重启命令，返回设备列表");
+                return Ok(());
+            }
+            DeviceOperation::RebootRecovery => {
+                device_mgr.reboot(&device.serial, Some("recovery")).await?;
+                ConsoleUi::success("已发送重启命令，返回设备列表");
+                return Ok(());
+            }
+            DeviceOperation::RebootBootloader => {
+                device_mgr.reboot(&device.serial, Some("bootloader")).await?;
+                ConsoleUi::success("已发送重启命令，返回设备列表");
+                return Ok(());
+            }
+        }
+    }
+}
+
+async fn batch_menu(devices: &[Device], device_mgr: &DeviceManager, config: &Config) -> anyhow::Result<()> {
+    if devices.is_empty() {
+        ConsoleUi::warn("所选设备均未就绪，无法批量执行");
+        return Ok(());
+    }
+    let names: Vec<String> = devices.iter().map(|d| d.serial.clone()).collect();
+    println!("\n=== 批量执行 ({} 台: {}) ===", devices.len(), names.join(", "));
+    let ops = BatchOperation::all();
+    for (idx, op) in ops.iter().enumerate() {
+        println!("  {}) {}", idx + 1, op.label());
+    }
+    println!("  0) 返回");
+    print!("\n请选择: ");
+    std::io::Write::flush(&mut std::io::stdout())?;
+    let op = match Menu::parse_index(&ConsoleUi::read_line()) {
+        Some(n) if n <= ops.len() => ops[n - 1],
+        _ => return Ok(()),
+    };
+    let results = BatchRunner::new(device_mgr.clone(), config.clone()).run(devices, op).await;
+    print!("\n{}", BatchRunner::render(&results));
+    Ok(())
+}
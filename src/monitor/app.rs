@@ -1,4 +1,4 @@
-﻿use crate::adb::AdbClient;
+﻿use crate::adb::SharedTransport;
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -24,10 +24,10 @@ pub struct AppSignature {
 }
 #[derive(Clone)]
 pub struct AppManager {
-    client: AdbClient,
+    client: SharedTransport,
 }
 impl AppManager {
-    pub fn new(client: AdbClient) -> Self {
+    pub fn new(client: SharedTransport) -> Self {
         Self { client }
     }
     pub async fn list_apps(&self, serial: &str) -> Result<Vec<AppInfo>> {
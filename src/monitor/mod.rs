@@ -6,12 +6,12 @@ pub use process::ProcessMonitor;
 pub use resource::ResourceMonitor;
 pub use service::ServiceMonitor;
 pub use app::AppManager;
-use crate::adb::AdbClient;
+use crate::adb::SharedTransport;
 pub struct MonitorRunner {
-    client: AdbClient,
+    client: SharedTransport,
 }
 impl MonitorRunner {
-    pub fn new(client: AdbClient) -> Self {
+    pub fn new(client: SharedTransport) -> Self {
         Self { client }
     }
 }
\ No newline at end of file
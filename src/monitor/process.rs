@@ -1,4 +1,4 @@
-﻿use crate::adb::AdbClient;
+﻿use crate::adb::SharedTransport;
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -25,10 +25,10 @@ impl ProcessInfo {
 }
 #[derive(Clone)]
 pub struct ProcessMonitor {
-    client: AdbClient,
+    client: SharedTransport,
 }
 impl ProcessMonitor {
-    pub fn new(client: AdbClient) -> Self {
+    pub fn new(client: SharedTransport) -> Self {
         Self { client }
     }
     pub async fn list_processes(&self, serial: &str) -> Result<Vec<ProcessInfo>> {
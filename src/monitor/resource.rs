@@ -1,4 +1,4 @@
-﻿use crate::adb::AdbClient;
+﻿use crate::adb::SharedTransport;
 use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -60,10 +60,10 @@ pub struct SimpleDiskInfo {
 }
 #[derive(Clone)]
 pub struct ResourceMonitor {
-    client: AdbClient,
+    client: SharedTransport,
 }
 impl ResourceMonitor {
-    pub fn new(client: AdbClient) -> Self {
+    pub fn new(client: SharedTransport) -> Self {
         Self { client }
     }
     pub async fn get_cpu_info(&self, serial: &str) -> Result<CpuInfo> {
@@ -0,0 +1,239 @@
+﻿use crate::adb::DeviceManager;
+use crate::core::config::Config;
+use crate::core::{AdbError, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+pub const CURRENT: &str = "current";
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PropertySnapshot {
+    pub serial: String,
+    pub name: String,
+    pub taken_at: String,
+    pub properties: BTreeMap<String, String>,
+}
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PropertyChange {
+    pub before: String,
+    pub after: String,
+}
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SnapshotDiff {
+    pub from: String,
+    pub to: String,
+    pub added: BTreeMap<String, String>,
+    pub removed: BTreeMap<String, String>,
+    pub changed: BTreeMap<String, PropertyChange>,
+}
+impl SnapshotDiff {
+    pub fn between(from: &PropertySnapshot, to: &PropertySnapshot, prefixes: &[String]) -> Self {
+        let wanted = |key: &str| prefixes.is_empty() || prefixes.iter().any(|p| key.starts_with(p.trim_end_matches('*')));
+        let mut diff = Self {
+            from: from.name.clone(),
+            to: to.name.clone(),
+            ..Self::default()
+        };
+        for (key, before) in from.properties.iter().filter(|(k, _)| wanted(k)) {
+            match to.properties.get(key) {
+                None => {
+                    diff.removed.insert(key.clone(), before.clone());
+                }
+                Some(after) if after != before => {
+                    diff.changed.insert(
+                        key.clone(),
+                        PropertyChange {
+                            before: before.clone(),
+                            after: after.clone(),
+                        },
+                    );
+                }
+                Some(_) => {}
+            }
+        }
+        for (key, after) in to.properties.iter().filter(|(k, _)| wanted(k)) {
+            if !from.properties.contains_key(key) {
+                diff.added.insert(key.clone(), after.clone());
+            }
+        }
+        diff
+    }
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+    pub fn render(&self) -> String {
+        let mut out = format!("{} → {}\n", self.from, self.to);
+        if self.is_empty() {
+            out.push_str("  无变化\n");
+            return out;
+        }
+        for (key, value) in &self.added {
+            out.push_str(&format!("+ {} = {}\n", key, value));
+        }
+        for (key, value) in &self.removed {
+            out.push_str(&format!("- {} = {}\n", key, value));
+        }
+        for (key, change) in &self.changed {
+            out.push_str(&format!("~ {}: {} → {}\n", key, change.before, change.after));
+        }
+        out.push_str(&format!(
+            "\n新增 {} 项, 删除 {} 项, 变化 {} 项\n",
+            self.added.len(),
+            self.removed.len(),
+            self.changed.len()
+        ));
+        out
+    }
+}
+pub struct SnapshotStore {
+    dir: PathBuf,
+}
+impl SnapshotStore {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+    pub fn default_dir() -> Result<Self> {
+        let dir = Config::user_dir().ok_or_else(|| AdbError::Config("无法确定用户配置目录".to_string()))?;
+        Ok(Self::new(dir.join("snapshots")))
+    }
+    fn device_dir(&self, serial: &str) -> PathBuf {
+        self.dir.join(sanitize(serial))
+    }
+    // 名称直接作为文件名，会被替换字符的名称（如 "a b" 与 "a_b"）会指向同一个文件，直接拒绝
+    fn path(&self, serial: &str, name: &str) -> Result<PathBuf> {
+        if name.is_empty() || sanitize(name) != name {
+            return Err(AdbError::Config(format!(
+                "快照名称 \"{}\" 无效，只能包含字母、数字、-、_ 和 .",
+                name
+            )));
+        }
+        Ok(self.device_dir(serial).join(format!("{}.json", name)))
+    }
+    pub async fn take(&self, device_mgr: &DeviceManager, serial: &str, name: &str) -> Result<PropertySnapshot> {
+        let props = device_mgr.get_properties(serial).await?;
+        let properties = props
+            .keys_sorted()
+            .into_iter()
+            .map(|k| (k.to_string(), props.raw(k).unwrap_or_default().to_string()))
+            .collect();
+        Ok(PropertySnapshot {
+            serial: serial.to_string(),
+            name: name.to_string(),
+            taken_at: chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+            properties,
+        })
+    }
+    pub fn save(&self, snapshot: &PropertySnapshot) -> Result<PathBuf> {
+        if snapshot.name == CURRENT {
+            return Err(AdbError::Config(format!("快照名称 \"{}\" 为保留名称", CURRENT)));
+        }
+        let path = self.path(&snapshot.serial, &snapshot.name)?;
+        std::fs::create_dir_all(self.device_dir(&snapshot.serial))?;
+        std::fs::write(&path, serde_json::to_string_pretty(snapshot)?)?;
+        Ok(path)
+    }
+    pub fn load(&self, serial: &str, name: &str) -> Result<PropertySnapshot> {
+        let path = self.path(serial, name)?;
+        if !path.is_file() {
+            return Err(AdbError::Config(format!("设备 {} 没有名为 \"{}\" 的快照", serial, name)));
+        }
+        Self::read(&path)
+    }
+    pub async fn resolve(&self, device_mgr: &DeviceManager, serial: &str, name: &str) -> Result<PropertySnapshot> {
+        if name == CURRENT {
+            self.take(device_mgr, serial, name).await
+        } else {
+            self.load(serial, name)
+        }
+    }
+    pub fn list(&self, serial: &str) -> Result<Vec<PropertySnapshot>> {
+        let dir = self.device_dir(serial);
+        if !dir.is_dir() {
+            return Ok(Vec::new());
+        }
+        let mut snapshots = Vec::new();
+        for entry in std::fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension().is_some_and(|e| e == "json") {
+                if let Ok(snapshot) = Self::read(&path) {
+                    snapshots.push(snapshot);
+                }
+            }
+        }
+        snapshots.sort_by(|a, b| a.taken_at.cmp(&b.taken_at));
+        Ok(snapshots)
+    }
+    pub fn delete(&self, serial: &str, name: &str) -> Result<()> {
+        std::fs::remove_file(self.path(serial, name)?)?;
+        Ok(())
+    }
+    fn read(path: &Path) -> Result<PropertySnapshot> {
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+}
+fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') { c } else { '_' })
+        .collect()
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    fn snapshot(name: &str, pairs: &[(&str, &str)]) -> PropertySnapshot {
+        PropertySnapshot {
+            serial: "AAA".to_string(),
+            name: name.to_string(),
+            taken_at: "2026-10-18 10:00:00".to_string(),
+            properties: pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+        }
+    }
+    #[test]
+    fn diff_reports_added_removed_and_changed() {
+        let before = snapshot("before", &[
+            ("ro.boot.verifiedbootstate", "green"),
+            ("ro.build.id", "UQ1A"),
+            ("persist.sys.timezone", "UTC"),
+            ("ro.debuggable", "0"),
+        ]);
+        let after = snapshot("after", &[
+            ("ro.boot.verifiedbootstate", "orange"),
+            ("ro.build.id", "UQ1A"),
+            ("persist.sys.timezone", "UTC"),
+            ("ro.boot.magisk", "1"),
+        ]);
+        let diff = SnapshotDiff::between(&before, &after, &[]);
+        assert_eq!((diff.from.as_str(), diff.to.as_str()), ("before", "after"));
+        assert_eq!(diff.added.keys().collect::<Vec<_>>(), ["ro.boot.magisk"]);
+        assert_eq!(diff.removed.get("ro.debuggable").map(String::as_str), Some("0"));
+        let change = &diff.changed["ro.boot.verifiedbootstate"];
+        assert_eq!((change.before.as_str(), change.after.as_str()), ("green", "orange"));
+        assert_eq!(diff.changed.len(), 1);
+        assert!(diff.render().contains("新增 1 项, 删除 1 项, 变化 1 项"));
+        assert!(SnapshotDiff::between(&before, &before, &[]).is_empty());
+    }
+    #[test]
+    fn diff_is_limited_to_prefixes() {
+        let before = snapshot("before", &[("ro.boot.flash.locked", "1"), ("persist.sys.locale", "en-US"), ("ro.debuggable", "0")]);
+        let after = snapshot("after", &[("ro.boot.flash.locked", "0"), ("persist.sys.locale", "zh-CN"), ("ro.debuggable", "1")]);
+        let diff = SnapshotDiff::between(&before, &after, &["ro.boot.".to_string(), "persist.*".to_string()]);
+        assert_eq!(diff.changed.keys().collect::<Vec<_>>(), ["persist.sys.locale", "ro.boot.flash.locked"]);
+        let diff = SnapshotDiff::between(&before, &after, &["vendor.".to_string()]);
+        assert!(diff.is_empty());
+        assert!(diff.render().contains("无变化"));
+    }
+    #[test]
+    fn names_that_would_collide_on_disk_are_rejected() {
+        let dir = std::env::temp_dir().join(format!("androidchecker-snapshots-{}", std::process::id()));
+        let store = SnapshotStore::new(dir.clone());
+        store.save(&snapshot("a_b", &[("ro.build.id", "1")])).unwrap();
+        for name in ["a b", "a/b", "", CURRENT] {
+            assert!(store.save(&snapshot(name, &[("ro.build.id", "2")])).is_err(), "{:?}", name);
+        }
+        assert!(store.load("AAA", "a b").is_err());
+        assert!(store.delete("AAA", "a b").is_err());
+        assert_eq!(store.load("AAA", "a_b").unwrap().properties["ro.build.id"], "1");
+        assert_eq!(store.list("AAA").unwrap().len(), 1);
+        store.delete("AAA", "a_b").unwrap();
+        assert!(store.list("AAA").unwrap().is_empty());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
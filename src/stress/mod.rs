@@ -1,21 +1,65 @@
-﻿use crate::adb::AdbClient;
+﻿use crate::adb::{DeviceEvent, DeviceWatcher, SharedTransport};
 use crate::core::Result;
+use crate::ui::ConsoleUi;
+use std::sync::Mutex;
+use tokio::sync::broadcast;
 pub struct StressRunner {
-    client: AdbClient,
+    client: SharedTransport,
+    events: Option<Mutex<broadcast::Receiver<DeviceEvent>>>,
+    device_progress: bool,
 }
 impl StressRunner {
-    pub fn new(c: AdbClient) -> Self {
-        Self { client: c }
+    pub fn new(c: SharedTransport) -> Self {
+        Self {
+            client: c,
+            events: None,
+            device_progress: false,
+        }
+    }
+    pub fn with_watcher(mut self, watcher: &DeviceWatcher) -> Self {
+        self.events = Some(Mutex::new(watcher.subscribe()));
+        self
+    }
+    pub fn with_device_progress(mut self, enabled: bool) -> Self {
+        self.device_progress = enabled;
+        self
+    }
+    fn device_lost(&self, serial: &str) -> bool {
+        let Some(events) = &self.events else {
+            return false;
+        };
+        let Ok(mut rx) = events.lock() else {
+            return false;
+        };
+        let mut lost = false;
+        while let Ok(event) = rx.try_recv() {
+            lost |= event.is_loss_of(serial);
+        }
+        lost
     }
     pub async fn adb_stability_test(&self, serial: &str, secs: u64) -> Result<(u64, u64)> {
         let t0 = std::time::Instant::now();
         let mut success = 0u64;
         let mut failed = 0u64;
+        let mut reported = 0u64;
         while t0.elapsed().as_secs() < secs {
-            let r = self.client.try_shell(serial, &["getprop", "ro.product.model"]).await;
+            let elapsed = t0.elapsed().as_secs();
+            // 批量模式下每 5 秒按设备输出一行进度
+            if self.device_progress && elapsed >= reported + 5 {
+                reported = elapsed;
+                ConsoleUi::info(&format!(
+                    "[{}] 稳定性测试 {}/{}s: 成功 {} 次, 失败 {} 次",
+                    serial, elapsed, secs, success, failed
+                ));
+            }
+            if self.device_lost(serial) {
+                ConsoleUi::warn(&format!("测试期间设备 {} 已断开，提前结束", serial));
+                break;
+            }
+            let r = self.client.shell(serial, &["getprop", "ro.product.model"]).await;
             match r {
-                Ok((true, _, _)) => success += 1,
-                _ => failed += 1,
+                Ok(_) => success += 1,
+                Err(_) => failed += 1,
             }
         }
         Ok((success, failed))
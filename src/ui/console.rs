@@ -1,7 +1,23 @@
 ﻿use colored::*;
 use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+static QUIET: AtomicBool = AtomicBool::new(false);
+static PROGRESS_SUSPENDED: AtomicBool = AtomicBool::new(false);
 pub struct ConsoleUi;
 impl ConsoleUi {
+    pub fn set_quiet(quiet: bool) {
+        QUIET.store(quiet, Ordering::Relaxed);
+    }
+    pub fn suspend_progress(suspended: bool) {
+        PROGRESS_SUSPENDED.store(suspended, Ordering::Relaxed);
+    }
+    fn emit(line: String) {
+        if QUIET.load(Ordering::Relaxed) {
+            eprintln!("{}", line);
+        } else {
+            println!("{}", line);
+        }
+    }
     pub fn write_banner() {
         println!("AndroidChecker\n");
     }
@@ -13,16 +29,30 @@ impl ConsoleUi {
         buf.trim().to_string()
     }
     pub fn info(msg: &str) {
-        println!("{} {}", "[INFO]".bright_blue(), msg);
+        Self::emit(format!("{} {}", "[INFO]".bright_blue(), msg));
     }
     pub fn warn(msg: &str) {
-        println!("{} {}", "[WARN]".bright_yellow(), msg);
+        Self::emit(format!("{} {}", "[WARN]".bright_yellow(), msg));
     }
     pub fn error(msg: &str) {
         eprintln!("{} {}", "[ERROR]".bright_red().bold(), msg);
     }
     pub fn success(msg: &str) {
-        println!("{}", msg);
+        Self::emit(msg.to_string());
+    }
+    pub fn progress(msg: &str) {
+        if QUIET.load(Ordering::Relaxed) || PROGRESS_SUSPENDED.load(Ordering::Relaxed) {
+            return;
+        }
+        let line: String = msg.chars().take(100).collect();
+        print!("\r\x1b[2K{}", line);
+        let _ = io::stdout().flush();
+    }
+    pub fn progress_done() {
+        if !QUIET.load(Ordering::Relaxed) && !PROGRESS_SUSPENDED.load(Ordering::Relaxed) {
+            print!("\r\x1b[2K");
+            let _ = io::stdout().flush();
+        }
     }
     pub fn pause_exit() {
         println!("\n按 Enter 退出...");
@@ -34,14 +64,16 @@ impl ConsoleUi {
             Self::warn("没有找到设备");
             return;
         }
-        let sep = "-".repeat(80);
+        let sep = "-".repeat(108);
         println!("{}", sep.bright_black());
         println!(
-            "{:<4} {:<20} {:<30} {:<15}",
+            "{:<4} {:<20} {:<30} {:<15} {:<15} {:<12}",
             "#".bright_cyan(),
             "Serial".bright_cyan(),
             "Device".bright_cyan(),
-            "Android".bright_cyan()
+            "State".bright_cyan(),
+            "Android".bright_cyan(),
+            "Connection".bright_cyan()
         );
         println!("{}", sep.bright_black());
         for (idx, d) in devices.iter().enumerate() {
@@ -50,9 +82,17 @@ impl ConsoleUi {
             let name = d.display_name().bright_green();
             let ver = d.android_version.as_deref().unwrap_or("Unknown");
             let v = ver.bright_yellow();
-            println!("{:<4} {:<20} {:<30} {:<15}", n, s, name, v);
+            let state = d.state.as_str();
+            let st = if d.state.is_online() { state.bright_green() } else { state.bright_red() };
+            let c = d.connection().bright_black();
+            println!("{:<4} {:<20} {:<30} {:<15} {:<15} {:<12}", n, s, name, st, v, c);
         }
         println!("{}", sep.bright_black());
+        for (idx, d) in devices.iter().enumerate() {
+            if let Some(guidance) = d.state.guidance() {
+                println!("  {} {}", format!("[{}]", idx + 1).yellow(), guidance);
+            }
+        }
         println!();
     }
 }
\ No newline at end of file
@@ -29,6 +29,29 @@ impl Menu {
         }
         None
     }
+    pub fn parse_selection(input: &str, max: usize) -> Option<Vec<usize>> {
+        let s = input.trim();
+        if s.eq_ignore_ascii_case("a") || s.eq_ignore_ascii_case("all") {
+            return Some((1..=max).collect());
+        }
+        let mut picked = Vec::new();
+        for part in s.split([',', '，', ' ']).filter(|p| !p.is_empty()) {
+            let (lo, hi) = match part.split_once('-') {
+                Some((lo, hi)) => (Self::parse_index(lo)?, Self::parse_index(hi)?),
+                None => {
+                    let n = Self::parse_index(part)?;
+                    (n, n)
+                }
+            };
+            if lo > hi || hi > max {
+                return None;
+            }
+            picked.extend(lo..=hi);
+        }
+        picked.sort_unstable();
+        picked.dedup();
+        (!picked.is_empty()).then_some(picked)
+    }
     pub fn render_device_menu(&self, device_name: &str) {
         use colored::*;
         println!("\n{} {} {}",
@@ -42,9 +65,9 @@ impl Menu {
             ("2", "手机备份及恢复"),
             ("3", "压力测试"),
             ("4", "系统与硬件安全检查"),
-            ("5", "功能敬请期待..."),
-            ("6", "功能敬请期待..."),
-            ("7", "功能敬请期待..."),
+            ("5", "端口转发管理"),
+            ("6", "检测 Hook/注入框架"),
+            ("7", "检测模拟器/虚拟设备"),
             ("8", "功能敬请期待..."),
             ("9", "功能敬请期待..."),
         ];
@@ -52,7 +75,7 @@ impl Menu {
             let lbl = self.config.get_label(k, def_label);
             println!("  {}) {}", k.bright_cyan(), lbl);
         }
-        println!("  {}) {}", "q".bright_red(), "退出程序");
+        println!("  {}) 退出程序", "q".bright_red());
         print!("\n选择编号后回车：");
         std::io::Write::flush(&mut std::io::stdout()).unwrap();
     }